@@ -3,222 +3,928 @@ use enigo::{
     Direction::{Click, Press, Release},
     Enigo, Key, Keyboard, Mouse, Settings,
 };
-use serde_json::json;
+use serde_json::{json, Value};
+use std::sync::{mpsc, Mutex};
 use std::thread;
 use std::time::Duration;
-use tauri::{command, AppHandle, Emitter, Runtime};
+use tauri::{async_runtime, command, AppHandle, Emitter, Manager, Runtime, State};
+
+use super::approval::{request_approval, Approvals};
+use super::error::{CommandError, ErrorKind};
+use super::governor::admit;
+use super::image::Region;
+use super::journal;
+use super::privacy::check_privacy;
+use super::secrets::{self, Secrets};
+use super::windowing::{foreground_window, visible_windows, WindowInfo};
+use super::zones::{check_on_screen, check_zones};
+
+// The longest a hold_key may keep keys down
+const MAX_HOLD_MS: u64 = 10_000;
+// Furthest apart the points of a drag are checked against the allowlist, in action coordinates
+const PATH_SAMPLE_STEP: f64 = 10.0;
+
+/// The last point an action was aimed at, so screenshots can show where it landed
+#[derive(Default)]
+pub struct LastActionPoint(Mutex<Option<(f64, f64)>>);
+
+impl LastActionPoint {
+    pub fn get(&self) -> Option<(f64, f64)> {
+        *self.0.lock().unwrap()
+    }
 
-#[command]
-pub fn click<R: Runtime>(handle: AppHandle<R>, button: &str, x: f64, y: f64) -> Result<(), String> {
-    log::info!("agent: clicking at {}, {}", x, y);
-    let mut enigo = Enigo::new(&Settings::default())
-        .map_err(|e| format!("Failed to initialize Enigo: {}", e))?;
+    fn set(&self, x: f64, y: f64) {
+        *self.0.lock().unwrap() = Some((x, y));
+    }
+}
 
-    // Move to position
-    enigo
-        .move_mouse(x as i32, y as i32, Coordinate::Abs)
-        .map_err(|e| format!("Failed to move mouse: {}", e))?;
-
-    thread::sleep(Duration::from_millis(10));
-    // Determine which button to click
-    let button_type = match button.to_lowercase().as_str() {
-        "left" => Button::Left,
-        "right" => Button::Right,
-        "middle" => Button::Middle,
-        _ => return Err(format!("Unsupported mouse button: {}", button)),
-    };
-    // Perform click
-    enigo
-        .button(button_type, Click)
-        .map_err(|e| format!("Failed to click: {}", e))?;
-    handle
-        .emit("agent_click", json!({ "x": x, "y": y }))
-        .map_err(|e| format!("Failed to emit click event: {}", e))?;
+/// One mouse or keyboard action, in action coordinates.
+/// Every action command builds one of these and hands it to `perform`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    Click {
+        button: String,
+        x: f64,
+        y: f64,
+    },
+    DoubleClick {
+        x: f64,
+        y: f64,
+    },
+    TripleClick {
+        x: f64,
+        y: f64,
+    },
+    /// Presses a button and keeps it down until a `MouseUp`
+    MouseDown {
+        button: String,
+        x: f64,
+        y: f64,
+    },
+    MouseUp {
+        button: String,
+        x: f64,
+        y: f64,
+    },
+    Scroll {
+        x: f64,
+        y: f64,
+        scroll_x: f64,
+        scroll_y: f64,
+    },
+    Keypress {
+        keys: Vec<String>,
+    },
+    /// Holds keys down together for a while, then releases them
+    HoldKey {
+        keys: Vec<String>,
+        ms: u64,
+    },
+    TypeText {
+        text: String,
+    },
+    Wait {
+        ms: u64,
+    },
+    MoveMouse {
+        x: f64,
+        y: f64,
+    },
+    Drag {
+        path: Vec<(f64, f64)>,
+    },
+}
 
-    Ok(())
+/// The apps a task may act on, set when the task starts. Any app is allowed while unset or while
+/// another task is running.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct AppAllowlist {
+    pub task_id: String,
+    /// Application names, matched case-insensitively. "Desktop" allows clicks on the bare desktop.
+    pub apps: Vec<String>,
 }
 
-#[command]
-pub fn scroll<R: Runtime>(
-    handle: AppHandle<R>,
-    x: f64,
-    y: f64,
-    scroll_x: f64,
-    scroll_y: f64,
-) -> Result<(), String> {
-    log::info!("agent: scrolling at {}, {}", x, y);
-    let mut enigo = Enigo::new(&Settings::default())
-        .map_err(|e| format!("Failed to initialize Enigo: {}", e))?;
+/// Whether actions are only simulated, for every task or for one
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct DryRun {
+    pub global: bool,
+    pub task_id: Option<String>,
+}
 
-    let scroll_x = scroll_x as i32;
-    let scroll_y = scroll_y as i32;
-    // Move to position first
-    enigo
-        .move_mouse(x as i32, y as i32, Coordinate::Abs)
-        .map_err(|e| format!("Failed to move mouse: {}", e))?;
+impl AppAllowlist {
+    // Whether the allowlist was set for the current task
+    fn applies(&self, current_task: Option<&str>) -> bool {
+        current_task == Some(self.task_id.as_str())
+    }
+}
 
-    thread::sleep(Duration::from_millis(10));
+impl DryRun {
+    // Whether actions of the current task are simulated
+    fn active(&self, current_task: Option<&str>) -> bool {
+        self.global || (self.task_id.is_some() && self.task_id.as_deref() == current_task)
+    }
+}
+
+#[derive(Default)]
+pub struct ActivePolicy {
+    allowlist: Mutex<Option<AppAllowlist>>,
+    dry_run: Mutex<DryRun>,
+}
 
-    // Perform scroll
-    if scroll_x != 0 {
-        enigo
-            .scroll(scroll_x, Axis::Horizontal)
-            .map_err(|e| format!("Failed to scroll: {}", e))?;
+/// What happened to an action that passed its checks
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionResult {
+    Executed,
+    /// Dry run: the events were emitted but no input was sent
+    Simulated,
+}
+
+/// What an action lands on, which decides the checks it goes through
+pub enum Target<'a> {
+    /// The window under a point
+    Point(f64, f64),
+    /// Every window along a path, for drags
+    Path(&'a [(f64, f64)]),
+    /// The focused window, for keyboard input
+    Focus,
+    /// Only the pointer moves there, nothing is pressed
+    Pointer(f64, f64),
+    /// Nothing at all, like waiting
+    None,
+}
+
+impl Target<'_> {
+    // The points the pointer passes through
+    fn points(&self) -> Vec<(f64, f64)> {
+        match self {
+            Target::Point(x, y) | Target::Pointer(x, y) => vec![(*x, *y)],
+            Target::Path(path) => path.to_vec(),
+            Target::Focus | Target::None => Vec::new(),
+        }
     }
+}
 
-    if scroll_y != 0 {
-        enigo
-            .scroll(scroll_y, Axis::Vertical)
-            .map_err(|e| format!("Failed to scroll: {}", e))?;
+impl Action {
+    /// The command name, which is also the event name without the `agent_` prefix
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Click { .. } => "click",
+            Action::DoubleClick { .. } => "double_click",
+            Action::TripleClick { .. } => "triple_click",
+            Action::MouseDown { .. } => "mouse_down",
+            Action::MouseUp { .. } => "mouse_up",
+            Action::Scroll { .. } => "scroll",
+            Action::Keypress { .. } => "keypress",
+            Action::HoldKey { .. } => "hold_key",
+            Action::TypeText { .. } => "type_text",
+            Action::Wait { .. } => "wait",
+            Action::MoveMouse { .. } => "move_mouse",
+            Action::Drag { .. } => "drag",
+        }
     }
 
-    handle
-        .emit(
-            "agent_scroll",
-            json!({ "x": x, "y": y, "scroll_x": scroll_x, "scroll_y": scroll_y }),
-        )
-        .map_err(|e| format!("Failed to emit scroll event: {}", e))?;
+    pub fn target(&self) -> Target<'_> {
+        match self {
+            Action::Click { x, y, .. }
+            | Action::DoubleClick { x, y }
+            | Action::TripleClick { x, y }
+            | Action::MouseDown { x, y, .. }
+            | Action::MouseUp { x, y, .. }
+            | Action::Scroll { x, y, .. } => Target::Point(*x, *y),
+            Action::Keypress { .. } | Action::HoldKey { .. } | Action::TypeText { .. } => {
+                Target::Focus
+            }
+            Action::MoveMouse { x, y } => Target::Pointer(*x, *y),
+            Action::Drag { path } => Target::Path(path),
+            Action::Wait { .. } => Target::None,
+        }
+    }
 
-    Ok(())
+    /// Where the pointer ends up after the action
+    pub fn end_point(&self) -> Option<(f64, f64)> {
+        match self.target() {
+            Target::Point(x, y) | Target::Pointer(x, y) => Some((x, y)),
+            Target::Path(path) => path.last().copied(),
+            Target::Focus | Target::None => None,
+        }
+    }
+
+    // The payload of the event the overlay draws the action from
+    fn event_payload(&self) -> Value {
+        match self {
+            Action::Click { x, y, .. }
+            | Action::DoubleClick { x, y }
+            | Action::TripleClick { x, y }
+            | Action::MoveMouse { x, y } => json!({ "x": x, "y": y }),
+            Action::MouseDown { button, x, y } | Action::MouseUp { button, x, y } => {
+                json!({ "button": button, "x": x, "y": y })
+            }
+            Action::Scroll {
+                x,
+                y,
+                scroll_x,
+                scroll_y,
+            } => json!({
+                "x": x,
+                "y": y,
+                "scroll_x": *scroll_x as i32,
+                "scroll_y": *scroll_y as i32,
+            }),
+            Action::Keypress { keys } => json!({ "keys": keys }),
+            Action::HoldKey { keys, ms } => json!({ "keys": keys, "ms": ms }),
+            Action::TypeText { text } => json!({ "text": text }),
+            Action::Wait { ms } => json!({ "ms": ms }),
+            Action::Drag { path } => json!({ "path": path }),
+        }
+    }
+
+    fn log(&self) {
+        match self {
+            Action::Click { x, y, .. } => log::info!("agent: clicking at {}, {}", x, y),
+            Action::DoubleClick { x, y } => log::info!("agent: double clicking at {}, {}", x, y),
+            Action::TripleClick { x, y } => log::info!("agent: triple clicking at {}, {}", x, y),
+            Action::MouseDown { button, x, y } => {
+                log::info!("agent: pressing {} button at {}, {}", button, x, y)
+            }
+            Action::MouseUp { button, x, y } => {
+                log::info!("agent: releasing {} button at {}, {}", button, x, y)
+            }
+            Action::Scroll { x, y, .. } => log::info!("agent: scrolling at {}, {}", x, y),
+            Action::Keypress { keys } => log::info!("agent: keypressing {}", keys.join(" ")),
+            Action::HoldKey { keys, ms } => {
+                log::info!("agent: holding {} for {}ms", keys.join(" "), ms)
+            }
+            // secrets stay as their placeholders
+            Action::TypeText { text } => log::info!("agent: typing text {}", text),
+            Action::Wait { ms } => log::info!("agent: waiting for {}ms", ms),
+            Action::MoveMouse { x, y } => log::info!("agent: moving mouse to {}, {}", x, y),
+            Action::Drag { path } => log::info!("agent: dragging path {:?}", path),
+        }
+    }
+
+    /// Checks the arguments without touching any input
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Action::Click { button, x, y }
+            | Action::MouseDown { button, x, y }
+            | Action::MouseUp { button, x, y } => {
+                parse_button(button)?;
+                if !all_finite(&[(*x, *y)]) {
+                    return Err(format!("Invalid click position {}, {}", x, y));
+                }
+            }
+            Action::DoubleClick { x, y }
+            | Action::TripleClick { x, y }
+            | Action::MoveMouse { x, y } => {
+                if !all_finite(&[(*x, *y)]) {
+                    return Err(format!("Invalid position {}, {}", x, y));
+                }
+            }
+            Action::Scroll {
+                x,
+                y,
+                scroll_x,
+                scroll_y,
+            } => {
+                if !all_finite(&[(*x, *y), (*scroll_x, *scroll_y)]) {
+                    return Err(format!("Invalid scroll at {}, {}", x, y));
+                }
+            }
+            Action::Keypress { keys } | Action::HoldKey { keys, .. } => {
+                if keys.is_empty() {
+                    return Err("Keys cannot be empty".to_string());
+                }
+                for key in keys {
+                    parse_key(key)?;
+                }
+                if let Action::HoldKey { ms, .. } = self {
+                    if *ms > MAX_HOLD_MS {
+                        return Err(format!(
+                            "Keys can be held for at most {}ms, not {}ms",
+                            MAX_HOLD_MS, ms
+                        ));
+                    }
+                }
+            }
+            Action::TypeText { .. } | Action::Wait { .. } => {}
+            Action::Drag { path } => {
+                if path.is_empty() {
+                    return Err("Path cannot be empty".to_string());
+                }
+                if !all_finite(path) {
+                    return Err("Path contains an invalid point".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Sends the input. Runs on the main thread, except for waits and held keys, which `run` splits up.
+    fn execute(&self) -> Result<(), String> {
+        if let Action::Wait { ms } = self {
+            thread::sleep(Duration::from_millis(*ms));
+            return Ok(());
+        }
+
+        let mut enigo = Enigo::new(&Settings::default())
+            .map_err(|e| format!("Failed to initialize Enigo: {}", e))?;
+        match self {
+            Action::Click { button, x, y } => {
+                // Move to position
+                enigo
+                    .move_mouse(*x as i32, *y as i32, Coordinate::Abs)
+                    .map_err(|e| format!("Failed to move mouse: {}", e))?;
+
+                thread::sleep(Duration::from_millis(10));
+                // Determine which button to click
+                let button_type = parse_button(button)?;
+                // Perform click
+                enigo
+                    .button(button_type, Click)
+                    .map_err(|e| format!("Failed to click: {}", e))?;
+            }
+            Action::Scroll {
+                x,
+                y,
+                scroll_x,
+                scroll_y,
+            } => {
+                let scroll_x = *scroll_x as i32;
+                let scroll_y = *scroll_y as i32;
+                // Move to position first
+                enigo
+                    .move_mouse(*x as i32, *y as i32, Coordinate::Abs)
+                    .map_err(|e| format!("Failed to move mouse: {}", e))?;
+
+                thread::sleep(Duration::from_millis(10));
+
+                // Perform scroll
+                if scroll_x != 0 {
+                    enigo
+                        .scroll(scroll_x, Axis::Horizontal)
+                        .map_err(|e| format!("Failed to scroll: {}", e))?;
+                }
+
+                if scroll_y != 0 {
+                    enigo
+                        .scroll(scroll_y, Axis::Vertical)
+                        .map_err(|e| format!("Failed to scroll: {}", e))?;
+                }
+            }
+            Action::DoubleClick { x, y } => {
+                // Move to position
+                enigo
+                    .move_mouse(*x as i32, *y as i32, Coordinate::Abs)
+                    .map_err(|e| format!("Failed to move mouse: {}", e))?;
+
+                // Perform double click
+                enigo
+                    .button(Button::Left, Click)
+                    .map_err(|e| format!("Failed to click: {}", e))?;
+                thread::sleep(Duration::from_millis(10)); // Small delay between clicks
+                enigo
+                    .button(Button::Left, Click)
+                    .map_err(|e| format!("Failed to click: {}", e))?;
+            }
+            Action::TripleClick { x, y } => {
+                enigo
+                    .move_mouse(*x as i32, *y as i32, Coordinate::Abs)
+                    .map_err(|e| format!("Failed to move mouse: {}", e))?;
+                for i in 0..3 {
+                    if i > 0 {
+                        thread::sleep(Duration::from_millis(10));
+                    }
+                    enigo
+                        .button(Button::Left, Click)
+                        .map_err(|e| format!("Failed to click: {}", e))?;
+                }
+            }
+            Action::MouseDown { button, x, y } | Action::MouseUp { button, x, y } => {
+                enigo
+                    .move_mouse(*x as i32, *y as i32, Coordinate::Abs)
+                    .map_err(|e| format!("Failed to move mouse: {}", e))?;
+                let direction = if matches!(self, Action::MouseDown { .. }) {
+                    Press
+                } else {
+                    Release
+                };
+                enigo
+                    .button(parse_button(button)?, direction)
+                    .map_err(|e| format!("Failed to {}: {}", self.name(), e))?;
+            }
+            Action::Keypress { keys } => {
+                for key_str in keys {
+                    let key = parse_key(key_str)?;
+                    enigo
+                        .key(key, Press)
+                        .map_err(|e| format!("Failed to keypress: {}", e))?;
+                    thread::sleep(Duration::from_millis(10));
+                }
+
+                for key_str in keys {
+                    let key = parse_key(key_str)?;
+                    enigo
+                        .key(key, Release)
+                        .map_err(|e| format!("Failed to keypress: {}", e))?;
+                }
+            }
+            Action::TypeText { text } => {
+                enigo
+                    .text(text)
+                    .map_err(|e| format!("Failed to type text: {}", e))?;
+            }
+            Action::MoveMouse { x, y } => {
+                enigo
+                    .move_mouse(*x as i32, *y as i32, Coordinate::Abs)
+                    .map_err(|e| format!("Failed to move mouse: {}", e))?;
+            }
+            Action::Drag { path } => {
+                // Move to the starting position
+                let (start_x, start_y) = path[0];
+                enigo
+                    .move_mouse(start_x as i32, start_y as i32, Coordinate::Abs)
+                    .map_err(|e| format!("Failed to move mouse: {}", e))?;
+
+                // Press the mouse button
+                enigo
+                    .button(Button::Left, Press)
+                    .map_err(|e| format!("Failed to press mouse button: {}", e))?;
+
+                // Move through each point in the path
+                for (x, y) in path.iter().skip(1) {
+                    enigo
+                        .move_mouse(*x as i32, *y as i32, Coordinate::Abs)
+                        .map_err(|e| format!("Failed to move mouse: {}", e))?;
+                    thread::sleep(Duration::from_millis(5)); // Small delay for smoother dragging
+                }
+
+                // Release the mouse button
+                enigo
+                    .button(Button::Left, Release)
+                    .map_err(|e| format!("Failed to release mouse button: {}", e))?;
+            }
+            Action::Wait { .. } | Action::HoldKey { .. } => unreachable!(),
+        }
+        Ok(())
+    }
 }
 
-#[command]
-pub fn double_click<R: Runtime>(handle: AppHandle<R>, x: f64, y: f64) -> Result<(), String> {
-    log::info!("agent: double clicking at {}, {}", x, y);
-    let mut enigo = Enigo::new(&Settings::default())
-        .map_err(|e| format!("Failed to initialize Enigo: {}", e))?;
+// Finds the topmost window under a point, looking through Paimon's own overlay
+fn window_at(windows: &[WindowInfo], own_name: &str, x: f64, y: f64) -> WindowInfo {
+    let window = windows.iter().find(|w| {
+        !w.app_name.eq_ignore_ascii_case(own_name)
+            && x >= w.bounds.x
+            && y >= w.bounds.y
+            && x < w.bounds.x + w.bounds.width
+            && y < w.bounds.y + w.bounds.height
+    });
+    window.cloned().unwrap_or(WindowInfo {
+        app_name: "Desktop".to_string(),
+        title: String::new(),
+        bounds: Region {
+            x,
+            y,
+            width: 0.0,
+            height: 0.0,
+        },
+    })
+}
 
-    // Move to position
-    enigo
-        .move_mouse(x as i32, y as i32, Coordinate::Abs)
-        .map_err(|e| format!("Failed to move mouse: {}", e))?;
-
-    // Perform double click
-    enigo
-        .button(Button::Left, Click)
-        .map_err(|e| format!("Failed to click: {}", e))?;
-    thread::sleep(Duration::from_millis(10)); // Small delay between clicks
-    enigo
-        .button(Button::Left, Click)
-        .map_err(|e| format!("Failed to click: {}", e))?;
-    handle
-        .emit("agent_double_click", json!({ "x": x, "y": y }))
-        .map_err(|e| format!("Failed to emit double click event: {}", e))?;
+// The points along a path no more than PATH_SAMPLE_STEP apart, so a drag can't cross a window
+// that only lies between the points it was given
+fn sample_path(path: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let mut points = Vec::new();
+    for pair in path.windows(2) {
+        let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+        let steps = ((x1 - x0).hypot(y1 - y0) / PATH_SAMPLE_STEP)
+            .ceil()
+            .max(1.0) as u32;
+        for i in 0..steps {
+            let t = i as f64 / steps as f64;
+            points.push((x0 + (x1 - x0) * t, y0 + (y1 - y0) * t));
+        }
+    }
+    points.extend(path.last());
+    points
+}
+
+fn check_allowlist<R: Runtime>(handle: &AppHandle<R>, target: &Target) -> Result<(), CommandError> {
+    let policy = handle.state::<ActivePolicy>();
+    let Some(allowlist) = policy.allowlist.lock().unwrap().clone() else {
+        return Ok(());
+    };
+    // An allowlist left behind by another task doesn't restrict this one
+    let task_id = handle.state::<journal::Journal>().task_id();
+    if !allowlist.applies(task_id.as_deref()) {
+        return Ok(());
+    }
+    let windows = match target {
+        Target::Point(..) | Target::Path(..) => {
+            let own_name = &handle.package_info().name;
+            let visible = visible_windows()?;
+            let points = match target {
+                Target::Path(path) => sample_path(path),
+                _ => target.points(),
+            };
+            points
+                .into_iter()
+                .map(|(x, y)| window_at(&visible, own_name, x, y))
+                .collect()
+        }
+        Target::Focus => vec![foreground_window()?],
+        Target::Pointer(..) | Target::None => Vec::new(),
+    };
+
+    for window in windows {
+        if allowlist
+            .apps
+            .iter()
+            .any(|app| app.eq_ignore_ascii_case(&window.app_name))
+        {
+            continue;
+        }
+        log::warn!(
+            "allowlist: refusing action on {} ({:?}), task {} allows {:?}",
+            window.app_name,
+            window.title,
+            allowlist.task_id,
+            allowlist.apps
+        );
+        return Err(CommandError::new(
+            ErrorKind::AppNotAllowed {
+                app: window.app_name.clone(),
+                title: window.title.clone(),
+                allowed: allowlist.apps.clone(),
+            },
+            format!(
+                "{} is not one of the apps this task may use ({}). Switch to an allowed app first.",
+                window.app_name,
+                allowlist.apps.join(", ")
+            ),
+        ));
+    }
     Ok(())
 }
 
-#[command]
-pub fn keypress<R: Runtime>(handle: AppHandle<R>, keys: Vec<String>) -> Result<(), String> {
-    log::info!("agent: keypressing {}", keys.join(" "));
-    let mut enigo = Enigo::new(&Settings::default())
-        .map_err(|e| format!("Failed to initialize Enigo: {}", e))?;
+// Runs before every action, refusing it when a policy says no. Blocks while waiting for approval,
+// which a dry run skips since nothing would happen anyway.
+fn guard<R: Runtime>(
+    handle: &AppHandle<R>,
+    action: &Action,
+    dry_run: bool,
+) -> Result<(), CommandError> {
+    let target = action.target();
+    if matches!(target, Target::None) {
+        return Ok(());
+    }
+    check_privacy(handle)?;
+    for (x, y) in target.points() {
+        check_on_screen(handle, x, y)?;
+        // Hovering over a zone is harmless, pressing in it is not
+        if !matches!(target, Target::Pointer(..)) {
+            check_zones(handle, x, y)?;
+        }
+    }
+    check_allowlist(handle, &target)?;
+    if !dry_run {
+        request_approval(handle, action)?;
+    }
+    Ok(())
+}
+
+/// Checks an action against the policies, sends the input and emits the event the overlay draws.
+/// Input goes out on the main thread, the checks and any wait for approval happen off it.
+/// Every outcome, refusals included, is recorded in the journal.
+pub async fn perform<R: Runtime>(
+    handle: &AppHandle<R>,
+    action: Action,
+) -> Result<ActionResult, CommandError> {
+    action.log();
+    let started = journal::start();
+    let outcome = run(handle, &action).await;
+    journal::record(handle, &action, started, &outcome);
+    outcome
+}
 
-    for key_str in keys.clone() {
-        let key = parse_key(&key_str)?;
-        enigo
-            .key(key, Press)
-            .map_err(|e| format!("Failed to keypress: {}", e))?;
-        thread::sleep(Duration::from_millis(10));
+async fn run<R: Runtime>(
+    handle: &AppHandle<R>,
+    action: &Action,
+) -> Result<ActionResult, CommandError> {
+    let invalid = |message| CommandError::new(ErrorKind::Invalid, message);
+    action.validate().map_err(invalid)?;
+    if let Action::TypeText { text } = action {
+        handle.state::<Secrets>().check(text).map_err(invalid)?;
     }
 
-    for key_str in keys.clone() {
-        let key = parse_key(&key_str)?;
-        enigo
-            .key(key, Release)
-            .map_err(|e| format!("Failed to keypress: {}", e))?;
+    let task_id = handle.state::<journal::Journal>().task_id();
+    let dry_run = handle
+        .state::<ActivePolicy>()
+        .dry_run
+        .lock()
+        .unwrap()
+        .active(task_id.as_deref());
+    let checker = handle.clone();
+    let checked = action.clone();
+    async_runtime::spawn_blocking(move || guard(&checker, &checked, dry_run))
+        .await
+        .map_err(|e| format!("Failed to check {}: {}", action.name(), e))??;
+    // Counted once it has passed the checks, so refused actions don't use up the budgets
+    admit(handle, action)?;
+
+    if dry_run {
+        log::info!("dry run: simulated {}", action.name());
+    } else if let Action::Wait { .. } = action {
+        let waited = action.clone();
+        async_runtime::spawn_blocking(move || waited.execute())
+            .await
+            .map_err(|e| format!("Failed to wait: {}", e))??;
+    } else if let Action::HoldKey { keys, ms } = action {
+        // Only pressing and releasing happen on the main thread, it isn't blocked for the hold.
+        // Once the keys are down they are released whatever happens.
+        let pressed = keys.clone();
+        on_main_thread(handle, action.name(), move || press_keys(&pressed)).await?;
+        let ms = *ms;
+        let held =
+            async_runtime::spawn_blocking(move || thread::sleep(Duration::from_millis(ms))).await;
+        let released = keys.clone();
+        on_main_thread(handle, action.name(), move || release_keys(&released)).await?;
+        held.map_err(|e| format!("Failed to hold keys: {}", e))?;
+    } else {
+        match action {
+            // Only the input itself gets the secrets, everything else sees the placeholders
+            Action::TypeText { text } if secrets::has_placeholders(text) => {
+                let resolved = handle.state::<Secrets>().resolve(text)?;
+                // Focus may have moved since, so it is checked again where the keys go out
+                on_main_thread(handle, action.name(), move || {
+                    resolved.check_focus()?;
+                    Action::TypeText {
+                        text: resolved.text,
+                    }
+                    .execute()
+                    .map_err(CommandError::from)
+                })
+                .await?;
+            }
+            _ => {
+                let input = action.clone();
+                on_main_thread(handle, action.name(), move || input.execute()).await?;
+            }
+        }
     }
 
+    if let Some((x, y)) = action.end_point() {
+        handle.state::<LastActionPoint>().set(x, y);
+    }
+    handle.state::<Approvals>().record(action);
+    let mut payload = action.event_payload();
+    if dry_run {
+        payload["simulated"] = json!(true);
+    }
+    handle
+        .emit(&format!("agent_{}", action.name()), payload)
+        .map_err(|e| format!("Failed to emit {} event: {}", action.name(), e))?;
+    Ok(if dry_run {
+        ActionResult::Simulated
+    } else {
+        ActionResult::Executed
+    })
+}
 
+// Sends input from the main thread and waits for it. Refusals from the input keep their kind.
+async fn on_main_thread<R, F, E>(
+    handle: &AppHandle<R>,
+    name: &str,
+    input: F,
+) -> Result<(), CommandError>
+where
+    R: Runtime,
+    F: FnOnce() -> Result<(), E> + Send + 'static,
+    E: Into<CommandError> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
     handle
-        .emit("agent_keypress", json!({ "keys": keys }))
-        .map_err(|e| format!("Failed to emit keypress event: {}", e))?;
+        .run_on_main_thread(move || {
+            let _ = sender.send(input());
+        })
+        .map_err(|e| format!("Failed to {}: {}", name, e))?;
+    let sent = async_runtime::spawn_blocking(move || receiver.recv())
+        .await
+        .map_err(|e| format!("Failed to {}: {}", name, e))?
+        .map_err(|e| format!("Failed to {}: {}", name, e))?;
+    sent.map_err(|e| match e.into() {
+        CommandError {
+            kind: ErrorKind::Failed,
+            message,
+        } => format!("Failed to {}: {}", name, message).into(),
+        refused => refused,
+    })
+}
 
+// Presses the keys in order. When one fails, the ones already down are released again.
+fn press_keys(keys: &[String]) -> Result<(), String> {
+    let mut enigo = Enigo::new(&Settings::default())
+        .map_err(|e| format!("Failed to initialize Enigo: {}", e))?;
+    for (index, key) in keys.iter().enumerate() {
+        let pressed = parse_key(key).and_then(|key| {
+            enigo
+                .key(key, Press)
+                .map_err(|e| format!("Failed to press keys: {}", e))
+        });
+        if let Err(e) = pressed {
+            if let Err(e) = release_keys(&keys[..index]) {
+                log::warn!("agent: {}", e);
+            }
+            return Err(e);
+        }
+    }
     Ok(())
 }
 
-#[command]
-pub fn type_text<R: Runtime>(handle: AppHandle<R>, text: String) -> Result<(), String> {
-    log::info!("agent: typing text {}", text);
+// Releases the keys, last pressed first, carrying on past any that fail
+fn release_keys(keys: &[String]) -> Result<(), String> {
     let mut enigo = Enigo::new(&Settings::default())
         .map_err(|e| format!("Failed to initialize Enigo: {}", e))?;
+    let mut result = Ok(());
+    for key in keys.iter().rev() {
+        let released = parse_key(key).and_then(|key| {
+            enigo
+                .key(key, Release)
+                .map_err(|e| format!("Failed to release keys: {}", e))
+        });
+        if result.is_ok() {
+            result = released;
+        }
+    }
+    result
+}
 
-    enigo
-        .text(&text)
-        .map_err(|e| format!("Failed to type text: {}", e))?;
+#[command]
+pub async fn click<R: Runtime>(
+    handle: AppHandle<R>,
+    button: String,
+    x: f64,
+    y: f64,
+) -> Result<ActionResult, CommandError> {
+    perform(&handle, Action::Click { button, x, y }).await
+}
 
-    handle
-        .emit("agent_type_text", json!({ "text": text }))
-        .map_err(|e| format!("Failed to emit type text event: {}", e))?;
+#[command]
+pub async fn scroll<R: Runtime>(
+    handle: AppHandle<R>,
+    x: f64,
+    y: f64,
+    scroll_x: f64,
+    scroll_y: f64,
+) -> Result<ActionResult, CommandError> {
+    let action = Action::Scroll {
+        x,
+        y,
+        scroll_x,
+        scroll_y,
+    };
+    perform(&handle, action).await
+}
 
-    Ok(())
+#[command]
+pub async fn double_click<R: Runtime>(
+    handle: AppHandle<R>,
+    x: f64,
+    y: f64,
+) -> Result<ActionResult, CommandError> {
+    perform(&handle, Action::DoubleClick { x, y }).await
 }
 
 #[command]
-pub fn wait<R: Runtime>(handle: AppHandle<R>, ms: u64) -> Result<(), String> {
-    log::info!("agent: waiting for {}ms", ms);
-    thread::sleep(Duration::from_millis(ms));
-    handle
-        .emit("agent_wait", json!({ "ms": ms }))
-        .map_err(|e| format!("Failed to emit wait event: {}", e))?;
-    Ok(())
+pub async fn triple_click<R: Runtime>(
+    handle: AppHandle<R>,
+    x: f64,
+    y: f64,
+) -> Result<ActionResult, CommandError> {
+    perform(&handle, Action::TripleClick { x, y }).await
 }
 
 #[command]
-pub fn move_mouse<R: Runtime>(handle: AppHandle<R>, x: f64, y: f64) -> Result<(), String> {
-    log::info!("agent: moving mouse to {}, {}", x, y);
-    let mut enigo = Enigo::new(&Settings::default())
-        .map_err(|e| format!("Failed to initialize Enigo: {}", e))?;
+pub async fn mouse_down<R: Runtime>(
+    handle: AppHandle<R>,
+    button: String,
+    x: f64,
+    y: f64,
+) -> Result<ActionResult, CommandError> {
+    perform(&handle, Action::MouseDown { button, x, y }).await
+}
 
-    enigo
-        .move_mouse(x as i32, y as i32, Coordinate::Abs)
-        .map_err(|e| format!("Failed to move mouse: {}", e))?;
+#[command]
+pub async fn mouse_up<R: Runtime>(
+    handle: AppHandle<R>,
+    button: String,
+    x: f64,
+    y: f64,
+) -> Result<ActionResult, CommandError> {
+    perform(&handle, Action::MouseUp { button, x, y }).await
+}
 
-    handle
-        .emit("agent_move_mouse", json!({ "x": x, "y": y }))
-        .map_err(|e| format!("Failed to emit move mouse event: {}", e))?;
+#[command]
+pub async fn keypress<R: Runtime>(
+    handle: AppHandle<R>,
+    keys: Vec<String>,
+) -> Result<ActionResult, CommandError> {
+    perform(&handle, Action::Keypress { keys }).await
+}
 
-    Ok(())
+#[command]
+pub async fn hold_key<R: Runtime>(
+    handle: AppHandle<R>,
+    keys: Vec<String>,
+    ms: u64,
+) -> Result<ActionResult, CommandError> {
+    perform(&handle, Action::HoldKey { keys, ms }).await
 }
 
 #[command]
-pub fn drag<R: Runtime>(handle: AppHandle<R>, path: Vec<(f64, f64)>) -> Result<(), String> {
-    log::info!("agent: dragging path {:?}", path);
-    if path.is_empty() {
-        return Err("Path cannot be empty".to_string());
-    }
+pub async fn type_text<R: Runtime>(
+    handle: AppHandle<R>,
+    text: String,
+) -> Result<ActionResult, CommandError> {
+    perform(&handle, Action::TypeText { text }).await
+}
 
-    let mut enigo = Enigo::new(&Settings::default())
-        .map_err(|e| format!("Failed to initialize Enigo: {}", e))?;
+#[command]
+pub async fn wait<R: Runtime>(handle: AppHandle<R>, ms: u64) -> Result<ActionResult, CommandError> {
+    perform(&handle, Action::Wait { ms }).await
+}
+
+#[command]
+pub async fn move_mouse<R: Runtime>(
+    handle: AppHandle<R>,
+    x: f64,
+    y: f64,
+) -> Result<ActionResult, CommandError> {
+    perform(&handle, Action::MoveMouse { x, y }).await
+}
+
+#[command]
+pub async fn drag<R: Runtime>(
+    handle: AppHandle<R>,
+    path: Vec<(f64, f64)>,
+) -> Result<ActionResult, CommandError> {
+    perform(&handle, Action::Drag { path }).await
+}
 
-    // Move to the starting position
-    let (start_x, start_y) = path[0];
-    enigo
-        .move_mouse(start_x as i32, start_y as i32, Coordinate::Abs)
-        .map_err(|e| format!("Failed to move mouse: {}", e))?;
-
-    // Press the mouse button
-    enigo
-        .button(Button::Left, Press)
-        .map_err(|e| format!("Failed to press mouse button: {}", e))?;
-
-    // Move through each point in the path
-    for (x, y) in path.iter().skip(1) {
-        enigo
-            .move_mouse(*x as i32, *y as i32, Coordinate::Abs)
-            .map_err(|e| format!("Failed to move mouse: {}", e))?;
-        thread::sleep(Duration::from_millis(5)); // Small delay for smoother dragging
+/// Turns dry run on or off, for one task when a task id is given and for everything otherwise
+#[command]
+pub fn set_dry_run(policy: State<'_, ActivePolicy>, enabled: bool, task_id: Option<String>) {
+    let mut dry_run = policy.dry_run.lock().unwrap();
+    match task_id {
+        None => dry_run.global = enabled,
+        Some(task_id) if enabled => dry_run.task_id = Some(task_id),
+        Some(task_id) => {
+            if dry_run.task_id.as_ref() == Some(&task_id) {
+                dry_run.task_id = None;
+            }
+        }
     }
+    log::info!("dry run: {:?}", *dry_run);
+}
 
-    // Release the mouse button
-    enigo
-        .button(Button::Left, Release)
-        .map_err(|e| format!("Failed to release mouse button: {}", e))?;
+#[command]
+pub fn get_dry_run(policy: State<'_, ActivePolicy>) -> DryRun {
+    policy.dry_run.lock().unwrap().clone()
+}
 
-    handle
-        .emit("agent_drag", json!({ "path": path }))
-        .map_err(|e| format!("Failed to emit drag event: {}", e))?;
+/// Restricts the actions of a task to the given apps
+#[command]
+pub fn set_app_allowlist(policy: State<'_, ActivePolicy>, task_id: String, apps: Vec<String>) {
+    log::info!("allowlist: task {} may use {:?}", task_id, apps);
+    *policy.allowlist.lock().unwrap() = Some(AppAllowlist { task_id, apps });
+}
 
-    Ok(())
+#[command]
+pub fn get_app_allowlist(policy: State<'_, ActivePolicy>) -> Option<AppAllowlist> {
+    policy.allowlist.lock().unwrap().clone()
+}
+
+/// Lifts the allowlist, only if it still belongs to the given task when one is passed
+#[command]
+pub fn clear_app_allowlist(policy: State<'_, ActivePolicy>, task_id: Option<String>) {
+    let mut allowlist = policy.allowlist.lock().unwrap();
+    if task_id.is_none() || allowlist.as_ref().map(|a| &a.task_id) == task_id.as_ref() {
+        *allowlist = None;
+    }
+}
+
+fn all_finite(points: &[(f64, f64)]) -> bool {
+    points.iter().all(|(x, y)| x.is_finite() && y.is_finite())
+}
+
+fn parse_button(button: &str) -> Result<Button, String> {
+    match button.to_lowercase().as_str() {
+        "left" => Ok(Button::Left),
+        "right" => Ok(Button::Right),
+        "middle" => Ok(Button::Middle),
+        _ => Err(format!("Unsupported mouse button: {}", button)),
+    }
 }
 
 // Helper function to parse key strings into Key enum
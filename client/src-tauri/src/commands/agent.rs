@@ -0,0 +1,857 @@
+use serde_json::{json, Value};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use tauri::{async_runtime, command, AppHandle, Emitter, Manager, Runtime, State};
+
+use super::action::{self, Action};
+use super::approval::{confirm_safety_checks, Approvals};
+use super::cursor::pointer_location;
+use super::error::{CommandError, ErrorKind};
+use super::governor::Governor;
+use super::image::{action_scale, get_screenshot, Region};
+use super::journal::Journal;
+use super::marks::{click_mark, MarkSource};
+use super::provider::{
+    self, ComputerCall, FunctionSpec, Input, Item, ModelProvider, Providers, Setup,
+};
+use super::secrets::Secrets;
+use super::watch::{wait_for_change, wait_until_stable, Watchers};
+use super::windowing::set_hide_from_screenshot;
+
+const SYSTEM_PROMPT: &str = "You are a helpful assistant that can use a macos desktop to accomplish tasks. Your starting point is the macos desktop. Use the windows, files and applications available to you to accomplish the task. Do not ask clarifying questions unless you are stuck, it's better to try things out and get some results. Otherwise carry the task through on your own. Before finalizing actions like posting, submitting, purchasing, sending or deleting something, stop and ask the user to confirm, unless the task asked for exactly that. Some actions are held until the user approves them. If one is refused, don't look for another way to do it, tell the user what you wanted to do.";
+
+/// Where the runner is. A run goes Running -> Finished or Failed, with Paused and
+/// AwaitingApproval in between, and back to Idle when stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentState {
+    #[default]
+    Idle,
+    Running,
+    Paused,
+    AwaitingApproval,
+    Finished,
+    Failed,
+}
+
+impl AgentState {
+    fn active(self) -> bool {
+        matches!(
+            self,
+            AgentState::Running | AgentState::Paused | AgentState::AwaitingApproval
+        )
+    }
+}
+
+/// What a run reports as it goes, emitted as `agent_progress` along with its `run_id`
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AgentEvent {
+    Started {
+        task: String,
+    },
+    StateChanged {
+        state: AgentState,
+        // why the run paused, failed or stopped
+        reason: Option<String>,
+    },
+    /// Text from the model, the last one is the run's answer
+    Message {
+        text: String,
+    },
+    /// The model declined the task
+    Refusal {
+        text: String,
+    },
+    /// An input action the model asked for, about to be performed
+    Action {
+        call_id: String,
+        action: Action,
+    },
+    /// The model only asked to look, "screenshot", "wait" or "cursor_position"
+    Observe {
+        call_id: String,
+        kind: &'static str,
+    },
+    FunctionCall {
+        call_id: String,
+        name: String,
+        arguments: Value,
+    },
+    /// A policy refused the last action or function, the model is told and can try something else
+    Refused {
+        call_id: String,
+        error: CommandError,
+    },
+}
+
+#[derive(Clone, serde::Serialize)]
+struct Progress<'a> {
+    run_id: u64,
+    #[serde(flatten)]
+    event: &'a AgentEvent,
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct AgentOptions {
+    /// Annotate screenshots with numbered marks and offer the model a `click_mark` tool
+    pub marks: Option<MarkSource>,
+    /// Forget the previous tasks instead of continuing the conversation
+    pub new_conversation: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AgentStatus {
+    pub run_id: Option<u64>,
+    pub state: AgentState,
+}
+
+#[derive(Default)]
+struct Control {
+    state: AgentState,
+    pause_requested: bool,
+    pause_reason: Option<String>,
+    stop_requested: bool,
+}
+
+struct Run {
+    id: u64,
+    control: Mutex<Control>,
+    changed: Condvar,
+}
+
+/// Runs the computer-use loop for one task at a time: asks the model what to do, performs it
+/// through the same checks as every other action, and reports progress as events.
+#[derive(Default)]
+pub struct AgentRunner {
+    next_id: AtomicU64,
+    // the current run, or the last one once it has ended
+    run: Mutex<Option<Arc<Run>>>,
+    // the provider and the settings revision it was built for, kept so the next task continues
+    // the conversation
+    provider: Mutex<Option<(u64, Box<dyn ModelProvider>)>>,
+}
+
+// Why a run ended early
+enum Stop {
+    Stopped,
+    Failed(String),
+}
+
+impl From<String> for Stop {
+    fn from(message: String) -> Self {
+        Stop::Failed(message)
+    }
+}
+
+impl From<&str> for Stop {
+    fn from(message: &str) -> Self {
+        Stop::Failed(message.to_string())
+    }
+}
+
+fn emit<R: Runtime>(handle: &AppHandle<R>, run_id: u64, event: AgentEvent) {
+    let progress = Progress {
+        run_id,
+        event: &event,
+    };
+    if let Err(e) = handle.emit("agent_progress", progress) {
+        log::warn!("agent {}: failed to emit progress: {}", run_id, e);
+    }
+}
+
+impl Run {
+    fn state(&self) -> AgentState {
+        self.control.lock().unwrap().state
+    }
+
+    fn set_state<R: Runtime>(
+        &self,
+        handle: &AppHandle<R>,
+        state: AgentState,
+        reason: Option<String>,
+    ) {
+        self.control.lock().unwrap().state = state;
+        log::info!(
+            "agent {}: {:?} {}",
+            self.id,
+            state,
+            reason.as_deref().unwrap_or("")
+        );
+        emit(handle, self.id, AgentEvent::StateChanged { state, reason });
+    }
+
+    // Moves between two states, leaving the run alone when it is in any other
+    fn transition<R: Runtime>(&self, handle: &AppHandle<R>, from: AgentState, to: AgentState) {
+        let mut control = self.control.lock().unwrap();
+        if control.state != from {
+            return;
+        }
+        control.state = to;
+        drop(control);
+        emit(
+            handle,
+            self.id,
+            AgentEvent::StateChanged {
+                state: to,
+                reason: None,
+            },
+        );
+    }
+
+    fn request_pause(&self, reason: Option<String>) {
+        let mut control = self.control.lock().unwrap();
+        control.pause_requested = true;
+        control.pause_reason = reason;
+    }
+
+    fn resume(&self) {
+        self.control.lock().unwrap().pause_requested = false;
+        self.changed.notify_all();
+    }
+
+    fn stop(&self) {
+        let mut control = self.control.lock().unwrap();
+        control.stop_requested = true;
+        control.pause_requested = false;
+        drop(control);
+        self.changed.notify_all();
+    }
+
+    // Called between model calls and actions: ends the run if it was stopped, holds it while paused
+    async fn checkpoint<R: Runtime>(self: &Arc<Self>, handle: &AppHandle<R>) -> Result<(), Stop> {
+        let reason = {
+            let control = self.control.lock().unwrap();
+            if control.stop_requested {
+                return Err(Stop::Stopped);
+            }
+            if !control.pause_requested {
+                return Ok(());
+            }
+            control.pause_reason.clone()
+        };
+        self.set_state(handle, AgentState::Paused, reason);
+        let run = self.clone();
+        let resumed = async_runtime::spawn_blocking(move || {
+            let mut control = run.control.lock().unwrap();
+            while control.pause_requested && !control.stop_requested {
+                control = run.changed.wait(control).unwrap();
+            }
+            !control.stop_requested
+        })
+        .await
+        .map_err(|e| format!("Failed to wait while paused: {}", e))?;
+        if !resumed {
+            return Err(Stop::Stopped);
+        }
+        self.set_state(handle, AgentState::Running, None);
+        Ok(())
+    }
+}
+
+impl AgentRunner {
+    fn current(&self) -> Option<Arc<Run>> {
+        self.run.lock().unwrap().clone()
+    }
+
+    fn active(&self) -> Result<Arc<Run>, String> {
+        self.current()
+            .filter(|run| run.state().active())
+            .ok_or("The agent is not running".to_string())
+    }
+
+    // Starts a run, giving it a task of its own in the governor and the journal
+    fn begin<R: Runtime>(
+        &self,
+        handle: &AppHandle<R>,
+        task: &str,
+        options: &AgentOptions,
+    ) -> Result<Arc<Run>, String> {
+        if task.trim().is_empty() {
+            return Err("The task is empty".to_string());
+        }
+        let mut current = self.run.lock().unwrap();
+        if current.as_ref().is_some_and(|run| run.state().active()) {
+            return Err("The agent is already running".to_string());
+        }
+        let run = Arc::new(Run {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            control: Mutex::new(Control {
+                state: AgentState::Running,
+                ..Control::default()
+            }),
+            changed: Condvar::new(),
+        });
+        *current = Some(run.clone());
+        drop(current);
+        if options.new_conversation {
+            if let Some((_, provider)) = self.provider.lock().unwrap().as_mut() {
+                provider.reset();
+            }
+        }
+
+        let task_id = format!(
+            "agent-{}-{}",
+            run.id,
+            chrono::Utc::now().format("%Y%m%dT%H%M%S")
+        );
+        handle.state::<Journal>().start_task(task_id.clone());
+        handle.state::<Governor>().start_task(task_id.clone());
+        log::info!("agent {}: starting {:?} as {}", run.id, task, task_id);
+        emit(
+            handle,
+            run.id,
+            AgentEvent::Started {
+                task: task.to_string(),
+            },
+        );
+        emit(
+            handle,
+            run.id,
+            AgentEvent::StateChanged {
+                state: AgentState::Running,
+                reason: None,
+            },
+        );
+        Ok(run)
+    }
+}
+
+/// Marks the current run as waiting on the user while an action is held for approval
+pub fn set_awaiting_approval<R: Runtime>(handle: &AppHandle<R>, awaiting: bool) {
+    let Some(run) = handle.state::<AgentRunner>().current() else {
+        return;
+    };
+    if awaiting {
+        run.transition(handle, AgentState::Running, AgentState::AwaitingApproval);
+    } else {
+        run.transition(handle, AgentState::AwaitingApproval, AgentState::Running);
+    }
+}
+
+fn environment() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "mac"
+    } else if cfg!(target_os = "windows") {
+        "windows"
+    } else {
+        "ubuntu"
+    }
+}
+
+// What every provider is told: the prompt, the screen in action coordinates and the functions
+fn setup<R: Runtime>(handle: &AppHandle<R>, marks: bool) -> Result<Setup, String> {
+    let monitor = handle
+        .primary_monitor()
+        .map_err(|e| e.to_string())?
+        .ok_or("No monitor found")?;
+    let scale = action_scale(monitor.scale_factor());
+    let mut functions = vec![FunctionSpec {
+        name: "wait_for_change",
+        description: "Block until a region of the screen changes, for example until a dialog appears or a progress bar moves. Use this instead of repeatedly taking screenshots while waiting.",
+        parameters: json!({
+            "type": "object",
+            "properties": {
+                "timeout_ms": {
+                    "type": "number",
+                    "description": "How long to wait before giving up",
+                },
+                "region": {
+                    "type": ["object", "null"],
+                    "description": "The area to watch, the whole screen if null",
+                    "properties": {
+                        "x": { "type": "number" },
+                        "y": { "type": "number" },
+                        "width": { "type": "number" },
+                        "height": { "type": "number" },
+                    },
+                    "required": ["x", "y", "width", "height"],
+                    "additionalProperties": false,
+                },
+            },
+            "required": ["timeout_ms", "region"],
+            "additionalProperties": false,
+        }),
+    }];
+    if marks {
+        functions.push(FunctionSpec {
+            name: "click_mark",
+            description: "Click the element labelled with the given number on the latest screenshot. Prefer this over clicking coordinates when the target is marked.",
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "mark": { "type": "number", "description": "The number of the mark" },
+                },
+                "required": ["mark"],
+                "additionalProperties": false,
+            }),
+        });
+    }
+    let mut system_prompt = SYSTEM_PROMPT.to_string();
+    let secrets = handle.state::<Secrets>().scopes();
+    if !secrets.is_empty() {
+        let secrets: Vec<String> = secrets
+            .iter()
+            .map(|(name, scope)| format!("{} (only in {})", name, scope))
+            .collect();
+        system_prompt.push_str(&format!(
+            " To type a password or other secret, focus the field and type {{{{secret:<name>}}}}, it is filled in for you. You will never see the value. The stored secrets are: {}.",
+            secrets.join(", ")
+        ));
+    }
+    Ok(Setup {
+        system_prompt,
+        display_width: (monitor.size().width as f64 / scale).round() as u32,
+        display_height: (monitor.size().height as f64 / scale).round() as u32,
+        environment: environment(),
+        functions,
+    })
+}
+
+// Runs `work` with the app's windows left out of captures, on the main thread like the command
+async fn hidden<R: Runtime, F: Future>(handle: &AppHandle<R>, work: F) -> F::Output {
+    set_hidden(handle, true).await;
+    let output = work.await;
+    set_hidden(handle, false).await;
+    output
+}
+
+async fn set_hidden<R: Runtime>(handle: &AppHandle<R>, hide: bool) {
+    let (sender, receiver) = mpsc::channel();
+    let main = handle.clone();
+    let dispatched = handle.run_on_main_thread(move || {
+        set_hide_from_screenshot(main, hide);
+        let _ = sender.send(());
+    });
+    match dispatched {
+        Ok(()) => {
+            let _ = async_runtime::spawn_blocking(move || receiver.recv()).await;
+        }
+        Err(e) => log::warn!("agent: failed to hide from screenshots: {}", e),
+    }
+}
+
+// Runs a command for the model. A privacy block pauses the run and tries again once resumed,
+// policy refusals and invalid actions come back for the model to work around, anything else ends
+// the run.
+async fn attempt<R, T, F, Fut>(
+    handle: &AppHandle<R>,
+    run: &Arc<Run>,
+    mut command: F,
+) -> Result<Result<T, CommandError>, Stop>
+where
+    R: Runtime,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, CommandError>>,
+{
+    loop {
+        let error = match command().await {
+            Ok(value) => return Ok(Ok(value)),
+            Err(error) => error,
+        };
+        match error.kind {
+            ErrorKind::PrivacyBlocked { .. } => {
+                run.request_pause(Some(error.message));
+                run.checkpoint(handle).await?;
+            }
+            ErrorKind::Failed | ErrorKind::BudgetExhausted { .. } => {
+                return Err(Stop::Failed(error.message))
+            }
+            _ => return Ok(Err(error)),
+        }
+    }
+}
+
+struct Driver<'a, R: Runtime> {
+    handle: &'a AppHandle<R>,
+    run: &'a Arc<Run>,
+    marks: Option<MarkSource>,
+}
+
+impl<R: Runtime> Driver<'_, R> {
+    fn emit(&self, event: AgentEvent) {
+        emit(self.handle, self.run.id, event);
+    }
+
+    async fn screenshot(&self) -> Result<String, Stop> {
+        let result = attempt(self.handle, self.run, || {
+            hidden(
+                self.handle,
+                get_screenshot(
+                    self.handle.clone(),
+                    self.marks.clone(),
+                    Some(true),
+                    Some(true),
+                ),
+            )
+        })
+        .await?;
+        Ok(result.map_err(|e| Stop::Failed(e.message))?.base64)
+    }
+
+    async fn settle(&self, stable_ms: u64, timeout_ms: u64) -> Result<(), Stop> {
+        let result = attempt(self.handle, self.run, || {
+            hidden(
+                self.handle,
+                wait_until_stable(self.handle.clone(), Some(stable_ms), Some(timeout_ms), None),
+            )
+        })
+        .await?;
+        result.map_err(|e| Stop::Failed(e.message))?;
+        Ok(())
+    }
+
+    // The provider wants the user's go-ahead before the call runs. Without it the run ends, the
+    // checks can't be acknowledged and the provider won't continue otherwise.
+    async fn confirm(&self, action: Option<Action>, checks: &[Value]) -> Result<(), Stop> {
+        log::info!(
+            "agent {}: asking the user about {} safety checks",
+            self.run.id,
+            checks.len()
+        );
+        let handle = self.handle.clone();
+        let checks = checks.to_vec();
+        let answer = async_runtime::spawn_blocking(move || {
+            confirm_safety_checks(&handle, action.as_ref(), &checks)
+        })
+        .await
+        .map_err(|e| format!("Failed to ask about safety checks: {}", e))?;
+        if let Err(error) = answer {
+            // stopping the run denies what is pending, that is a stop rather than a failure
+            self.run.checkpoint(self.handle).await?;
+            return Err(Stop::Failed(error.message));
+        }
+        log::info!("agent {}: acknowledging the safety checks", self.run.id);
+        Ok(())
+    }
+
+    async fn computer_call(
+        &self,
+        call_id: String,
+        call: Result<ComputerCall, String>,
+        safety_checks: Vec<Value>,
+    ) -> Result<Input, Stop> {
+        self.handle
+            .state::<Journal>()
+            .set_step(Some(call_id.clone()));
+        // actions at the pointer take its position now, after the calls before them moved it
+        let call = call.and_then(|call| match call {
+            ComputerCall::AtPointer(pointer) => {
+                let (x, y) = pointer_location()?;
+                Ok(ComputerCall::Perform(pointer.at(x, y)))
+            }
+            call => Ok(call),
+        });
+        if !safety_checks.is_empty() {
+            let action = match &call {
+                Ok(ComputerCall::Perform(action)) => Some(action.clone()),
+                _ => None,
+            };
+            self.confirm(action, &safety_checks).await?;
+        }
+        let mut output = None;
+        let mut refusal = None;
+        match call {
+            Ok(ComputerCall::Screenshot) => self.emit(AgentEvent::Observe {
+                call_id: call_id.clone(),
+                kind: "screenshot",
+            }),
+            Ok(ComputerCall::Wait) => {
+                self.emit(AgentEvent::Observe {
+                    call_id: call_id.clone(),
+                    kind: "wait",
+                });
+                // the model asked for a wait, give slow page loads more time to settle
+                self.settle(1000, 10000).await?;
+            }
+            Ok(ComputerCall::CursorPosition) => {
+                self.emit(AgentEvent::Observe {
+                    call_id: call_id.clone(),
+                    kind: "cursor_position",
+                });
+                let (x, y) = pointer_location()?;
+                output = Some(format!("X={},Y={}", x.round(), y.round()));
+            }
+            Ok(ComputerCall::Perform(action)) => {
+                self.emit(AgentEvent::Action {
+                    call_id: call_id.clone(),
+                    action: action.clone(),
+                });
+                let name = action.name();
+                let result = attempt(self.handle, self.run, || {
+                    action::perform(self.handle, action.clone())
+                })
+                .await?;
+                if let Err(error) = result {
+                    self.emit(AgentEvent::Refused {
+                        call_id: call_id.clone(),
+                        error: error.clone(),
+                    });
+                    refusal = Some(format!("The {} action was refused: {}", name, error));
+                }
+            }
+            Ok(ComputerCall::AtPointer(_)) => unreachable!("resolved above"),
+            // a call the provider couldn't map, the model is told and can try another way
+            Err(message) => {
+                self.emit(AgentEvent::Refused {
+                    call_id: call_id.clone(),
+                    error: CommandError::from(message.clone()),
+                });
+                refusal = Some(format!("The action could not be performed: {}", message));
+            }
+        }
+
+        // let the UI settle before taking the screenshot
+        self.settle(300, 5000).await?;
+        let screenshot = self.screenshot().await?;
+        Ok(Input::ComputerResult {
+            call_id,
+            screenshot,
+            output,
+            refusal,
+            safety_checks,
+        })
+    }
+
+    async fn function_call(
+        &self,
+        call_id: String,
+        name: String,
+        arguments: Result<Value, String>,
+    ) -> Result<Input, Stop> {
+        // arguments that don't parse are refused below, there is no call to show
+        if let Ok(arguments) = &arguments {
+            self.emit(AgentEvent::FunctionCall {
+                call_id: call_id.clone(),
+                name: name.clone(),
+                arguments: arguments.clone(),
+            });
+        }
+        self.handle
+            .state::<Journal>()
+            .set_step(Some(call_id.clone()));
+        let invalid = |message| CommandError::new(ErrorKind::Invalid, message);
+        let result = match arguments.map(|arguments| (name.as_str(), arguments)) {
+            Err(e) => Err(invalid(e)),
+            Ok(("wait_for_change", arguments)) => {
+                match serde_json::from_value::<Option<Region>>(arguments["region"].clone()) {
+                    Ok(region) => {
+                        let timeout_ms = arguments["timeout_ms"].as_f64().map(|ms| ms as u64);
+                        attempt(self.handle, self.run, || async move {
+                            let watchers = self.handle.state::<Watchers>();
+                            let result = hidden(
+                                self.handle,
+                                wait_for_change(
+                                    self.handle.clone(),
+                                    watchers,
+                                    None,
+                                    region,
+                                    timeout_ms,
+                                ),
+                            )
+                            .await?;
+                            Ok::<_, CommandError>(json!(result))
+                        })
+                        .await?
+                    }
+                    Err(e) => Err(invalid(format!(
+                        "Invalid region for wait_for_change: {}",
+                        e
+                    ))),
+                }
+            }
+            Ok(("click_mark", arguments)) => match arguments["mark"].as_f64() {
+                Some(mark) => {
+                    attempt(self.handle, self.run, || async move {
+                        let mark = click_mark(self.handle.clone(), mark as u32, None).await?;
+                        Ok::<_, CommandError>(json!(mark))
+                    })
+                    .await?
+                }
+                None => Err(invalid("click_mark needs the number of a mark".to_string())),
+            },
+            Ok(_) => Err(invalid(format!("Unknown function {}", name))),
+        };
+        let output = match result {
+            Ok(value) => value.to_string(),
+            Err(error) => {
+                self.emit(AgentEvent::Refused {
+                    call_id: call_id.clone(),
+                    error: error.clone(),
+                });
+                json!({ "refused": error }).to_string()
+            }
+        };
+        Ok(Input::FunctionResult { call_id, output })
+    }
+
+    // Asks the model and does what it says until it stops asking for anything, returning its last message
+    async fn drive(&self, task: &str) -> Result<Option<String>, Stop> {
+        let runner = self.handle.state::<AgentRunner>();
+        let providers = self.handle.state::<Providers>();
+        let revision = providers.revision();
+        // reuse the provider, and with it the conversation, unless the settings or keys changed
+        let previous = runner.provider.lock().unwrap().take();
+        let mut model = match previous {
+            Some((built, model)) if built == revision => model,
+            _ => provider::build(&providers.config())?,
+        };
+        let outcome = self.converse(model.as_mut(), task).await;
+        if outcome.is_err() {
+            // the last turn was cut off and can't be continued
+            model.reset();
+        }
+        *runner.provider.lock().unwrap() = Some((revision, model));
+        outcome
+    }
+
+    async fn converse(
+        &self,
+        model: &mut dyn ModelProvider,
+        task: &str,
+    ) -> Result<Option<String>, Stop> {
+        let setup = setup(self.handle, self.marks.is_some())?;
+        let mut input = vec![Input::Task(task.to_string())];
+        let mut answer = None;
+        loop {
+            self.run.checkpoint(self.handle).await?;
+            let items = model.respond(&setup, input).await?;
+            let mut results = Vec::new();
+            for item in items {
+                match item {
+                    Item::Message(text) => {
+                        self.emit(AgentEvent::Message { text: text.clone() });
+                        answer = Some(text);
+                    }
+                    Item::Refusal(text) => {
+                        self.emit(AgentEvent::Refusal { text: text.clone() });
+                        return Ok(Some(text));
+                    }
+                    Item::Computer {
+                        call_id,
+                        call,
+                        safety_checks,
+                    } => {
+                        self.run.checkpoint(self.handle).await?;
+                        results.push(self.computer_call(call_id, call, safety_checks).await?);
+                    }
+                    Item::Function {
+                        call_id,
+                        name,
+                        arguments,
+                    } => {
+                        self.run.checkpoint(self.handle).await?;
+                        results.push(self.function_call(call_id, name, arguments).await?);
+                    }
+                }
+            }
+            // Nothing left to do, the model is waiting for the user
+            if results.is_empty() {
+                return Ok(answer);
+            }
+            input = results;
+        }
+    }
+}
+
+// Runs a started run to its end and settles its final state
+async fn complete<R: Runtime>(
+    handle: &AppHandle<R>,
+    run: Arc<Run>,
+    task: String,
+    options: AgentOptions,
+) -> Result<Option<String>, String> {
+    let driver = Driver {
+        handle,
+        run: &run,
+        marks: options.marks,
+    };
+    let outcome = driver.drive(&task).await;
+    handle.state::<Journal>().set_step(None);
+    match outcome {
+        Ok(answer) => {
+            run.set_state(handle, AgentState::Finished, None);
+            Ok(answer)
+        }
+        Err(Stop::Stopped) => {
+            run.set_state(handle, AgentState::Idle, Some("Stopped".to_string()));
+            Err("The agent was stopped".to_string())
+        }
+        Err(Stop::Failed(error)) => {
+            run.set_state(handle, AgentState::Failed, Some(error.clone()));
+            Err(error)
+        }
+    }
+}
+
+/// Runs a task to the end, returning the model's last message
+pub async fn run_task<R: Runtime>(
+    handle: &AppHandle<R>,
+    task: String,
+    options: AgentOptions,
+) -> Result<Option<String>, String> {
+    let run = handle
+        .state::<AgentRunner>()
+        .begin(handle, &task, &options)?;
+    complete(handle, run, task, options).await
+}
+
+/// Starts a task in the background and returns its run id, progress follows as `agent_progress` events
+#[command]
+pub fn start_agent<R: Runtime>(
+    handle: AppHandle<R>,
+    runner: State<'_, AgentRunner>,
+    task: String,
+    options: Option<AgentOptions>,
+) -> Result<u64, String> {
+    let options = options.unwrap_or_default();
+    let run = runner.begin(&handle, &task, &options)?;
+    let run_id = run.id;
+    async_runtime::spawn(async move {
+        let _ = complete(&handle, run, task, options).await;
+    });
+    Ok(run_id)
+}
+
+/// Pauses the run before its next model call or action
+#[command]
+pub fn pause_agent(runner: State<'_, AgentRunner>) -> Result<(), String> {
+    runner.active()?.request_pause(None);
+    Ok(())
+}
+
+#[command]
+pub fn resume_agent(runner: State<'_, AgentRunner>) -> Result<(), String> {
+    runner.active()?.resume();
+    Ok(())
+}
+
+/// Stops the run before its next model call or action, denying an action held for approval
+#[command]
+pub fn stop_agent(
+    runner: State<'_, AgentRunner>,
+    approvals: State<'_, Approvals>,
+) -> Result<(), String> {
+    let run = runner.active()?;
+    run.stop();
+    if run.state() == AgentState::AwaitingApproval {
+        approvals.deny_pending();
+    }
+    Ok(())
+}
+
+#[command]
+pub fn get_agent_state(runner: State<'_, AgentRunner>) -> AgentStatus {
+    match runner.current() {
+        Some(run) => AgentStatus {
+            run_id: Some(run.id),
+            state: run.state(),
+        },
+        None => AgentStatus {
+            run_id: None,
+            state: AgentState::Idle,
+        },
+    }
+}
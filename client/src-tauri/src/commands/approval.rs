@@ -0,0 +1,411 @@
+use image::{DynamicImage, Rgba};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
+use tauri::{command, AppHandle, Emitter, Manager, Runtime, State};
+
+use super::action::{Action, Target};
+use super::agent::set_awaiting_approval;
+use super::cursor::draw_crosshair;
+use super::error::{CommandError, ErrorKind};
+use super::image::{capture_screen, encode_webp};
+use super::marks::outline_rect;
+use super::windowing::foreground_window;
+use crate::config;
+
+const CONFIG_NAME: &str = "approval";
+const SPOTLIGHT_WINDOW: &str = "spotlight";
+const FOCUS_COLOR: Rgba<u8> = Rgba([255, 32, 32, 255]);
+
+/// When an action has to be approved by the user before it runs
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ApprovalRule {
+    /// Typing right after Enter was pressed, when a form may just have been submitted
+    TypeAfterEnter,
+    /// Any input while the app is focused, matched case-insensitively
+    AppFocused { app: String },
+    /// A key combination like ["cmd", "q"], in any order
+    Keys { keys: Vec<String> },
+    /// Every action of a kind, named like the commands, e.g. "drag"
+    Action { action: String },
+}
+
+impl fmt::Display for ApprovalRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApprovalRule::TypeAfterEnter => write!(f, "typing after pressing Enter"),
+            ApprovalRule::AppFocused { app } => write!(f, "input while {} is focused", app),
+            ApprovalRule::Keys { keys } => write!(f, "pressing {}", keys.join("+")),
+            ApprovalRule::Action { action } => write!(f, "every {}", action),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ApprovalConfig {
+    pub enabled: bool,
+    /// How long to wait for an answer before the action is denied
+    pub timeout_ms: u64,
+    pub rules: Vec<ApprovalRule>,
+}
+
+impl Default for ApprovalConfig {
+    fn default() -> Self {
+        ApprovalConfig {
+            enabled: true,
+            timeout_ms: 60000,
+            rules: vec![
+                ApprovalRule::Keys {
+                    keys: vec!["cmd".to_string(), "q".to_string()],
+                },
+                ApprovalRule::Keys {
+                    keys: vec!["alt".to_string(), "f4".to_string()],
+                },
+            ],
+        }
+    }
+}
+
+/// The approval rules, persisted to `~/.paimon/approval.json`, and the requests waiting for an answer
+pub struct Approvals {
+    config: Mutex<ApprovalConfig>,
+    next_id: AtomicU64,
+    pending: Mutex<HashMap<u64, mpsc::Sender<bool>>>,
+    // Enter was the last key pressed, so the next text may land in a different form
+    after_enter: AtomicBool,
+}
+
+impl Default for Approvals {
+    fn default() -> Self {
+        Approvals {
+            config: Mutex::new(config::load(CONFIG_NAME)),
+            next_id: AtomicU64::new(0),
+            pending: Mutex::new(HashMap::new()),
+            after_enter: AtomicBool::new(false),
+        }
+    }
+}
+
+fn is_enter(key: &str) -> bool {
+    key.eq_ignore_ascii_case("enter") || key.eq_ignore_ascii_case("return")
+}
+
+// Names keys the way parse_key understands them, so "cmd" matches "meta" and "command"
+fn normalize_keys(keys: &[String]) -> Vec<String> {
+    let mut keys: Vec<String> = keys
+        .iter()
+        .map(|key| match key.to_lowercase().as_str() {
+            "command" | "cmd" | "windows" | "win" => "meta".to_string(),
+            "ctrl" => "control".to_string(),
+            "esc" => "escape".to_string(),
+            "return" => "enter".to_string(),
+            other => other.to_string(),
+        })
+        .collect();
+    keys.sort();
+    keys
+}
+
+impl Approvals {
+    /// Remembers what was just done, for rules that depend on the previous action
+    pub fn record(&self, action: &Action) {
+        let after_enter = match action {
+            Action::Keypress { keys } | Action::HoldKey { keys, .. } => {
+                keys.iter().any(|k| is_enter(k))
+            }
+            Action::TypeText { text } => text.ends_with('\n'),
+            Action::Wait { .. } | Action::MoveMouse { .. } => return,
+            _ => false,
+        };
+        self.after_enter.store(after_enter, Ordering::Relaxed);
+    }
+
+    /// Denies every action waiting for an answer, like when the run they belong to is stopped
+    pub fn deny_pending(&self) {
+        for (_, sender) in self.pending.lock().unwrap().drain() {
+            let _ = sender.send(false);
+        }
+    }
+
+    fn matches(&self, rule: &ApprovalRule, action: &Action) -> Result<bool, String> {
+        Ok(match rule {
+            ApprovalRule::TypeAfterEnter => {
+                matches!(action, Action::TypeText { .. })
+                    && self.after_enter.load(Ordering::Relaxed)
+            }
+            ApprovalRule::AppFocused { app } => {
+                !matches!(action, Action::Wait { .. } | Action::MoveMouse { .. })
+                    && app.eq_ignore_ascii_case(&foreground_window()?.app_name)
+            }
+            ApprovalRule::Keys { keys } => match action {
+                Action::Keypress { keys: pressed } | Action::HoldKey { keys: pressed, .. } => {
+                    normalize_keys(pressed) == normalize_keys(keys)
+                }
+                _ => false,
+            },
+            ApprovalRule::Action { action: name } => name == action.name(),
+        })
+    }
+}
+
+// The current screen with the action drawn on it: crosshairs where the pointer goes,
+// or an outline around the focused window for keyboard input
+fn preview<R: Runtime>(handle: &AppHandle<R>, action: Option<&Action>) -> Result<String, String> {
+    let capture = capture_screen(handle).map_err(|e| e.message)?;
+    let mut image = capture.image.to_rgba8();
+    let size = capture.scale.round().max(1.0) as i64;
+    let to_pixels = |(x, y): (f64, f64)| ((x * capture.scale) as i64, (y * capture.scale) as i64);
+    match action.map_or(Target::None, Action::target) {
+        Target::Point(x, y) | Target::Pointer(x, y) => {
+            let (x, y) = to_pixels((x, y));
+            draw_crosshair(&mut image, x, y, size);
+        }
+        Target::Path(path) => {
+            for point in [path.first(), path.last()].into_iter().flatten() {
+                let (x, y) = to_pixels(*point);
+                draw_crosshair(&mut image, x, y, size);
+            }
+        }
+        Target::Focus => {
+            let bounds = foreground_window()?.bounds;
+            let (x, y) = to_pixels((bounds.x, bounds.y));
+            let (width, height) = to_pixels((bounds.width, bounds.height));
+            outline_rect(&mut image, x, y, width, height, 3 * size, FOCUS_COLOR);
+        }
+        Target::None => {}
+    }
+    encode_webp(&DynamicImage::ImageRgba8(image))
+}
+
+/// Holds an action that matches an approval rule until the user answers in the spotlight window.
+/// Emits `agent_approval_requested` with a preview and `agent_approval_resolved` once answered or timed out.
+pub fn request_approval<R: Runtime>(
+    handle: &AppHandle<R>,
+    action: &Action,
+) -> Result<(), CommandError> {
+    let approvals = handle.state::<Approvals>();
+    let config = approvals.config.lock().unwrap().clone();
+    if !config.enabled {
+        return Ok(());
+    }
+    let mut rule = None;
+    for candidate in &config.rules {
+        if approvals.matches(candidate, action)? {
+            rule = Some(candidate);
+            break;
+        }
+    }
+    let Some(rule) = rule else {
+        return Ok(());
+    };
+    hold(handle, Some(action), &rule.to_string(), config.timeout_ms)
+}
+
+/// Asks the user about the safety checks a provider attached to a call, like a warning that the
+/// screen may contain instructions for the model. Asks whatever the rules say, the checks may only
+/// be acknowledged once the user approved. `action` is what the call would do, if anything.
+pub fn confirm_safety_checks<R: Runtime>(
+    handle: &AppHandle<R>,
+    action: Option<&Action>,
+    checks: &[Value],
+) -> Result<(), CommandError> {
+    let timeout_ms = handle
+        .state::<Approvals>()
+        .config
+        .lock()
+        .unwrap()
+        .timeout_ms;
+    let concerns: Vec<&str> = checks
+        .iter()
+        .map(|check| {
+            check["message"]
+                .as_str()
+                .or(check["code"].as_str())
+                .unwrap_or("unspecified")
+        })
+        .collect();
+    let reason = format!("safety checks ({})", concerns.join("; "));
+    hold(handle, action, &reason, timeout_ms)
+}
+
+// Holds the caller until the user answers in the spotlight window or the timeout passes
+fn hold<R: Runtime>(
+    handle: &AppHandle<R>,
+    action: Option<&Action>,
+    reason: &str,
+    timeout_ms: u64,
+) -> Result<(), CommandError> {
+    let approvals = handle.state::<Approvals>();
+    let name = action.map_or("call", Action::name);
+    let id = approvals.next_id.fetch_add(1, Ordering::Relaxed);
+    let (sender, receiver) = mpsc::channel();
+    approvals.pending.lock().unwrap().insert(id, sender);
+    log::info!("approval: holding {} {} for {}", name, id, reason);
+    let requested = preview(handle, action).and_then(|screenshot| {
+        handle
+            .emit_to(
+                SPOTLIGHT_WINDOW,
+                "agent_approval_requested",
+                json!({
+                    "id": id,
+                    "action": action,
+                    "rule": reason,
+                    "screenshot": screenshot,
+                    "timeout_ms": timeout_ms,
+                }),
+            )
+            .map_err(|e| format!("Failed to emit approval request: {}", e))
+    });
+    let answer = match requested {
+        Ok(()) => {
+            set_awaiting_approval(handle, true);
+            let answer = receiver
+                .recv_timeout(Duration::from_millis(timeout_ms))
+                .ok();
+            set_awaiting_approval(handle, false);
+            answer
+        }
+        Err(e) => {
+            approvals.pending.lock().unwrap().remove(&id);
+            return Err(e.into());
+        }
+    };
+    approvals.pending.lock().unwrap().remove(&id);
+
+    let timed_out = answer.is_none();
+    let approved = answer.unwrap_or(false);
+    log::info!(
+        "approval: {} {} was {}",
+        name,
+        id,
+        match (approved, timed_out) {
+            (true, _) => "approved",
+            (false, true) => "not answered in time",
+            (false, false) => "denied",
+        }
+    );
+    handle
+        .emit_to(
+            SPOTLIGHT_WINDOW,
+            "agent_approval_resolved",
+            json!({ "id": id, "approved": approved, "timed_out": timed_out }),
+        )
+        .map_err(|e| format!("Failed to emit approval result: {}", e))?;
+    if approved {
+        return Ok(());
+    }
+    Err(CommandError::new(
+        ErrorKind::ApprovalDenied {
+            rule: reason.to_string(),
+            timed_out,
+        },
+        if timed_out {
+            format!("Nobody approved the {} ({}) in time", name, reason)
+        } else {
+            format!("The user denied the {} ({})", name, reason)
+        },
+    ))
+}
+
+/// Answers a pending approval request
+#[command]
+pub fn respond_to_approval(
+    approvals: State<'_, Approvals>,
+    id: u64,
+    approved: bool,
+) -> Result<(), String> {
+    let sender = approvals
+        .pending
+        .lock()
+        .unwrap()
+        .remove(&id)
+        .ok_or(format!("Approval {} is no longer pending", id))?;
+    sender
+        .send(approved)
+        .map_err(|_| format!("Approval {} is no longer pending", id))
+}
+
+#[command]
+pub fn get_approval_config(approvals: State<'_, Approvals>) -> ApprovalConfig {
+    approvals.config.lock().unwrap().clone()
+}
+
+#[command]
+pub fn set_approval_config(
+    approvals: State<'_, Approvals>,
+    config: ApprovalConfig,
+) -> Result<(), String> {
+    config::save(CONFIG_NAME, &config)?;
+    *approvals.config.lock().unwrap() = config;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approvals() -> Approvals {
+        Approvals {
+            config: Mutex::new(ApprovalConfig::default()),
+            next_id: AtomicU64::new(0),
+            pending: Mutex::new(HashMap::new()),
+            after_enter: AtomicBool::new(false),
+        }
+    }
+
+    fn keys(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    fn needs_approval(approvals: &Approvals, action: &Action) -> bool {
+        let rules = approvals.config.lock().unwrap().rules.clone();
+        rules
+            .iter()
+            .any(|rule| approvals.matches(rule, action).unwrap())
+    }
+
+    #[test]
+    fn holding_a_default_key_combination_needs_approval() {
+        let approvals = approvals();
+        for pressed in [
+            keys(&["cmd", "q"]),
+            keys(&["meta", "q"]),
+            keys(&["alt", "F4"]),
+        ] {
+            let action = Action::HoldKey {
+                keys: pressed.clone(),
+                ms: 500,
+            };
+            assert!(needs_approval(&approvals, &action), "{:?}", pressed);
+            let action = Action::Keypress { keys: pressed };
+            assert!(needs_approval(&approvals, &action));
+        }
+        let action = Action::HoldKey {
+            keys: keys(&["shift"]),
+            ms: 500,
+        };
+        assert!(!needs_approval(&approvals, &action));
+    }
+
+    #[test]
+    fn holding_enter_counts_as_pressing_it() {
+        let approvals = approvals();
+        let rule = ApprovalRule::TypeAfterEnter;
+        let typing = Action::TypeText {
+            text: "hello".to_string(),
+        };
+        assert!(!approvals.matches(&rule, &typing).unwrap());
+        approvals.record(&Action::HoldKey {
+            keys: keys(&["enter"]),
+            ms: 100,
+        });
+        assert!(approvals.matches(&rule, &typing).unwrap());
+        approvals.record(&typing);
+        assert!(!approvals.matches(&rule, &typing).unwrap());
+    }
+}
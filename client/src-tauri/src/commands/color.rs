@@ -0,0 +1,135 @@
+use image::{GenericImageView, Rgba};
+use tauri::{async_runtime, command, AppHandle, Runtime};
+
+use super::error::CommandError;
+use super::image::{capture_screen, Region};
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    fn from_pixel(pixel: Rgba<u8>) -> Self {
+        Rgb {
+            r: pixel.0[0],
+            g: pixel.0[1],
+            b: pixel.0[2],
+        }
+    }
+
+    // Largest difference across the three channels
+    fn distance(&self, other: &Rgb) -> u8 {
+        self.r
+            .abs_diff(other.r)
+            .max(self.g.abs_diff(other.g))
+            .max(self.b.abs_diff(other.b))
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct PixelColor {
+    r: u8,
+    g: u8,
+    b: u8,
+    hex: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct ColorSearchResult {
+    found: bool,
+    // number of matching pixels and their share of the searched area
+    count: u64,
+    fraction: f64,
+    // bounding box of every matching pixel, in action coordinates
+    bounds: Option<Region>,
+}
+
+/// Reads the colour of the screen at a point given in action coordinates
+#[command]
+pub async fn get_pixel_color<R: Runtime>(
+    handle: AppHandle<R>,
+    x: f64,
+    y: f64,
+) -> Result<PixelColor, CommandError> {
+    log::info!("getting pixel color at {}, {}", x, y);
+    let color = async_runtime::spawn_blocking(move || {
+        let capture = capture_screen(&handle)?;
+        let px = (x * capture.scale).floor();
+        let py = (y * capture.scale).floor();
+        if px < 0.0
+            || py < 0.0
+            || px >= capture.image.width() as f64
+            || py >= capture.image.height() as f64
+        {
+            return Err(format!("Point {}, {} is outside of the screen", x, y).into());
+        }
+        Ok::<_, CommandError>(Rgb::from_pixel(
+            capture.image.get_pixel(px as u32, py as u32),
+        ))
+    })
+    .await
+    .map_err(|e| format!("Failed to read pixel color: {}", e))??;
+
+    Ok(PixelColor {
+        r: color.r,
+        g: color.g,
+        b: color.b,
+        hex: format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b),
+    })
+}
+
+/// Looks for pixels within `tolerance` (per channel, 0-255) of a colour, on the whole screen or in a region
+#[command]
+pub async fn find_color<R: Runtime>(
+    handle: AppHandle<R>,
+    rgb: Rgb,
+    tolerance: Option<u8>,
+    region: Option<Region>,
+) -> Result<ColorSearchResult, CommandError> {
+    let tolerance = tolerance.unwrap_or(0);
+    log::info!(
+        "finding color {:?} (tolerance {}) in {:?}",
+        rgb,
+        tolerance,
+        region
+    );
+
+    async_runtime::spawn_blocking(move || {
+        let capture = capture_screen(&handle)?;
+        let (image, offset_x, offset_y) = match &region {
+            Some(region) => (capture.crop(region), region.x.max(0.0), region.y.max(0.0)),
+            None => (capture.image, 0.0, 0.0),
+        };
+
+        let mut count = 0u64;
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (u32::MAX, u32::MAX, 0, 0);
+        for (x, y, pixel) in image.pixels() {
+            if Rgb::from_pixel(pixel).distance(&rgb) <= tolerance {
+                count += 1;
+                min_x = min_x.min(x);
+                min_y = min_y.min(y);
+                max_x = max_x.max(x);
+                max_y = max_y.max(y);
+            }
+        }
+
+        let area = (image.width() as u64 * image.height() as u64).max(1);
+        let bounds = (count > 0).then(|| Region {
+            x: offset_x + min_x as f64 / capture.scale,
+            y: offset_y + min_y as f64 / capture.scale,
+            width: (max_x - min_x + 1) as f64 / capture.scale,
+            height: (max_y - min_y + 1) as f64 / capture.scale,
+        });
+        Ok::<_, CommandError>(ColorSearchResult {
+            found: count > 0,
+            count,
+            fraction: count as f64 / area as f64,
+            bounds,
+        })
+    })
+    .await
+    .map_err(|e| format!("Failed to search for color: {}", e))?
+}
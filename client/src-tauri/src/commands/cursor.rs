@@ -0,0 +1,93 @@
+use enigo::{Enigo, Mouse, Settings};
+use image::{Rgba, RgbaImage};
+
+use super::marks::fill_rect;
+
+const OUTLINE: Rgba<u8> = Rgba([0, 0, 0, 255]);
+const FILL: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const CROSSHAIR_COLOR: Rgba<u8> = Rgba([255, 32, 32, 255]);
+// Length of each crosshair arm and the gap left around the point, in points
+const CROSSHAIR_ARM: f64 = 12.0;
+const CROSSHAIR_GAP: f64 = 3.0;
+
+// The classic arrow pointer at one pixel per point, tip at the top left.
+// 'X' is the outline, '.' the fill and spaces are transparent.
+const ARROW: [&str; 19] = [
+    "X",
+    "XX",
+    "X.X",
+    "X..X",
+    "X...X",
+    "X....X",
+    "X.....X",
+    "X......X",
+    "X.......X",
+    "X........X",
+    "X.........X",
+    "X......XXXXX",
+    "X...X..X",
+    "X..XX..X",
+    "X.X  X..X",
+    "XX   X..X",
+    "X     X..X",
+    "      X..X",
+    "       XX",
+];
+
+/// Where the pointer currently is, in action coordinates
+pub fn pointer_location() -> Result<(f64, f64), String> {
+    let enigo = Enigo::new(&Settings::default())
+        .map_err(|e| format!("Failed to initialize Enigo: {}", e))?;
+    let (x, y) = enigo
+        .location()
+        .map_err(|e| format!("Failed to get mouse location: {}", e))?;
+    Ok((x as f64, y as f64))
+}
+
+/// Draws an arrow pointer with its tip at (x, y) in screenshot pixels.
+/// `size` is the number of screenshot pixels per point so the glyph matches the real cursor on hiDPI screens.
+pub fn draw_cursor(image: &mut RgbaImage, x: i64, y: i64, size: i64) {
+    for (row, line) in ARROW.iter().enumerate() {
+        for (col, c) in line.chars().enumerate() {
+            let color = match c {
+                'X' => OUTLINE,
+                '.' => FILL,
+                _ => continue,
+            };
+            fill_rect(
+                image,
+                x + col as i64 * size,
+                y + row as i64 * size,
+                size,
+                size,
+                color,
+            );
+        }
+    }
+}
+
+/// Draws a crosshair centred on (x, y) in screenshot pixels, marking where the last action landed
+pub fn draw_crosshair(image: &mut RgbaImage, x: i64, y: i64, size: i64) {
+    let arm = (CROSSHAIR_ARM * size as f64) as i64;
+    let gap = (CROSSHAIR_GAP * size as f64) as i64;
+    let thickness = size.max(1);
+    let half = thickness / 2;
+    fill_rect(
+        image,
+        x - gap - arm,
+        y - half,
+        arm,
+        thickness,
+        CROSSHAIR_COLOR,
+    );
+    fill_rect(image, x + gap, y - half, arm, thickness, CROSSHAIR_COLOR);
+    fill_rect(
+        image,
+        x - half,
+        y - gap - arm,
+        thickness,
+        arm,
+        CROSSHAIR_COLOR,
+    );
+    fill_rect(image, x - half, y + gap, thickness, arm, CROSSHAIR_COLOR);
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+
+/// Why a command was refused or failed, tagged by `kind` so the frontend can react to policy denials
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// A blocked application is focused or visible, see `privacy.rs`
+    PrivacyBlocked {
+        app: String,
+        title: String,
+        // "foreground" or "visible"
+        reason: String,
+    },
+    /// The action targets an app outside the task's allowlist
+    AppNotAllowed {
+        app: String,
+        title: String,
+        allowed: Vec<String>,
+    },
+    /// The point is not on any monitor
+    OutOfBounds { x: f64, y: f64 },
+    /// The point is inside a forbidden zone, see `zones.rs`
+    ForbiddenZone { zone: String, x: f64, y: f64 },
+    /// The user denied the action, or did not answer in time, see `approval.rs`
+    ApprovalDenied { rule: String, timed_out: bool },
+    /// Too many actions of a kind in the last minute, see `governor.rs`
+    RateLimited {
+        action: String,
+        per_minute: u32,
+        retry_after_ms: u64,
+    },
+    /// A secret would be typed into a window it isn't meant for, see `secrets.rs`
+    SecretNotAllowed {
+        secret: String,
+        app: String,
+        title: String,
+        allowed: String,
+    },
+    /// The task used up one of its budgets, "actions" or "typed characters"
+    BudgetExhausted { budget: String, limit: u64 },
+    /// The action or its arguments make no sense, like an unknown key or secret
+    Invalid,
+    /// Anything else, only the message is meaningful
+    Failed,
+}
+
+/// The error returned by commands that are subject to policies
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CommandError {
+    #[serde(flatten)]
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl CommandError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        CommandError {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        CommandError::new(ErrorKind::Failed, message)
+    }
+}
+
+impl From<&str> for CommandError {
+    fn from(message: &str) -> Self {
+        CommandError::new(ErrorKind::Failed, message)
+    }
+}
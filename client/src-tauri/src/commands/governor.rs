@@ -0,0 +1,210 @@
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{command, AppHandle, Emitter, Manager, Runtime, State};
+
+use super::action::Action;
+use super::error::{CommandError, ErrorKind};
+use crate::config;
+
+const CONFIG_NAME: &str = "governor";
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct GovernorConfig {
+    /// Most actions of a kind per minute, keyed by command name like "click"
+    pub rates: HashMap<String, u32>,
+    /// Most actions per task, waits aside
+    pub max_actions: Option<u64>,
+    /// Most characters typed per task
+    pub max_typed_chars: Option<u64>,
+}
+
+impl Default for GovernorConfig {
+    fn default() -> Self {
+        GovernorConfig {
+            rates: [
+                ("click", 60),
+                ("double_click", 60),
+                ("triple_click", 60),
+                ("mouse_down", 60),
+                ("mouse_up", 60),
+                ("scroll", 120),
+                ("keypress", 120),
+                ("hold_key", 30),
+                ("type_text", 60),
+                ("move_mouse", 120),
+                ("drag", 30),
+            ]
+            .into_iter()
+            .map(|(name, rate)| (name.to_string(), rate))
+            .collect(),
+            max_actions: Some(500),
+            max_typed_chars: Some(10000),
+        }
+    }
+}
+
+/// What the current task has used so far
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct BudgetUsage {
+    pub task_id: Option<String>,
+    pub actions: u64,
+    pub typed_chars: u64,
+}
+
+/// The limits, persisted to `~/.paimon/governor.json`, and what has been used against them
+pub struct Governor {
+    config: Mutex<GovernorConfig>,
+    usage: Mutex<BudgetUsage>,
+    // when recent actions of each kind ran, oldest first
+    recent: Mutex<HashMap<&'static str, VecDeque<Instant>>>,
+}
+
+impl Default for Governor {
+    fn default() -> Self {
+        Governor {
+            config: Mutex::new(config::load(CONFIG_NAME)),
+            usage: Mutex::new(BudgetUsage::default()),
+            recent: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+fn exhausted<R: Runtime>(
+    handle: &AppHandle<R>,
+    usage: &BudgetUsage,
+    budget: &str,
+    limit: u64,
+) -> CommandError {
+    log::warn!(
+        "governor: task {:?} used up its {} budget of {}",
+        usage.task_id,
+        budget,
+        limit
+    );
+    let _ = handle.emit(
+        "agent_budget_exhausted",
+        json!({ "task_id": usage.task_id, "budget": budget, "limit": limit }),
+    );
+    CommandError::new(
+        ErrorKind::BudgetExhausted {
+            budget: budget.to_string(),
+            limit,
+        },
+        format!("The task used up its budget of {} {}", limit, budget),
+    )
+}
+
+/// Lets an action through if it is within the rate limits and the task's budgets, and counts it.
+/// Emits `agent_rate_limited` or `agent_budget_exhausted` when it refuses.
+pub fn admit<R: Runtime>(handle: &AppHandle<R>, action: &Action) -> Result<(), CommandError> {
+    if let Action::Wait { .. } = action {
+        return Ok(());
+    }
+    let governor = handle.state::<Governor>();
+    let config = governor.config.lock().unwrap().clone();
+    let mut usage = governor.usage.lock().unwrap();
+
+    if let Some(limit) = config.max_actions {
+        if usage.actions >= limit {
+            return Err(exhausted(handle, &usage, "actions", limit));
+        }
+    }
+    let typed = match action {
+        Action::TypeText { text } => text.chars().count() as u64,
+        _ => 0,
+    };
+    if let Some(limit) = config.max_typed_chars {
+        if usage.typed_chars + typed > limit {
+            return Err(exhausted(handle, &usage, "typed characters", limit));
+        }
+    }
+
+    let name = action.name();
+    let mut recent = governor.recent.lock().unwrap();
+    let times = recent.entry(name).or_default();
+    let now = Instant::now();
+    while times
+        .front()
+        .is_some_and(|t| now.duration_since(*t) >= RATE_WINDOW)
+    {
+        times.pop_front();
+    }
+    if let Some(&rate) = config.rates.get(name) {
+        if times.len() >= rate as usize {
+            let retry_after_ms = times.front().map_or(0, |t| {
+                (RATE_WINDOW - now.duration_since(*t)).as_millis() as u64
+            });
+            log::warn!("governor: refusing {}, more than {} per minute", name, rate);
+            let _ = handle.emit(
+                "agent_rate_limited",
+                json!({ "action": name, "per_minute": rate, "retry_after_ms": retry_after_ms }),
+            );
+            return Err(CommandError::new(
+                ErrorKind::RateLimited {
+                    action: name.to_string(),
+                    per_minute: rate,
+                    retry_after_ms,
+                },
+                format!(
+                    "Too many {} actions, at most {} per minute. Try again in {}ms.",
+                    name, rate, retry_after_ms
+                ),
+            ));
+        }
+    }
+
+    times.push_back(now);
+    usage.actions += 1;
+    usage.typed_chars += typed;
+    Ok(())
+}
+
+impl Governor {
+    pub fn start_task(&self, task_id: String) {
+        log::info!("governor: starting budgets for task {}", task_id);
+        *self.usage.lock().unwrap() = BudgetUsage {
+            task_id: Some(task_id),
+            ..Default::default()
+        };
+    }
+
+    pub fn usage(&self) -> BudgetUsage {
+        self.usage.lock().unwrap().clone()
+    }
+
+    /// Goes back to counting for a task that started earlier, keeping what it had used
+    pub fn resume_task(&self, usage: BudgetUsage) {
+        log::info!("governor: resuming budgets for task {:?}", usage.task_id);
+        *self.usage.lock().unwrap() = usage;
+    }
+}
+
+/// Starts counting the budgets afresh for a new task
+#[command]
+pub fn start_task_budget(governor: State<'_, Governor>, task_id: String) {
+    governor.start_task(task_id);
+}
+
+#[command]
+pub fn get_budget_usage(governor: State<'_, Governor>) -> BudgetUsage {
+    governor.usage()
+}
+
+#[command]
+pub fn get_governor_config(governor: State<'_, Governor>) -> GovernorConfig {
+    governor.config.lock().unwrap().clone()
+}
+
+#[command]
+pub fn set_governor_config(
+    governor: State<'_, Governor>,
+    config: GovernorConfig,
+) -> Result<(), String> {
+    config::save(CONFIG_NAME, &config)?;
+    *governor.config.lock().unwrap() = config;
+    Ok(())
+}
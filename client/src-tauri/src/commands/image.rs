@@ -1,27 +1,210 @@
 use base64::{engine::general_purpose, Engine as _};
-use image::{imageops::FilterType, ImageFormat, ImageReader};
+use image::{imageops::FilterType, DynamicImage, ImageFormat, ImageReader};
 use std::fs;
 use std::io::Cursor;
 use std::path::{Path, PathBuf};
 use tauri::{async_runtime, command, AppHandle, Manager, Runtime};
 use tauri_plugin_screenshots::{get_monitor_screenshot, get_screenshotable_monitors};
+use xcap::Monitor;
+
+use super::action::LastActionPoint;
+use super::cursor::{draw_crosshair, draw_cursor, pointer_location};
+use super::error::CommandError;
+use super::journal::save_screenshot;
+use super::marks::{draw_marks, find_marks, Mark, MarkSource, Marks};
+use super::privacy::check_privacy;
+use super::redact::{redact, Redaction};
+
+/// A rectangle in the same coordinate space the action commands take
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct Region {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// An in-memory capture of the primary monitor
+pub struct Capture {
+    pub image: DynamicImage,
+    // screenshot pixels per action coordinate unit
+    pub scale: f64,
+}
+
+impl Capture {
+    /// Crops the capture to a region given in action coordinates, clamped to the screen
+    pub fn crop(&self, region: &Region) -> DynamicImage {
+        let x = ((region.x * self.scale).max(0.0) as u32).min(self.image.width());
+        let y = ((region.y * self.scale).max(0.0) as u32).min(self.image.height());
+        let width = ((region.width * self.scale).max(0.0) as u32).min(self.image.width() - x);
+        let height = ((region.height * self.scale).max(0.0) as u32).min(self.image.height() - y);
+        self.image.crop_imm(x, y, width, height)
+    }
+}
+
+// Captures straight into memory, skipping the file round trip of the screenshots plugin.
+// Everything but get_screenshot goes through `capture_screen`.
+fn capture_primary_monitor() -> Result<Capture, String> {
+    let monitors = Monitor::all().map_err(|e| e.to_string())?;
+    let monitor = monitors
+        .into_iter()
+        .find(|m| m.is_primary())
+        .ok_or("No primary monitor found")?;
+    let image = monitor.capture_image().map_err(|e| e.to_string())?;
+    Ok(Capture {
+        image: DynamicImage::ImageRgba8(image),
+        scale: action_scale(monitor.scale_factor() as f64),
+    })
+}
+
+/// Captures the primary monitor for anything that reads the screen, meant for polling.
+/// Refuses with `privacy_blocked` while a blocked app is on screen, like get_screenshot.
+pub fn capture_screen<R: Runtime>(handle: &AppHandle<R>) -> Result<Capture, CommandError> {
+    check_privacy(handle)?;
+    Ok(capture_primary_monitor()?)
+}
+
+// Screenshot pixels per action coordinate unit for a monitor with the given scale factor.
+// enigo works in points on macOS and in physical pixels everywhere else.
+pub(crate) fn action_scale(monitor_scale_factor: f64) -> f64 {
+    if cfg!(target_os = "macos") {
+        monitor_scale_factor
+    } else {
+        1.0
+    }
+}
+
+/// Encodes an image as a base64 webp data URL, the way screenshots are handed to the webview
+pub fn encode_webp(image: &DynamicImage) -> Result<String, String> {
+    let mut buffer = Cursor::new(Vec::new());
+    image
+        .write_to(&mut buffer, ImageFormat::WebP)
+        .map_err(|e| e.to_string())?;
+    let base64_image = general_purpose::STANDARD.encode(buffer.into_inner());
+    Ok(format!("data:image/webp;base64,{}", base64_image))
+}
+
+/// Directory where named captures used as reference images are kept
+fn captures_dir() -> Result<PathBuf, String> {
+    let dir = crate::dot_paimon_dir().join("captures");
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create captures directory: {}", e))?;
+    Ok(dir)
+}
+
+/// The file of a named capture. Names are kept to letters, digits, - and _ so they stay inside
+/// the captures directory.
+pub fn capture_path(name: &str) -> Result<PathBuf, String> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!("Invalid capture name: {}", name));
+    }
+    Ok(captures_dir()?.join(format!("{}.png", name)))
+}
 
 #[derive(serde::Serialize)]
 pub struct ScreenshotResult {
     path: String,
-    base64: String,
+    pub base64: String,
+    // the numbered marks drawn on the screenshot, when annotation was requested
+    marks: Option<Vec<Mark>>,
+    // everything hidden from the screenshot before it was encoded
+    redactions: Vec<Redaction>,
+    // the copy kept for the journal, which the next actions refer to
+    journal_id: String,
 }
 
 #[command]
-pub async fn get_screenshot<R: Runtime>(handle: AppHandle<R>) -> Result<ScreenshotResult, String> {
+pub async fn get_screenshot<R: Runtime>(
+    handle: AppHandle<R>,
+    marks: Option<MarkSource>,
+    cursor: Option<bool>,
+    crosshair: Option<bool>,
+) -> Result<ScreenshotResult, CommandError> {
     log::info!("getting screenshot");
-    let monitors = get_screenshotable_monitors().await?;
-    let screenshot_path = get_monitor_screenshot(handle.clone(), monitors[0].id).await?;
+    check_privacy(&handle)?;
+    let monitors = get_screenshotable_monitors()
+        .await
+        .map_err(|e| e.to_string())?;
+    let screenshot_path = get_monitor_screenshot(handle.clone(), monitors[0].id)
+        .await
+        .map_err(|e| e.to_string())?;
     let image = ImageReader::open(&screenshot_path).map_err(|e| e.to_string())?;
-    let mut image = image.decode().map_err(|e| e.to_string())?;
+    let image = image.decode();
+    // The plugin's file is the unredacted screen, it mustn't outlive the decoding
+    if let Err(e) = fs::remove_file(&screenshot_path) {
+        log::warn!(
+            "failed to remove raw screenshot {:?}: {}",
+            screenshot_path,
+            e
+        );
+    }
+    let image = image.map_err(|e| e.to_string())?;
     // image size limit for openai api
     // image = image.resize(2000, 768, FilterType::CatmullRom);
 
+    // The scale of the monitor that was captured, which need not be the primary one
+    let scale_factor = Monitor::all()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .find(|m| m.id() == monitors[0].id)
+        .map_or(1.0, |m| m.scale_factor() as f64);
+    let mut capture = Capture {
+        image,
+        scale: action_scale(scale_factor),
+    };
+
+    // Redaction and text marks read the screen with OCR, so these stages run on the blocking pool
+    let processor = handle.clone();
+    let (mut capture, redactions, journal_id, marks) = async_runtime::spawn_blocking(move || {
+        // Redaction stage: hide anything sensitive before the screenshot leaves the machine
+        let redactions = redact(&processor, &mut capture)?;
+        if !redactions.is_empty() {
+            log::info!("redacted {} areas from screenshot", redactions.len());
+        }
+        // Journal stage: keep what the screen looked like before the next actions, without annotations
+        let journal_id = save_screenshot(&processor, &capture.image)?;
+
+        // Annotation stage: number things on screen so the model can refer to them
+        let marks = match marks {
+            Some(source) => {
+                let found = find_marks(&processor, &capture, &source)?;
+                let mut annotated = capture.image.to_rgba8();
+                draw_marks(&mut annotated, &found, capture.scale);
+                capture.image = DynamicImage::ImageRgba8(annotated);
+                log::info!("annotated screenshot with {} marks", found.len());
+                processor.state::<Marks>().set(found.clone());
+                Some(found)
+            }
+            None => None,
+        };
+        Ok::<_, String>((capture, redactions, journal_id, marks))
+    })
+    .await
+    .map_err(|e| format!("Failed to process screenshot: {}", e))??;
+
+    // Capture APIs leave out the pointer, draw it back in along with where the last action landed
+    if cursor.unwrap_or(false) || crosshair.unwrap_or(false) {
+        let mut annotated = capture.image.to_rgba8();
+        let to_pixels =
+            |(x, y): (f64, f64)| ((x * capture.scale) as i64, (y * capture.scale) as i64);
+        let size = scale_factor.round().max(1.0) as i64;
+        if crosshair.unwrap_or(false) {
+            if let Some(point) = handle.state::<LastActionPoint>().get() {
+                let (x, y) = to_pixels(point);
+                draw_crosshair(&mut annotated, x, y, size);
+            }
+        }
+        if cursor.unwrap_or(false) {
+            let (x, y) = to_pixels(pointer_location()?);
+            draw_cursor(&mut annotated, x, y, size);
+        }
+        capture.image = DynamicImage::ImageRgba8(annotated);
+    }
+    let mut image = capture.image;
+
     // If the image has an alpha channel, convert it to RGB
     if image.color().has_alpha() {
         image = image.to_rgb8().into();
@@ -38,15 +221,36 @@ pub async fn get_screenshot<R: Runtime>(handle: AppHandle<R>) -> Result<Screensh
     // Convert PathBuf to String
     let jpg_path_str = jpg_path.to_string_lossy().to_string();
 
-    // Create base64 representation
-    let mut buffer = Cursor::new(Vec::new());
-    image
-        .write_to(&mut buffer, ImageFormat::WebP)
-        .map_err(|e| e.to_string())?;
-    let base64_image = general_purpose::STANDARD.encode(buffer.into_inner());
-
     Ok(ScreenshotResult {
         path: jpg_path_str,
-        base64: format!("data:image/webp;base64,{}", base64_image),
+        base64: encode_webp(&image)?,
+        marks,
+        redactions,
+        journal_id,
+    })
+}
+
+/// Stores the current screen (or a region of it) under a name so it can be used as a reference image later
+#[command]
+pub async fn save_capture<R: Runtime>(
+    handle: AppHandle<R>,
+    name: String,
+    region: Option<Region>,
+) -> Result<String, CommandError> {
+    let path = capture_path(&name)?;
+    log::info!("saving capture {} of {:?}", name, region);
+
+    async_runtime::spawn_blocking(move || {
+        let capture = capture_screen(&handle)?;
+        let image = match region {
+            Some(region) => capture.crop(&region),
+            None => capture.image,
+        };
+        image
+            .save_with_format(&path, ImageFormat::Png)
+            .map_err(|e| format!("Failed to save capture: {}", e))?;
+        Ok::<_, CommandError>(path.to_string_lossy().to_string())
     })
+    .await
+    .map_err(|e| format!("Failed to save capture: {}", e))?
 }
@@ -0,0 +1,257 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use image::{DynamicImage, ImageFormat};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+use tauri::{async_runtime, command, AppHandle, Manager, Runtime, State};
+
+use super::action::{Action, ActionResult};
+use super::cursor::pointer_location;
+use super::error::CommandError;
+
+const JOURNAL_FILE: &str = "actions.jsonl";
+
+/// One action as it was performed, a line of `~/.paimon/journal/actions.jsonl`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct JournalEntry {
+    pub task_id: Option<String>,
+    pub step_id: Option<String>,
+    pub action: Action,
+    /// RFC 3339, UTC
+    pub started_at: String,
+    pub finished_at: String,
+    pub duration_ms: u64,
+    pub result: Option<ActionResult>,
+    pub error: Option<CommandError>,
+    /// Pointer position in action coordinates, when it could be read
+    pub cursor_before: Option<(f64, f64)>,
+    pub cursor_after: Option<(f64, f64)>,
+    /// The last screenshot taken before the action, stored in `~/.paimon/journal/screenshots`
+    pub screenshot_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Context {
+    task_id: Option<String>,
+    step_id: Option<String>,
+    screenshot_id: Option<String>,
+}
+
+/// What the next entries belong to. Writes to the journal file are serialized through it.
+#[derive(Default)]
+pub struct Journal(Mutex<Context>);
+
+/// Filters for `query_journal`, every one optional
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct JournalQuery {
+    pub task_id: Option<String>,
+    pub step_id: Option<String>,
+    /// The command name, like "click"
+    pub action: Option<String>,
+    /// Only actions that failed or were refused
+    pub errors_only: bool,
+    /// RFC 3339 bounds on when the action started
+    pub since: Option<String>,
+    pub until: Option<String>,
+    /// Only the most recent matches
+    pub limit: Option<usize>,
+}
+
+pub fn journal_dir() -> Result<PathBuf, String> {
+    let dir = crate::dot_paimon_dir().join("journal");
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create journal directory: {}", e))?;
+    Ok(dir)
+}
+
+pub fn screenshots_dir() -> Result<PathBuf, String> {
+    let dir = journal_dir()?.join("screenshots");
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create journal screenshots directory: {}", e))?;
+    Ok(dir)
+}
+
+pub(crate) fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Marks the start of an action, `record` turns it into an entry once the action is done
+pub struct Started {
+    at: DateTime<Utc>,
+    instant: Instant,
+    cursor: Option<(f64, f64)>,
+}
+
+pub fn start() -> Started {
+    Started {
+        at: Utc::now(),
+        instant: Instant::now(),
+        cursor: pointer_location().ok(),
+    }
+}
+
+/// Appends an action and its outcome to the journal. Failing to write is logged, never fatal.
+pub fn record<R: Runtime>(
+    handle: &AppHandle<R>,
+    action: &Action,
+    started: Started,
+    outcome: &Result<ActionResult, CommandError>,
+) {
+    let journal = handle.state::<Journal>();
+    let context = journal.0.lock().unwrap();
+    let entry = JournalEntry {
+        task_id: context.task_id.clone(),
+        step_id: context.step_id.clone(),
+        action: action.clone(),
+        started_at: timestamp(started.at),
+        finished_at: timestamp(Utc::now()),
+        duration_ms: started.instant.elapsed().as_millis() as u64,
+        result: outcome.as_ref().ok().copied(),
+        error: outcome.as_ref().err().cloned(),
+        cursor_before: started.cursor,
+        cursor_after: pointer_location().ok(),
+        screenshot_id: context.screenshot_id.clone(),
+    };
+    if let Err(e) = append(&entry) {
+        log::warn!("journal: failed to record {}: {}", action.name(), e);
+    }
+}
+
+pub(crate) fn append(entry: &JournalEntry) -> Result<(), String> {
+    let line = serde_json::to_string(entry)
+        .map_err(|e| format!("Failed to serialize journal entry: {}", e))?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(journal_dir()?.join(JOURNAL_FILE))
+        .map_err(|e| format!("Failed to open journal: {}", e))?;
+    writeln!(file, "{}", line).map_err(|e| format!("Failed to write journal: {}", e))
+}
+
+/// Keeps a copy of a screenshot for the journal, returning its ID
+pub fn store_screenshot(image: &DynamicImage) -> Result<String, String> {
+    let id = Utc::now().format("%Y%m%dT%H%M%S%.3fZ").to_string();
+    let path = screenshots_dir()?.join(format!("{}.webp", id));
+    DynamicImage::ImageRgb8(image.to_rgb8())
+        .save_with_format(&path, ImageFormat::WebP)
+        .map_err(|e| format!("Failed to save journal screenshot: {}", e))?;
+    Ok(id)
+}
+
+/// Stores a screenshot and makes it the one the next actions refer to
+pub fn save_screenshot<R: Runtime>(
+    handle: &AppHandle<R>,
+    image: &DynamicImage,
+) -> Result<String, String> {
+    let id = store_screenshot(image)?;
+    handle.state::<Journal>().0.lock().unwrap().screenshot_id = Some(id.clone());
+    Ok(id)
+}
+
+pub(crate) fn parse_time(time: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(time)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|e| format!("Invalid time {:?}: {}", time, e))
+}
+
+impl JournalQuery {
+    fn matches(
+        &self,
+        entry: &JournalEntry,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> bool {
+        if self.task_id.is_some() && self.task_id != entry.task_id {
+            return false;
+        }
+        if self.step_id.is_some() && self.step_id != entry.step_id {
+            return false;
+        }
+        if self
+            .action
+            .as_ref()
+            .is_some_and(|name| name != entry.action.name())
+        {
+            return false;
+        }
+        if self.errors_only && entry.error.is_none() {
+            return false;
+        }
+        if since.is_some() || until.is_some() {
+            let Ok(started) = parse_time(&entry.started_at) else {
+                return false;
+            };
+            if since.is_some_and(|t| started < t) || until.is_some_and(|t| started > t) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Reads the journal entries matching a query, oldest first
+pub fn query(query: &JournalQuery) -> Result<Vec<JournalEntry>, String> {
+    let since = query.since.as_deref().map(parse_time).transpose()?;
+    let until = query.until.as_deref().map(parse_time).transpose()?;
+    let path = journal_dir()?.join(JOURNAL_FILE);
+    let file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to open journal: {}", e)),
+    };
+    let mut entries = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("Failed to read journal: {}", e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<JournalEntry>(&line) {
+            Ok(entry) if query.matches(&entry, since, until) => entries.push(entry),
+            Ok(_) => {}
+            Err(e) => log::warn!("journal: skipping invalid line {}: {}", number + 1, e),
+        }
+    }
+    if let Some(limit) = query.limit {
+        let skip = entries.len().saturating_sub(limit);
+        entries.drain(..skip);
+    }
+    Ok(entries)
+}
+
+impl Journal {
+    pub fn start_task(&self, task_id: String) {
+        let mut context = self.0.lock().unwrap();
+        context.task_id = Some(task_id);
+        context.step_id = None;
+    }
+
+    /// The task the next entries belong to
+    pub fn task_id(&self) -> Option<String> {
+        self.0.lock().unwrap().task_id.clone()
+    }
+
+    pub fn set_step(&self, step_id: Option<String>) {
+        self.0.lock().unwrap().step_id = step_id;
+    }
+}
+
+/// Starts a new task in the journal, the entries that follow carry its ID
+#[command]
+pub fn start_journal_task(journal: State<'_, Journal>, task_id: String) {
+    journal.start_task(task_id);
+}
+
+/// Sets the step of the current task the next actions belong to, like the model's call ID
+#[command]
+pub fn set_journal_step(journal: State<'_, Journal>, step_id: Option<String>) {
+    journal.set_step(step_id);
+}
+
+#[command]
+pub async fn query_journal(query: JournalQuery) -> Result<Vec<JournalEntry>, String> {
+    async_runtime::spawn_blocking(move || self::query(&query))
+        .await
+        .map_err(|e| format!("Failed to query journal: {}", e))?
+}
@@ -0,0 +1,331 @@
+use image::{imageops::FilterType, GrayImage, Rgba, RgbaImage};
+use std::sync::Mutex;
+use tauri::{command, AppHandle, Manager, Runtime, State};
+
+use super::action::{self, Action};
+use super::error::CommandError;
+use super::image::{Capture, Region};
+use super::ocr::Ocr;
+
+const DEFAULT_GRID_SPACING: f64 = 100.0;
+// Neighbouring pixels further apart than this are treated as an edge when looking for elements
+const EDGE_THRESHOLD: u8 = 24;
+// Elements smaller than this (in action coordinates) are usually noise
+const MIN_ELEMENT_SIZE: f64 = 8.0;
+// More marks than this make the screenshot unreadable
+const MAX_MARKS: usize = 200;
+
+const MARK_COLOR: Rgba<u8> = Rgba([255, 0, 128, 255]);
+const LABEL_TEXT_COLOR: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+// 5x7 bitmaps for the digits 0-9, one byte per row with the 5 low bits used
+const DIGITS: [[u8; 7]; 10] = [
+    [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+    [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+    [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+    [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+    [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+    [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+    [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+    [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+];
+
+/// Where the numbered marks drawn on a screenshot come from
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MarkSource {
+    /// A regular grid, `spacing` apart in action coordinates
+    Grid { spacing: Option<f64> },
+    /// Boxes around anything that stands out from its background, like buttons and icons
+    Elements,
+    /// Every word found by OCR
+    Text,
+}
+
+/// A numbered mark and the point it stands for, in action coordinates
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Mark {
+    pub id: u32,
+    pub x: f64,
+    pub y: f64,
+    pub bounds: Region,
+    pub label: Option<String>,
+}
+
+/// The marks drawn on the last annotated screenshot, so the model can refer to them by number
+#[derive(Default)]
+pub struct Marks(Mutex<Vec<Mark>>);
+
+impl Marks {
+    pub fn set(&self, marks: Vec<Mark>) {
+        *self.0.lock().unwrap() = marks;
+    }
+
+    pub fn get(&self, id: u32) -> Result<Mark, String> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|m| m.id == id)
+            .cloned()
+            .ok_or(format!("No mark {} on the last screenshot", id))
+    }
+}
+
+fn mark(id: usize, bounds: Region, label: Option<String>) -> Mark {
+    Mark {
+        id: id as u32 + 1,
+        x: bounds.x + bounds.width / 2.0,
+        y: bounds.y + bounds.height / 2.0,
+        bounds,
+        label,
+    }
+}
+
+fn grid_marks(capture: &Capture, spacing: f64) -> Vec<Mark> {
+    let width = capture.image.width() as f64 / capture.scale;
+    let height = capture.image.height() as f64 / capture.scale;
+    let mut bounds = Vec::new();
+    let mut y = 0.0;
+    while y < height {
+        let mut x = 0.0;
+        while x < width {
+            bounds.push(Region {
+                x,
+                y,
+                width: spacing.min(width - x),
+                height: spacing.min(height - y),
+            });
+            x += spacing;
+        }
+        y += spacing;
+    }
+    bounds
+        .into_iter()
+        .enumerate()
+        .map(|(id, bounds)| mark(id, bounds, None))
+        .collect()
+}
+
+// Finds boxes around connected areas of edges. Works at one pixel per action coordinate,
+// and grows edges by a pixel so the letters of a label merge into one element.
+fn element_marks(capture: &Capture) -> Vec<Mark> {
+    let width = ((capture.image.width() as f64 / capture.scale) as u32).max(1);
+    let height = ((capture.image.height() as f64 / capture.scale) as u32).max(1);
+    let gray: GrayImage = capture
+        .image
+        .resize_exact(width, height, FilterType::Triangle)
+        .to_luma8();
+
+    let (w, h) = (width as usize, height as usize);
+    let mut edges = vec![false; w * h];
+    for y in 0..h - 1 {
+        for x in 0..w - 1 {
+            let value = gray.get_pixel(x as u32, y as u32).0[0];
+            let right = gray.get_pixel(x as u32 + 1, y as u32).0[0];
+            let below = gray.get_pixel(x as u32, y as u32 + 1).0[0];
+            if value.abs_diff(right) > EDGE_THRESHOLD || value.abs_diff(below) > EDGE_THRESHOLD {
+                edges[y * w + x] = true;
+            }
+        }
+    }
+    let mut grown = edges.clone();
+    for y in 0..h {
+        for x in 0..w {
+            if edges[y * w + x] {
+                for (nx, ny) in [
+                    (x.wrapping_sub(1), y),
+                    (x + 1, y),
+                    (x, y.wrapping_sub(1)),
+                    (x, y + 1),
+                ] {
+                    if nx < w && ny < h {
+                        grown[ny * w + nx] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    // Flood fill each connected area and keep its bounding box
+    let mut visited = vec![false; w * h];
+    let mut boxes: Vec<Region> = Vec::new();
+    for start in 0..w * h {
+        if !grown[start] || visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut stack = vec![start];
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (w, h, 0, 0);
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i % w, i / w);
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+            for (nx, ny) in [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ] {
+                if nx < w && ny < h && grown[ny * w + nx] && !visited[ny * w + nx] {
+                    visited[ny * w + nx] = true;
+                    stack.push(ny * w + nx);
+                }
+            }
+        }
+        let region = Region {
+            x: min_x as f64,
+            y: min_y as f64,
+            width: (max_x - min_x + 1) as f64,
+            height: (max_y - min_y + 1) as f64,
+        };
+        // Huge areas are windows and panels rather than things to click
+        if region.width >= MIN_ELEMENT_SIZE
+            && region.height >= MIN_ELEMENT_SIZE
+            && region.width <= width as f64 / 2.0
+            && region.height <= height as f64 / 2.0
+        {
+            boxes.push(region);
+        }
+    }
+
+    // Prefer the smaller elements when there are too many to label
+    boxes.sort_by(|a, b| (a.width * a.height).total_cmp(&(b.width * b.height)));
+    boxes.truncate(MAX_MARKS);
+    boxes.sort_by(|a, b| a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x)));
+    boxes
+        .into_iter()
+        .enumerate()
+        .map(|(id, bounds)| mark(id, bounds, None))
+        .collect()
+}
+
+fn text_marks<R: Runtime>(handle: &AppHandle<R>, capture: &Capture) -> Result<Vec<Mark>, String> {
+    let result = handle.state::<Ocr>().recognize(capture, None)?;
+    Ok(result
+        .words()
+        .take(MAX_MARKS)
+        .enumerate()
+        .map(|(id, word)| mark(id, word.bounds, Some(word.text.clone())))
+        .collect())
+}
+
+/// Works out the marks for a capture without drawing them
+pub fn find_marks<R: Runtime>(
+    handle: &AppHandle<R>,
+    capture: &Capture,
+    source: &MarkSource,
+) -> Result<Vec<Mark>, String> {
+    match source {
+        MarkSource::Grid { spacing } => {
+            let spacing = spacing.unwrap_or(DEFAULT_GRID_SPACING);
+            if spacing < 10.0 {
+                return Err(format!("Grid spacing {} is too small", spacing));
+            }
+            Ok(grid_marks(capture, spacing))
+        }
+        MarkSource::Elements => Ok(element_marks(capture)),
+        MarkSource::Text => text_marks(handle, capture),
+    }
+}
+
+pub(crate) fn fill_rect(
+    image: &mut RgbaImage,
+    x: i64,
+    y: i64,
+    width: i64,
+    height: i64,
+    color: Rgba<u8>,
+) {
+    for py in y.max(0)..(y + height).min(image.height() as i64) {
+        for px in x.max(0)..(x + width).min(image.width() as i64) {
+            image.put_pixel(px as u32, py as u32, color);
+        }
+    }
+}
+
+pub(crate) fn outline_rect(
+    image: &mut RgbaImage,
+    x: i64,
+    y: i64,
+    width: i64,
+    height: i64,
+    thickness: i64,
+    color: Rgba<u8>,
+) {
+    fill_rect(image, x, y, width, thickness, color);
+    fill_rect(image, x, y + height - thickness, width, thickness, color);
+    fill_rect(image, x, y, thickness, height, color);
+    fill_rect(image, x + width - thickness, y, thickness, height, color);
+}
+
+/// Draws each mark's box and its number onto a screenshot taken at the given scale
+pub fn draw_marks(image: &mut RgbaImage, marks: &[Mark], scale: f64) {
+    // size of one font pixel, roughly 2 points
+    let px = (2.0 * scale).round().max(1.0) as i64;
+    for mark in marks {
+        let x = (mark.bounds.x * scale) as i64;
+        let y = (mark.bounds.y * scale) as i64;
+        let width = (mark.bounds.width * scale) as i64;
+        let height = (mark.bounds.height * scale) as i64;
+        outline_rect(image, x, y, width, height, px.max(2) / 2, MARK_COLOR);
+
+        // Label in the top left corner of the box: a filled tag with white digits
+        let digits: Vec<usize> = mark
+            .id
+            .to_string()
+            .chars()
+            .filter_map(|c| c.to_digit(10))
+            .map(|d| d as usize)
+            .collect();
+        let label_width = (digits.len() as i64 * 6 + 1) * px;
+        let label_height = 9 * px;
+        fill_rect(image, x, y, label_width, label_height, MARK_COLOR);
+        for (i, digit) in digits.iter().enumerate() {
+            let origin_x = x + (i as i64 * 6 + 1) * px;
+            let origin_y = y + px;
+            for (row, bits) in DIGITS[*digit].iter().enumerate() {
+                for col in 0..5 {
+                    if bits & (0x10 >> col) != 0 {
+                        fill_rect(
+                            image,
+                            origin_x + col * px,
+                            origin_y + row as i64 * px,
+                            px,
+                            px,
+                            LABEL_TEXT_COLOR,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Resolves a mark from the last annotated screenshot to its point
+#[command]
+pub fn resolve_mark(marks: State<'_, Marks>, id: u32) -> Result<Mark, String> {
+    marks.get(id)
+}
+
+/// Clicks the centre of a mark from the last annotated screenshot
+#[command]
+pub async fn click_mark<R: Runtime>(
+    handle: AppHandle<R>,
+    id: u32,
+    button: Option<String>,
+) -> Result<Mark, CommandError> {
+    let mark = handle.state::<Marks>().get(id)?;
+    log::info!("agent: clicking mark {} at {}, {}", id, mark.x, mark.y);
+    let action = Action::Click {
+        button: button.unwrap_or_else(|| "left".to_string()),
+        x: mark.x,
+        y: mark.y,
+    };
+    action::perform(&handle, action).await?;
+    Ok(mark)
+}
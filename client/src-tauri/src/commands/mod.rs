@@ -1,3 +1,24 @@
 pub mod action;
+pub mod agent;
+pub mod approval;
+pub mod color;
+pub mod cursor;
+pub mod error;
+pub mod governor;
 pub mod image;
+pub mod journal;
+pub mod marks;
+pub mod ocr;
+pub mod privacy;
+pub mod provider;
+pub mod redact;
+pub mod replay;
+pub mod secrets;
+pub mod skills;
+pub mod teach;
+pub mod template;
+pub mod text;
+pub mod watch;
 pub mod windowing;
+pub mod workflow;
+pub mod zones;
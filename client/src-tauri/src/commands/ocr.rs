@@ -0,0 +1,190 @@
+use image::DynamicImage;
+use ocrs::{ImageSource, OcrEngine, OcrEngineParams, TextItem};
+use rten::Model;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use tauri::{async_runtime, command, AppHandle, Manager, Runtime};
+
+use super::error::CommandError;
+use super::image::{capture_screen, Capture, Region};
+
+const DETECTION_MODEL: &str = "text-detection.rten";
+const RECOGNITION_MODEL: &str = "text-recognition.rten";
+const MODELS_URL: &str = "https://ocrs-models.s3-accelerate.amazonaws.com";
+// Screens rarely cycle through more distinct states than this within a task
+const CACHE_SIZE: usize = 16;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct OcrWord {
+    pub text: String,
+    pub bounds: Region,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct OcrLine {
+    pub text: String,
+    pub bounds: Region,
+    pub words: Vec<OcrWord>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct OcrResult {
+    // hash of the captured pixels the text was read from
+    pub hash: String,
+    pub lines: Vec<OcrLine>,
+}
+
+impl OcrResult {
+    pub fn words(&self) -> impl Iterator<Item = &OcrWord> {
+        self.lines.iter().flat_map(|line| line.words.iter())
+    }
+}
+
+/// The OCR engine, loaded on first use, and recent results keyed by capture hash
+#[derive(Default)]
+pub struct Ocr {
+    engine: OnceLock<OcrEngine>,
+    cache: Mutex<VecDeque<(u64, Arc<OcrResult>)>>,
+}
+
+fn models_dir() -> PathBuf {
+    crate::dot_paimon_dir().join("models")
+}
+
+/// Whether the model files are in place, the engine can't load without them
+pub fn models_available() -> bool {
+    [DETECTION_MODEL, RECOGNITION_MODEL]
+        .iter()
+        .all(|name| models_dir().join(name).exists())
+}
+
+fn load_model(name: &str) -> Result<Model, String> {
+    let path = models_dir().join(name);
+    if !path.exists() {
+        return Err(format!(
+            "OCR model {} is missing, download it from {}/{} into {}",
+            name,
+            MODELS_URL,
+            name,
+            models_dir().display()
+        ));
+    }
+    Model::load_file(&path).map_err(|e| format!("Failed to load OCR model {}: {}", name, e))
+}
+
+fn hash_image(image: &DynamicImage) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    image.width().hash(&mut hasher);
+    image.height().hash(&mut hasher);
+    image.as_bytes().hash(&mut hasher);
+    hasher.finish()
+}
+
+// Converts the bounds of a recognized item from capture pixels into action coordinates
+fn bounds_of(item: &impl TextItem, capture: &Capture, offset: (f64, f64)) -> Region {
+    let rect = item.bounding_rect();
+    Region {
+        x: offset.0 + rect.left() as f64 / capture.scale,
+        y: offset.1 + rect.top() as f64 / capture.scale,
+        width: rect.width() as f64 / capture.scale,
+        height: rect.height() as f64 / capture.scale,
+    }
+}
+
+impl Ocr {
+    fn engine(&self) -> Result<&OcrEngine, String> {
+        if let Some(engine) = self.engine.get() {
+            return Ok(engine);
+        }
+        log::info!("loading OCR models from {}", models_dir().display());
+        let engine = OcrEngine::new(OcrEngineParams {
+            detection_model: Some(load_model(DETECTION_MODEL)?),
+            recognition_model: Some(load_model(RECOGNITION_MODEL)?),
+            ..Default::default()
+        })
+        .map_err(|e| format!("Failed to create OCR engine: {}", e))?;
+        // Another thread may have won the race, either engine is fine
+        Ok(self.engine.get_or_init(|| engine))
+    }
+
+    /// Reads the text in a capture (or a region of it), with bounds in action coordinates
+    pub fn recognize(
+        &self,
+        capture: &Capture,
+        region: Option<&Region>,
+    ) -> Result<Arc<OcrResult>, String> {
+        let (image, offset_x, offset_y) = match region {
+            Some(region) => (capture.crop(region), region.x.max(0.0), region.y.max(0.0)),
+            None => (capture.image.clone(), 0.0, 0.0),
+        };
+        let hash = hash_image(&image);
+        if let Some((_, result)) = self.cache.lock().unwrap().iter().find(|(h, _)| *h == hash) {
+            log::debug!("OCR cache hit for {:016x}", hash);
+            return Ok(result.clone());
+        }
+
+        let engine = self.engine()?;
+        let rgb = image.into_rgb8();
+        let source = ImageSource::from_bytes(rgb.as_raw(), rgb.dimensions())
+            .map_err(|e| format!("Failed to prepare image for OCR: {}", e))?;
+        let input = engine
+            .prepare_input(source)
+            .map_err(|e| format!("Failed to prepare image for OCR: {}", e))?;
+        let word_rects = engine
+            .detect_words(&input)
+            .map_err(|e| format!("Failed to detect text: {}", e))?;
+        let line_rects = engine.find_text_lines(&input, &word_rects);
+        let texts = engine
+            .recognize_text(&input, &line_rects)
+            .map_err(|e| format!("Failed to recognize text: {}", e))?;
+
+        let offset = (offset_x, offset_y);
+        let lines = texts
+            .iter()
+            .flatten()
+            .map(|line| OcrLine {
+                text: line.to_string(),
+                bounds: bounds_of(line, capture, offset),
+                words: line
+                    .words()
+                    .map(|word| OcrWord {
+                        text: word.to_string(),
+                        bounds: bounds_of(&word, capture, offset),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let result = Arc::new(OcrResult {
+            hash: format!("{:016x}", hash),
+            lines,
+        });
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= CACHE_SIZE {
+            cache.pop_front();
+        }
+        cache.push_back((hash, result.clone()));
+        Ok(result)
+    }
+}
+
+/// Captures the screen and reads the text on it, or in a region of it
+#[command]
+pub async fn recognize_text<R: Runtime>(
+    handle: AppHandle<R>,
+    region: Option<Region>,
+) -> Result<OcrResult, CommandError> {
+    log::info!("recognizing text in {:?}", region);
+    let result = async_runtime::spawn_blocking(move || {
+        let capture = capture_screen(&handle)?;
+        Ok::<_, CommandError>(handle.state::<Ocr>().recognize(&capture, region.as_ref())?)
+    })
+    .await
+    .map_err(|e| format!("Failed to recognize text: {}", e))??;
+
+    log::info!("recognized {} lines of text", result.lines.len());
+    Ok((*result).clone())
+}
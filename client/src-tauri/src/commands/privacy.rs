@@ -0,0 +1,142 @@
+use serde_json::json;
+use std::sync::Mutex;
+use tauri::{command, AppHandle, Emitter, Manager, Runtime, State};
+
+use super::error::{CommandError, ErrorKind};
+use super::windowing::{foreground_window, visible_windows, WindowInfo};
+use crate::config;
+
+const CONFIG_NAME: &str = "privacy";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PrivacyConfig {
+    pub enabled: bool,
+    /// Applications the agent must not see or touch, matched case-insensitively
+    pub apps: Vec<String>,
+    /// Fragments of window titles, for things like banking sites open in a browser
+    pub titles: Vec<String>,
+    /// Also pause while a blocked window is visible anywhere, not only when it is focused
+    pub when_visible: bool,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        PrivacyConfig {
+            enabled: true,
+            apps: [
+                "1Password",
+                "Bitwarden",
+                "Dashlane",
+                "KeePassXC",
+                "Keychain Access",
+                "LastPass",
+            ]
+            .map(String::from)
+            .to_vec(),
+            titles: Vec::new(),
+            when_visible: true,
+        }
+    }
+}
+
+impl PrivacyConfig {
+    fn blocks(&self, window: &WindowInfo) -> bool {
+        let title = window.title.to_lowercase();
+        self.apps
+            .iter()
+            .any(|app| app.eq_ignore_ascii_case(&window.app_name))
+            || self
+                .titles
+                .iter()
+                .any(|fragment| !fragment.is_empty() && title.contains(&fragment.to_lowercase()))
+    }
+}
+
+/// The privacy policy, persisted to `~/.paimon/privacy.json`, and whether it is currently pausing the agent
+pub struct Privacy {
+    config: Mutex<PrivacyConfig>,
+    paused: Mutex<bool>,
+}
+
+impl Default for Privacy {
+    fn default() -> Self {
+        Privacy {
+            config: Mutex::new(config::load(CONFIG_NAME)),
+            paused: Mutex::new(false),
+        }
+    }
+}
+
+/// Refuses with a `privacy_blocked` error while a blocked window is focused (or visible).
+/// Emits `agent_privacy_paused` when refusing and `agent_privacy_resumed` once it clears.
+pub fn check_privacy<R: Runtime>(handle: &AppHandle<R>) -> Result<(), CommandError> {
+    let privacy = handle.state::<Privacy>();
+    let config = privacy.config.lock().unwrap().clone();
+    if !config.enabled {
+        return Ok(());
+    }
+
+    let foreground = foreground_window()?;
+    let mut blocked = config
+        .blocks(&foreground)
+        .then(|| (foreground, "foreground"));
+    if blocked.is_none() && config.when_visible {
+        blocked = visible_windows()?
+            .into_iter()
+            .find(|window| config.blocks(window))
+            .map(|window| (window, "visible"));
+    }
+
+    let mut paused = privacy.paused.lock().unwrap();
+    let Some((window, reason)) = blocked else {
+        if *paused {
+            *paused = false;
+            log::info!("privacy: no blocked windows left, resuming");
+            handle
+                .emit("agent_privacy_resumed", json!({}))
+                .map_err(|e| format!("Failed to emit privacy event: {}", e))?;
+        }
+        return Ok(());
+    };
+
+    *paused = true;
+    log::warn!(
+        "privacy: refusing while {} ({:?}) is {}",
+        window.app_name,
+        window.title,
+        reason
+    );
+    handle
+        .emit(
+            "agent_privacy_paused",
+            json!({ "app": window.app_name, "title": window.title, "reason": reason }),
+        )
+        .map_err(|e| format!("Failed to emit privacy event: {}", e))?;
+    Err(CommandError::new(
+        ErrorKind::PrivacyBlocked {
+            app: window.app_name.clone(),
+            title: window.title.clone(),
+            reason: reason.to_string(),
+        },
+        format!(
+            "Blocked by privacy policy: {} is {}",
+            window.app_name, reason
+        ),
+    ))
+}
+
+#[command]
+pub fn get_privacy_config(privacy: State<'_, Privacy>) -> PrivacyConfig {
+    privacy.config.lock().unwrap().clone()
+}
+
+#[command]
+pub fn set_privacy_config(
+    privacy: State<'_, Privacy>,
+    config: PrivacyConfig,
+) -> Result<(), String> {
+    config::save(CONFIG_NAME, &config)?;
+    *privacy.config.lock().unwrap() = config;
+    Ok(())
+}
@@ -0,0 +1,476 @@
+use serde_json::{json, Value};
+
+use super::{post, translate, Input, Item, ModelProvider, ProviderFuture, Setup};
+
+pub const BASE_URL: &str = "https://api.anthropic.com/v1";
+pub const MODEL: &str = "claude-sonnet-4-20250514";
+const API_VERSION: &str = "2023-06-01";
+const BETA: &str = "computer-use-2025-01-24";
+const MAX_TOKENS: u32 = 4096;
+// Older screenshots are dropped from the history, they only cost tokens
+const KEPT_SCREENSHOTS: usize = 3;
+
+/// The Messages API with the computer-use tool. The API is stateless, so the conversation is
+/// kept here and sent whole each turn.
+pub struct Anthropic {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: String,
+    model: String,
+    messages: Vec<Value>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Response {
+    content: Vec<Value>,
+    #[serde(default)]
+    stop_reason: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Block {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    #[serde(other)]
+    Other,
+}
+
+// Splits a data URL into its media type and base64 data
+fn image(data_url: &str) -> Value {
+    let (media_type, data) = data_url
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"))
+        .unwrap_or(("image/png", data_url));
+    json!({
+        "type": "image",
+        "source": { "type": "base64", "media_type": media_type, "data": data },
+    })
+}
+
+impl Anthropic {
+    pub fn new(base_url: String, api_key: String, model: String) -> Self {
+        Anthropic {
+            client: reqwest::Client::new(),
+            base_url,
+            api_key,
+            model,
+            messages: Vec::new(),
+        }
+    }
+
+    fn tools(setup: &Setup) -> Vec<Value> {
+        let mut tools = vec![json!({
+            "type": "computer_20250124",
+            "name": "computer",
+            "display_width_px": setup.display_width,
+            "display_height_px": setup.display_height,
+        })];
+        tools.extend(setup.functions.iter().map(|function| {
+            json!({
+                "name": function.name,
+                "description": function.description,
+                "input_schema": function.parameters,
+            })
+        }));
+        tools
+    }
+
+    fn user_message(input: Vec<Input>) -> Value {
+        let content: Vec<Value> = input
+            .into_iter()
+            .map(|input| match input {
+                Input::Task(task) => json!({ "type": "text", "text": task }),
+                Input::ComputerResult {
+                    call_id,
+                    screenshot,
+                    output,
+                    refusal,
+                    ..
+                } => {
+                    let mut content = vec![image(&screenshot)];
+                    for text in output.iter().chain(&refusal) {
+                        content.push(json!({ "type": "text", "text": text }));
+                    }
+                    json!({
+                        "type": "tool_result",
+                        "tool_use_id": call_id,
+                        "content": content,
+                        "is_error": refusal.is_some(),
+                    })
+                }
+                Input::FunctionResult { call_id, output } => json!({
+                    "type": "tool_result",
+                    "tool_use_id": call_id,
+                    "content": output,
+                }),
+            })
+            .collect();
+        json!({ "role": "user", "content": content })
+    }
+
+    // Replaces all but the latest screenshots in the history with a note
+    fn drop_old_screenshots(&mut self) {
+        let mut kept = 0;
+        for message in self.messages.iter_mut().rev() {
+            let Some(blocks) = message["content"].as_array_mut() else {
+                continue;
+            };
+            for block in blocks.iter_mut().rev() {
+                let Some(content) = block["content"].as_array_mut() else {
+                    continue;
+                };
+                for part in content.iter_mut().filter(|part| part["type"] == "image") {
+                    if kept < KEPT_SCREENSHOTS {
+                        kept += 1;
+                    } else {
+                        *part = json!({ "type": "text", "text": "(older screenshot removed)" });
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl ModelProvider for Anthropic {
+    fn respond<'a>(
+        &'a mut self,
+        setup: &'a Setup,
+        input: Vec<Input>,
+    ) -> ProviderFuture<'a, Vec<Item>> {
+        Box::pin(async move {
+            let sent = self.messages.len();
+            self.messages.push(Self::user_message(input));
+            self.drop_old_screenshots();
+            let body = json!({
+                "model": self.model,
+                "max_tokens": MAX_TOKENS,
+                "system": setup.system_prompt,
+                "tools": Self::tools(setup),
+                "messages": self.messages,
+            });
+            let request = self
+                .client
+                .post(format!("{}/messages", self.base_url))
+                .header("x-api-key", &self.api_key)
+                .header("anthropic-version", API_VERSION)
+                .header("anthropic-beta", BETA);
+            let response: Response = match post(request, &body).await {
+                Ok(response) => response,
+                Err(e) => {
+                    // leave the history as it was so the turn can be sent again
+                    self.messages.truncate(sent);
+                    return Err(e);
+                }
+            };
+            self.messages
+                .push(json!({ "role": "assistant", "content": response.content }));
+
+            let refused = response.stop_reason.as_deref() == Some("refusal");
+            let mut items = Vec::new();
+            for block in response.content {
+                match serde_json::from_value(block) {
+                    Ok(Block::Text { text }) if refused => items.push(Item::Refusal(text)),
+                    Ok(Block::Text { text }) => items.push(Item::Message(text)),
+                    Ok(Block::ToolUse { id, name, input }) if name == "computer" => {
+                        items.push(Item::Computer {
+                            call_id: id,
+                            call: translate::anthropic(&input),
+                            safety_checks: Vec::new(),
+                        })
+                    }
+                    Ok(Block::ToolUse { id, name, input }) => items.push(Item::Function {
+                        call_id: id,
+                        name,
+                        arguments: Ok(input),
+                    }),
+                    Ok(Block::Other) => {}
+                    Err(e) => log::warn!("agent: skipping a content block: {}", e),
+                }
+            }
+            Ok(items)
+        })
+    }
+
+    fn reset(&mut self) {
+        self.messages.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use tauri::async_runtime::block_on;
+
+    use super::super::mock::{screenshot, setup, Server};
+    use super::super::{ComputerCall, Input, Item, ModelProvider, PointerAction};
+    use super::{Anthropic, KEPT_SCREENSHOTS};
+
+    fn provider(server: &Server) -> Anthropic {
+        Anthropic::new(
+            server.base_url.clone(),
+            "sk-ant-test".to_string(),
+            "claude-test".to_string(),
+        )
+    }
+
+    fn tool_use(id: &str, input: Value) -> Value {
+        json!({
+            "content": [{ "type": "tool_use", "id": id, "name": "computer", "input": input }],
+            "stop_reason": "tool_use",
+        })
+    }
+
+    fn result(call_id: &str, refusal: Option<&str>) -> Input {
+        Input::ComputerResult {
+            call_id: call_id.to_string(),
+            screenshot: screenshot(),
+            output: None,
+            refusal: refusal.map(str::to_string),
+            safety_checks: Vec::new(),
+        }
+    }
+
+    // The image parts and placeholders left in a request's history
+    fn screenshots(body: &Value) -> (usize, usize) {
+        let parts: Vec<&Value> = body["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|message| message["content"].as_array())
+            .flatten()
+            .filter_map(|block| block["content"].as_array())
+            .flatten()
+            .collect();
+        let images = parts.iter().filter(|part| part["type"] == "image").count();
+        let removed = parts
+            .iter()
+            .filter(|part| part["text"] == "(older screenshot removed)")
+            .count();
+        (images, removed)
+    }
+
+    #[test]
+    fn pairs_tool_results_with_their_calls() {
+        let server = Server::start(vec![
+            (
+                200,
+                json!({
+                    "content": [
+                        { "type": "text", "text": "Clicking it" },
+                        {
+                            "type": "tool_use",
+                            "id": "toolu_1",
+                            "name": "computer",
+                            "input": { "action": "left_click", "coordinate": [10, 20] },
+                        },
+                        {
+                            "type": "tool_use",
+                            "id": "toolu_2",
+                            "name": "click_mark",
+                            "input": { "mark": 3 },
+                        },
+                    ],
+                    "stop_reason": "tool_use",
+                }),
+            ),
+            (
+                200,
+                json!({
+                    "content": [{ "type": "text", "text": "Done" }],
+                    "stop_reason": "end_turn",
+                }),
+            ),
+        ]);
+        let setup = setup();
+        let mut provider = provider(&server);
+
+        let items =
+            block_on(provider.respond(&setup, vec![Input::Task("Open it".to_string())])).unwrap();
+        assert_eq!(
+            items,
+            vec![
+                Item::Message("Clicking it".to_string()),
+                Item::Computer {
+                    call_id: "toolu_1".to_string(),
+                    call: Ok(ComputerCall::Perform(
+                        PointerAction::Click {
+                            button: "left".to_string()
+                        }
+                        .at(10.0, 20.0)
+                    )),
+                    safety_checks: Vec::new(),
+                },
+                Item::Function {
+                    call_id: "toolu_2".to_string(),
+                    name: "click_mark".to_string(),
+                    arguments: Ok(json!({ "mark": 3 })),
+                },
+            ]
+        );
+
+        let items = block_on(provider.respond(
+            &setup,
+            vec![
+                result("toolu_1", Some("Not allowed")),
+                Input::FunctionResult {
+                    call_id: "toolu_2".to_string(),
+                    output: "{}".to_string(),
+                },
+            ],
+        ))
+        .unwrap();
+        assert_eq!(items, vec![Item::Message("Done".to_string())]);
+
+        let requests = server.requests();
+        for request in requests.iter() {
+            assert_eq!(request.path, "/messages");
+            assert_eq!(request.headers["x-api-key"], "sk-ant-test");
+            assert_eq!(request.headers["anthropic-version"], "2023-06-01");
+            assert_eq!(request.headers["anthropic-beta"], "computer-use-2025-01-24");
+            assert!(!request.headers.contains_key("authorization"));
+            assert_eq!(request.body["system"], "You use a computer.");
+            assert_eq!(request.body["tools"][0]["name"], "computer");
+            assert_eq!(request.body["tools"][0]["display_width_px"], 1280);
+            assert_eq!(request.body["tools"][1]["input_schema"]["type"], "object");
+        }
+        let messages = requests[1].body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["role"], "assistant");
+        assert_eq!(messages[1]["content"][1]["id"], "toolu_1");
+        let results = &messages[2]["content"];
+        assert_eq!(results[0]["type"], "tool_result");
+        assert_eq!(results[0]["tool_use_id"], "toolu_1");
+        assert_eq!(results[0]["is_error"], true);
+        assert_eq!(results[0]["content"][0]["type"], "image");
+        assert_eq!(
+            results[0]["content"][0]["source"]["media_type"],
+            "image/png"
+        );
+        assert_eq!(results[0]["content"][0]["source"]["data"], "iVBORw0KGgo=");
+        assert_eq!(results[0]["content"][1]["text"], "Not allowed");
+        assert_eq!(results[1]["tool_use_id"], "toolu_2");
+        assert_eq!(results[1]["content"], "{}");
+    }
+
+    #[test]
+    fn reports_refusals() {
+        let server = Server::start(vec![(
+            200,
+            json!({
+                "content": [{ "type": "text", "text": "I can't help with that" }],
+                "stop_reason": "refusal",
+            }),
+        )]);
+        let setup = setup();
+        let mut provider = provider(&server);
+
+        let items =
+            block_on(provider.respond(&setup, vec![Input::Task("Do it".to_string())])).unwrap();
+        assert_eq!(
+            items,
+            vec![Item::Refusal("I can't help with that".to_string())]
+        );
+    }
+
+    #[test]
+    fn drops_old_screenshots() {
+        let turns = KEPT_SCREENSHOTS + 2;
+        let server = Server::start(
+            (0..=turns)
+                .map(|turn| {
+                    (
+                        200,
+                        tool_use(
+                            &format!("toolu_{}", turn),
+                            json!({ "action": "screenshot" }),
+                        ),
+                    )
+                })
+                .collect(),
+        );
+        let setup = setup();
+        let mut provider = provider(&server);
+
+        block_on(provider.respond(&setup, vec![Input::Task("Look".to_string())])).unwrap();
+        for turn in 0..turns {
+            let items =
+                block_on(provider.respond(&setup, vec![result(&format!("toolu_{}", turn), None)]))
+                    .unwrap();
+            assert!(matches!(
+                &items[..],
+                [Item::Computer {
+                    call: Ok(ComputerCall::Screenshot),
+                    ..
+                }]
+            ));
+        }
+
+        let requests = server.requests();
+        let last = &requests.last().unwrap().body;
+        assert_eq!(
+            screenshots(last),
+            (KEPT_SCREENSHOTS, turns - KEPT_SCREENSHOTS)
+        );
+        // the newest ones are kept
+        let messages = last["messages"].as_array().unwrap();
+        let newest = &messages[messages.len() - 1]["content"][0]["content"][0];
+        assert_eq!(newest["type"], "image");
+        let oldest = &messages[2]["content"][0]["content"][0];
+        assert_eq!(oldest["type"], "text");
+    }
+
+    #[test]
+    fn forgets_a_failed_turn() {
+        let server = Server::start(vec![
+            (200, tool_use("toolu_1", json!({ "action": "screenshot" }))),
+            (
+                529,
+                json!({ "type": "error", "error": { "type": "overloaded_error" } }),
+            ),
+            (
+                200,
+                json!({ "content": [{ "type": "text", "text": "Done" }], "stop_reason": "end_turn" }),
+            ),
+        ]);
+        let setup = setup();
+        let mut provider = provider(&server);
+
+        block_on(provider.respond(&setup, vec![Input::Task("Look".to_string())])).unwrap();
+        let error = block_on(provider.respond(&setup, vec![result("toolu_1", None)])).unwrap_err();
+        assert!(error.contains("529"), "{}", error);
+        assert!(error.contains("overloaded_error"), "{}", error);
+        block_on(provider.respond(&setup, vec![result("toolu_1", None)])).unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[1].body["messages"].as_array().unwrap().len(), 3);
+        // the retry replaces the failed turn instead of following it
+        assert_eq!(requests[2].body["messages"], requests[1].body["messages"]);
+    }
+
+    #[test]
+    fn turns_bad_computer_input_into_an_error_for_the_model() {
+        let server = Server::start(vec![(
+            200,
+            tool_use(
+                "toolu_1",
+                json!({ "action": "scroll", "coordinate": [1, 2], "scroll_direction": "sideways" }),
+            ),
+        )]);
+        let setup = setup();
+        let mut provider = provider(&server);
+
+        let items =
+            block_on(provider.respond(&setup, vec![Input::Task("Scroll".to_string())])).unwrap();
+        assert!(matches!(
+            &items[..],
+            [Item::Computer { call_id, call: Err(_), .. }] if call_id == "toolu_1"
+        ));
+    }
+}
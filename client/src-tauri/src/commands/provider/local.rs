@@ -0,0 +1,501 @@
+use serde_json::{json, Value};
+
+use super::{post, translate, Input, Item, ModelProvider, ProviderFuture, Setup};
+
+pub const BASE_URL: &str = "http://localhost:11434/v1";
+// Local models have small contexts, only the latest screenshots are sent
+const KEPT_SCREENSHOTS: usize = 2;
+
+/// A server with an OpenAI-compatible chat completions API. These have no computer tool, so the
+/// computer is offered as a function taking the Responses API's action vocabulary.
+pub struct Local {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+    messages: Vec<Value>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Response {
+    choices: Vec<Choice>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Choice {
+    message: Value,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ToolCall {
+    id: String,
+    function: Function,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Function {
+    name: String,
+    arguments: String,
+}
+
+fn computer_tool(setup: &Setup) -> Value {
+    json!({
+        "type": "function",
+        "function": {
+            "name": "computer",
+            "description": format!(
+                "Use the mouse and keyboard on a {} screen of {}x{} and see the result as a screenshot. Scroll amounts are in pixels.",
+                setup.environment, setup.display_width, setup.display_height
+            ),
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "type": {
+                        "type": "string",
+                        "enum": ["click", "double_click", "drag", "keypress", "move", "screenshot", "scroll", "type", "wait"],
+                    },
+                    "button": { "type": "string", "enum": ["left", "right", "middle"] },
+                    "x": { "type": "number" },
+                    "y": { "type": "number" },
+                    "path": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": { "x": { "type": "number" }, "y": { "type": "number" } },
+                            "required": ["x", "y"],
+                        },
+                    },
+                    "keys": { "type": "array", "items": { "type": "string" } },
+                    "scroll_x": { "type": "number" },
+                    "scroll_y": { "type": "number" },
+                    "text": { "type": "string" },
+                },
+                "required": ["type"],
+            },
+        },
+    })
+}
+
+impl Local {
+    pub fn new(base_url: String, api_key: Option<String>, model: String) -> Self {
+        Local {
+            client: reqwest::Client::new(),
+            base_url,
+            api_key,
+            model,
+            messages: Vec::new(),
+        }
+    }
+
+    fn tools(setup: &Setup) -> Vec<Value> {
+        let mut tools = vec![computer_tool(setup)];
+        tools.extend(setup.functions.iter().map(|function| {
+            json!({
+                "type": "function",
+                "function": {
+                    "name": function.name,
+                    "description": function.description,
+                    "parameters": function.parameters,
+                },
+            })
+        }));
+        tools
+    }
+
+    // Tool results must directly follow the call, so the screenshots go in a user message after them
+    fn push_input(&mut self, setup: &Setup, input: Vec<Input>) {
+        if self.messages.is_empty() {
+            self.messages
+                .push(json!({ "role": "system", "content": setup.system_prompt }));
+        }
+        let mut screenshots = Vec::new();
+        for input in input {
+            match input {
+                Input::Task(task) => self
+                    .messages
+                    .push(json!({ "role": "user", "content": task })),
+                Input::ComputerResult {
+                    call_id,
+                    screenshot,
+                    output,
+                    refusal,
+                    ..
+                } => {
+                    let content = refusal
+                        .or(output)
+                        .unwrap_or("Done, the screenshot follows.".to_string());
+                    self.messages.push(
+                        json!({ "role": "tool", "tool_call_id": call_id, "content": content }),
+                    );
+                    screenshots.push(json!({
+                        "type": "image_url",
+                        "image_url": { "url": screenshot },
+                    }));
+                }
+                Input::FunctionResult { call_id, output } => self
+                    .messages
+                    .push(json!({ "role": "tool", "tool_call_id": call_id, "content": output })),
+            }
+        }
+        if !screenshots.is_empty() {
+            self.messages
+                .push(json!({ "role": "user", "content": screenshots }));
+        }
+    }
+
+    // Replaces all but the latest screenshots in the history with a note
+    fn drop_old_screenshots(&mut self) {
+        let mut kept = 0;
+        for message in self.messages.iter_mut().rev() {
+            let Some(content) = message["content"].as_array_mut() else {
+                continue;
+            };
+            for part in content
+                .iter_mut()
+                .filter(|part| part["type"] == "image_url")
+            {
+                if kept < KEPT_SCREENSHOTS {
+                    kept += 1;
+                } else {
+                    *part = json!({ "type": "text", "text": "(older screenshot removed)" });
+                }
+            }
+        }
+    }
+}
+
+impl ModelProvider for Local {
+    fn respond<'a>(
+        &'a mut self,
+        setup: &'a Setup,
+        input: Vec<Input>,
+    ) -> ProviderFuture<'a, Vec<Item>> {
+        Box::pin(async move {
+            let sent = self.messages.len();
+            self.push_input(setup, input);
+            self.drop_old_screenshots();
+            let body = json!({
+                "model": self.model,
+                "messages": self.messages,
+                "tools": Self::tools(setup),
+            });
+            let mut request = self
+                .client
+                .post(format!("{}/chat/completions", self.base_url));
+            if let Some(key) = &self.api_key {
+                request = request.bearer_auth(key);
+            }
+            let response: Response = match post(request, &body).await {
+                Ok(response) => response,
+                Err(e) => {
+                    // leave the history as it was so the turn can be sent again
+                    self.messages.truncate(sent);
+                    return Err(e);
+                }
+            };
+            let message = response
+                .choices
+                .into_iter()
+                .next()
+                .ok_or("The model returned no choices")?
+                .message;
+            self.messages.push(message.clone());
+
+            let mut items = Vec::new();
+            if let Some(text) = message["content"].as_str().filter(|t| !t.is_empty()) {
+                items.push(Item::Message(text.to_string()));
+            }
+            if let Some(refusal) = message["refusal"].as_str() {
+                items.push(Item::Refusal(refusal.to_string()));
+            }
+            let calls: Vec<ToolCall> =
+                serde_json::from_value(message["tool_calls"].clone()).unwrap_or_default();
+            for call in calls {
+                // bad arguments go back to the model like any other mistake
+                let arguments = serde_json::from_str(&call.function.arguments)
+                    .map_err(|e| format!("Invalid arguments for {}: {}", call.function.name, e));
+                if call.function.name == "computer" {
+                    items.push(Item::Computer {
+                        call_id: call.id,
+                        call: arguments.and_then(translate::local),
+                        safety_checks: Vec::new(),
+                    });
+                } else {
+                    items.push(Item::Function {
+                        call_id: call.id,
+                        name: call.function.name,
+                        arguments,
+                    });
+                }
+            }
+            Ok(items)
+        })
+    }
+
+    fn reset(&mut self) {
+        self.messages.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use tauri::async_runtime::block_on;
+
+    use super::super::mock::{screenshot, setup, Server};
+    use super::super::{ComputerCall, Input, Item, ModelProvider};
+    use super::{Local, KEPT_SCREENSHOTS};
+    use crate::commands::action::Action;
+
+    fn provider(server: &Server, api_key: Option<&str>) -> Local {
+        Local::new(
+            server.base_url.clone(),
+            api_key.map(str::to_string),
+            "qwen2.5vl".to_string(),
+        )
+    }
+
+    fn reply(message: Value) -> (u16, Value) {
+        (200, json!({ "choices": [{ "message": message }] }))
+    }
+
+    fn tool_call(id: &str, name: &str, arguments: &str) -> Value {
+        json!({
+            "id": id,
+            "type": "function",
+            "function": { "name": name, "arguments": arguments },
+        })
+    }
+
+    fn screenshot_call(id: &str) -> (u16, Value) {
+        reply(json!({
+            "role": "assistant",
+            "content": "",
+            "tool_calls": [tool_call(id, "computer", "{\"type\": \"screenshot\"}")],
+        }))
+    }
+
+    fn result(call_id: &str) -> Input {
+        Input::ComputerResult {
+            call_id: call_id.to_string(),
+            screenshot: screenshot(),
+            output: None,
+            refusal: None,
+            safety_checks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn pairs_tool_results_with_their_calls() {
+        let server = Server::start(vec![
+            reply(json!({
+                "role": "assistant",
+                "content": "Clicking it",
+                "tool_calls": [
+                    tool_call("call_1", "computer", "{\"type\": \"click\", \"x\": 10, \"y\": 20}"),
+                    tool_call("call_2", "computer", "{\"action\": \"key\", \"text\": \"ctrl+s\"}"),
+                    tool_call("call_3", "click_mark", "{\"mark\": 3}"),
+                ],
+            })),
+            reply(json!({ "role": "assistant", "content": "Done" })),
+        ]);
+        let setup = setup();
+        let mut provider = provider(&server, Some("local-key"));
+
+        let items =
+            block_on(provider.respond(&setup, vec![Input::Task("Save it".to_string())])).unwrap();
+        assert_eq!(
+            items,
+            vec![
+                Item::Message("Clicking it".to_string()),
+                Item::Computer {
+                    call_id: "call_1".to_string(),
+                    call: Ok(ComputerCall::Perform(Action::Click {
+                        button: "left".to_string(),
+                        x: 10.0,
+                        y: 20.0,
+                    })),
+                    safety_checks: Vec::new(),
+                },
+                Item::Computer {
+                    call_id: "call_2".to_string(),
+                    call: Ok(ComputerCall::Perform(Action::Keypress {
+                        keys: vec!["ctrl".to_string(), "s".to_string()],
+                    })),
+                    safety_checks: Vec::new(),
+                },
+                Item::Function {
+                    call_id: "call_3".to_string(),
+                    name: "click_mark".to_string(),
+                    arguments: Ok(json!({ "mark": 3 })),
+                },
+            ]
+        );
+
+        let items = block_on(provider.respond(
+            &setup,
+            vec![
+                result("call_1"),
+                Input::ComputerResult {
+                    call_id: "call_2".to_string(),
+                    screenshot: screenshot(),
+                    output: None,
+                    refusal: Some("Not allowed".to_string()),
+                    safety_checks: Vec::new(),
+                },
+                Input::FunctionResult {
+                    call_id: "call_3".to_string(),
+                    output: "{}".to_string(),
+                },
+            ],
+        ))
+        .unwrap();
+        assert_eq!(items, vec![Item::Message("Done".to_string())]);
+
+        let requests = server.requests();
+        for request in requests.iter() {
+            assert_eq!(request.path, "/chat/completions");
+            assert_eq!(request.headers["authorization"], "Bearer local-key");
+            assert_eq!(request.body["model"], "qwen2.5vl");
+            assert_eq!(request.body["tools"][0]["function"]["name"], "computer");
+            assert_eq!(request.body["tools"][1]["function"]["name"], "click_mark");
+        }
+        let messages = requests[1].body["messages"].as_array().unwrap();
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[1]["content"], "Save it");
+        assert_eq!(messages[2]["role"], "assistant");
+        assert_eq!(messages[2]["tool_calls"][0]["id"], "call_1");
+        // the tool results follow the call directly, the screenshots come after them
+        let paired: Vec<(&Value, &Value)> = messages[3..6]
+            .iter()
+            .map(|message| (&message["tool_call_id"], &message["content"]))
+            .collect();
+        assert_eq!(
+            paired,
+            vec![
+                (&json!("call_1"), &json!("Done, the screenshot follows.")),
+                (&json!("call_2"), &json!("Not allowed")),
+                (&json!("call_3"), &json!("{}")),
+            ]
+        );
+        assert!(messages[3..6]
+            .iter()
+            .all(|message| message["role"] == "tool"));
+        assert_eq!(messages[6]["role"], "user");
+        assert_eq!(
+            messages[6]["content"],
+            json!([
+                { "type": "image_url", "image_url": { "url": screenshot() } },
+                { "type": "image_url", "image_url": { "url": screenshot() } },
+            ])
+        );
+        assert_eq!(messages.len(), 7);
+    }
+
+    #[test]
+    fn sends_no_key_when_there_is_none() {
+        let server = Server::start(vec![reply(json!({ "role": "assistant", "content": "Hi" }))]);
+        let setup = setup();
+        let mut provider = provider(&server, None);
+
+        block_on(provider.respond(&setup, vec![Input::Task("Hello".to_string())])).unwrap();
+        assert!(!server.requests()[0].headers.contains_key("authorization"));
+    }
+
+    #[test]
+    fn keeps_bad_arguments_for_the_model() {
+        let server = Server::start(vec![reply(json!({
+            "role": "assistant",
+            "content": null,
+            "tool_calls": [
+                tool_call("call_1", "computer", "{\"type\": \"click\", \"x\": "),
+                tool_call("call_2", "click_mark", "mark=3"),
+                tool_call("call_3", "computer", "{\"type\": \"fly\"}"),
+            ],
+        }))]);
+        let setup = setup();
+        let mut provider = provider(&server, None);
+
+        let items =
+            block_on(provider.respond(&setup, vec![Input::Task("Click".to_string())])).unwrap();
+        assert_eq!(items.len(), 3);
+        assert!(matches!(
+            &items[0],
+            Item::Computer { call_id, call: Err(_), .. } if call_id == "call_1"
+        ));
+        assert!(matches!(
+            &items[1],
+            Item::Function { call_id, arguments: Err(_), .. } if call_id == "call_2"
+        ));
+        assert!(matches!(
+            &items[2],
+            Item::Computer { call_id, call: Err(_), .. } if call_id == "call_3"
+        ));
+    }
+
+    #[test]
+    fn drops_old_screenshots() {
+        let turns = KEPT_SCREENSHOTS + 2;
+        let server = Server::start(
+            (0..=turns)
+                .map(|turn| screenshot_call(&format!("call_{}", turn)))
+                .collect(),
+        );
+        let setup = setup();
+        let mut provider = provider(&server, None);
+
+        block_on(provider.respond(&setup, vec![Input::Task("Look".to_string())])).unwrap();
+        for turn in 0..turns {
+            let items = block_on(provider.respond(&setup, vec![result(&format!("call_{}", turn))]))
+                .unwrap();
+            assert!(matches!(
+                &items[..],
+                [Item::Computer {
+                    call: Ok(ComputerCall::Screenshot),
+                    ..
+                }]
+            ));
+        }
+
+        let requests = server.requests();
+        let parts: Vec<Value> = requests.last().unwrap().body["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|message| message["role"] == "user")
+            .filter_map(|message| message["content"].as_array())
+            .map(|content| content[0].clone())
+            .collect();
+        let kinds: Vec<&str> = parts
+            .iter()
+            .map(|part| part["type"].as_str().unwrap())
+            .collect();
+        let mut expected = vec!["text"; turns - KEPT_SCREENSHOTS];
+        expected.resize(turns, "image_url");
+        assert_eq!(kinds, expected);
+        assert_eq!(parts[0]["text"], "(older screenshot removed)");
+    }
+
+    #[test]
+    fn forgets_a_failed_turn() {
+        let server = Server::start(vec![
+            screenshot_call("call_1"),
+            (500, json!({ "error": "model not loaded" })),
+            reply(json!({ "role": "assistant", "content": "Done" })),
+        ]);
+        let setup = setup();
+        let mut provider = provider(&server, None);
+
+        block_on(provider.respond(&setup, vec![Input::Task("Look".to_string())])).unwrap();
+        let error = block_on(provider.respond(&setup, vec![result("call_1")])).unwrap_err();
+        assert!(error.contains("500"), "{}", error);
+        assert!(error.contains("model not loaded"), "{}", error);
+        let items = block_on(provider.respond(&setup, vec![result("call_1")])).unwrap();
+        assert_eq!(items, vec![Item::Message("Done".to_string())]);
+
+        let requests = server.requests();
+        // system, task, call, tool result and screenshots, once
+        assert_eq!(requests[1].body["messages"].as_array().unwrap().len(), 5);
+        assert_eq!(requests[2].body["messages"], requests[1].body["messages"]);
+    }
+}
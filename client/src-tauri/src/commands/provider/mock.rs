@@ -0,0 +1,103 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::{FunctionSpec, Setup};
+
+pub struct Request {
+    pub path: String,
+    /// Header names in lower case
+    pub headers: HashMap<String, String>,
+    pub body: Value,
+}
+
+/// A stand-in for a model API that answers each request with the next canned reply and keeps
+/// what it was sent
+pub struct Server {
+    pub base_url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl Server {
+    /// Serves one reply per request, a status and a JSON body, in order
+    pub fn start(replies: Vec<(u16, Value)>) -> Server {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        thread::spawn(move || {
+            for (status, reply) in replies {
+                let Ok((stream, _)) = listener.accept() else {
+                    return;
+                };
+                let mut reader = BufReader::new(stream);
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let path = line.split_whitespace().nth(1).unwrap_or_default();
+                let path = path.strip_prefix("/v1").unwrap_or(path).to_string();
+                let mut headers = HashMap::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let Some((name, value)) = line.trim_end().split_once(':') else {
+                        break;
+                    };
+                    headers.insert(name.to_lowercase(), value.trim().to_string());
+                }
+                let length = headers
+                    .get("content-length")
+                    .and_then(|length| length.parse().ok())
+                    .unwrap_or(0);
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                received.lock().unwrap().push(Request {
+                    path,
+                    headers,
+                    body: serde_json::from_slice(&body).unwrap_or_default(),
+                });
+
+                let reply = reply.to_string();
+                let mut stream = reader.into_inner();
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    reply.len(),
+                    reply
+                )
+                .unwrap();
+            }
+        });
+        Server { base_url, requests }
+    }
+
+    /// What the server was sent so far, in order
+    pub fn requests(&self) -> std::sync::MutexGuard<'_, Vec<Request>> {
+        self.requests.lock().unwrap()
+    }
+}
+
+pub fn setup() -> Setup {
+    Setup {
+        system_prompt: "You use a computer.".to_string(),
+        display_width: 1280,
+        display_height: 800,
+        environment: "mac",
+        functions: vec![FunctionSpec {
+            name: "click_mark",
+            description: "Clicks a numbered mark",
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": { "mark": { "type": "number" } },
+                "required": ["mark"],
+            }),
+        }],
+    }
+}
+
+pub fn screenshot() -> String {
+    "data:image/png;base64,iVBORw0KGgo=".to_string()
+}
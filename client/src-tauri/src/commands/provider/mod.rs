@@ -0,0 +1,452 @@
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tauri::{command, State};
+
+use super::action::Action;
+use crate::config;
+use crate::credentials::{self, Store};
+
+mod anthropic;
+mod local;
+#[cfg(test)]
+mod mock;
+mod openai;
+mod translate;
+
+const CONFIG_NAME: &str = "provider";
+
+/// Which computer-use API the agent talks to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderKind {
+    /// The OpenAI Responses API with its computer tool
+    #[default]
+    #[serde(rename = "openai")]
+    OpenAi,
+    /// The Anthropic Messages API with the computer-use tool
+    Anthropic,
+    /// A local server with an OpenAI-compatible chat completions API, like Ollama or LM Studio
+    Local,
+}
+
+impl ProviderKind {
+    const ALL: [ProviderKind; 3] = [
+        ProviderKind::OpenAi,
+        ProviderKind::Anthropic,
+        ProviderKind::Local,
+    ];
+
+    fn default_base_url(self) -> &'static str {
+        match self {
+            ProviderKind::OpenAi => openai::BASE_URL,
+            ProviderKind::Anthropic => anthropic::BASE_URL,
+            ProviderKind::Local => local::BASE_URL,
+        }
+    }
+
+    // Whether the base URL is where the provider's own key goes. A hosted provider's key only
+    // goes to its public API, a local server's wherever the server is.
+    fn own_address(self, base_url: Option<&str>) -> bool {
+        match base_url.map(|url| url.trim_end_matches('/')) {
+            Some(url) => self == ProviderKind::Local || url == self.default_base_url(),
+            None => true,
+        }
+    }
+
+    // The name its API key for a base URL is stored under. A key for any other address than its
+    // own is stored apart, so changing the URL never sends a key somewhere it wasn't meant for.
+    fn credential(self, base_url: Option<&str>) -> String {
+        let name = match self {
+            ProviderKind::OpenAi => "openai_api_key",
+            ProviderKind::Anthropic => "anthropic_api_key",
+            ProviderKind::Local => "local_api_key",
+        };
+        match base_url {
+            Some(url) if !self.own_address(base_url) => {
+                format!("{}:{}", name, url.trim_end_matches('/'))
+            }
+            _ => name.to_string(),
+        }
+    }
+
+    // Where the API key is read from when none is stored, for development. Like the stored key,
+    // it only goes to the provider's own address.
+    fn variable(self) -> &'static str {
+        match self {
+            ProviderKind::OpenAi => "OPENAI_API_KEY",
+            ProviderKind::Anthropic => "ANTHROPIC_API_KEY",
+            ProviderKind::Local => "LOCAL_MODEL_API_KEY",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ProviderConfig {
+    pub kind: ProviderKind,
+    /// The provider's default model when unset, a local server needs one
+    pub model: Option<String>,
+    /// The provider's public API when unset, or a local server or mock
+    pub base_url: Option<String>,
+}
+
+/// The model settings, stored in `~/.paimon/provider.json`. API keys are kept apart, in the
+/// keyring or an encrypted file, and never sent to the webview.
+pub struct Providers {
+    config: Mutex<ProviderConfig>,
+    revision: AtomicU64,
+}
+
+impl Default for Providers {
+    fn default() -> Self {
+        Providers {
+            config: Mutex::new(config::load(CONFIG_NAME)),
+            revision: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Providers {
+    pub fn config(&self) -> ProviderConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    // The base URL a provider's key is used with, the configured one is for the selected provider
+    fn base_url(&self, kind: ProviderKind) -> Option<String> {
+        let config = self.config.lock().unwrap();
+        config.base_url.clone().filter(|_| config.kind == kind)
+    }
+
+    /// Changes with the config or any key, so a provider built before can tell it is out of date
+    pub fn revision(&self) -> u64 {
+        self.revision.load(Ordering::Relaxed)
+    }
+
+    fn changed(&self) {
+        self.revision.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Where a provider's API key comes from, without the key itself
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ApiKeyStatus {
+    pub provider: ProviderKind,
+    /// "keyring", "file" or "environment", null when there is no key. "file" is only obfuscated,
+    /// its key lies beside it, so it is no protection against anything that can read `~/.paimon`.
+    pub source: Option<&'static str>,
+}
+
+/// What a provider is told about the machine and the tools it has besides the computer
+#[derive(Debug, Clone)]
+pub struct Setup {
+    pub system_prompt: String,
+    /// The screen in action coordinates
+    pub display_width: u32,
+    pub display_height: u32,
+    /// "mac", "windows" or "ubuntu"
+    pub environment: &'static str,
+    pub functions: Vec<FunctionSpec>,
+}
+
+/// A function tool, its parameters as a JSON schema
+#[derive(Debug, Clone)]
+pub struct FunctionSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub parameters: Value,
+}
+
+/// What the agent sends the model
+#[derive(Debug, Clone)]
+pub enum Input {
+    /// A task from the user
+    Task(String),
+    /// The screen after a computer call, and why its action was refused if it was
+    ComputerResult {
+        call_id: String,
+        /// A data URL
+        screenshot: String,
+        /// What the call reported besides the screen, like the pointer position
+        output: Option<String>,
+        refusal: Option<String>,
+        safety_checks: Vec<Value>,
+    },
+    FunctionResult {
+        call_id: String,
+        output: String,
+    },
+}
+
+/// A computer call in terms of the action set
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComputerCall {
+    Perform(Action),
+    /// An action wherever the pointer is when it runs
+    AtPointer(PointerAction),
+    /// Only a new screenshot
+    Screenshot,
+    /// Let the screen settle before the screenshot
+    Wait,
+    /// Report where the pointer is
+    CursorPosition,
+}
+
+/// An action without a position, for models that act where the pointer already is
+#[derive(Debug, Clone, PartialEq)]
+pub enum PointerAction {
+    Click { button: String },
+    DoubleClick,
+    TripleClick,
+    MouseDown { button: String },
+    MouseUp { button: String },
+    Scroll { scroll_x: f64, scroll_y: f64 },
+}
+
+impl PointerAction {
+    pub fn at(self, x: f64, y: f64) -> Action {
+        match self {
+            PointerAction::Click { button } => Action::Click { button, x, y },
+            PointerAction::DoubleClick => Action::DoubleClick { x, y },
+            PointerAction::TripleClick => Action::TripleClick { x, y },
+            PointerAction::MouseDown { button } => Action::MouseDown { button, x, y },
+            PointerAction::MouseUp { button } => Action::MouseUp { button, x, y },
+            PointerAction::Scroll { scroll_x, scroll_y } => Action::Scroll {
+                x,
+                y,
+                scroll_x,
+                scroll_y,
+            },
+        }
+    }
+}
+
+/// One thing the model said or asked for in a turn
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Message(String),
+    /// The model declined the task
+    Refusal(String),
+    /// A computer call, or why it couldn't be understood
+    Computer {
+        call_id: String,
+        call: Result<ComputerCall, String>,
+        safety_checks: Vec<Value>,
+    },
+    /// A function call, or why its arguments couldn't be understood
+    Function {
+        call_id: String,
+        name: String,
+        arguments: Result<Value, String>,
+    },
+}
+
+pub type ProviderFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, String>> + Send + 'a>>;
+
+/// A computer-use API. Each one keeps its own conversation and turns its tool calls into the
+/// action set, so the agent loop doesn't depend on any of them.
+pub trait ModelProvider: Send {
+    /// Sends a task, or the results of the last turn's calls, and returns the next turn.
+    /// A turn without calls means the model is done.
+    fn respond<'a>(
+        &'a mut self,
+        setup: &'a Setup,
+        input: Vec<Input>,
+    ) -> ProviderFuture<'a, Vec<Item>>;
+
+    /// Forgets the conversation, the next task starts a new one
+    fn reset(&mut self);
+}
+
+// The key stored for the base URL, or the environment variable when none is stored
+fn api_key(
+    kind: ProviderKind,
+    base_url: Option<&str>,
+) -> Result<Option<(String, &'static str)>, String> {
+    if let Some((key, store)) = credentials::get(&kind.credential(base_url))? {
+        let source = match store {
+            Store::Keyring => "keyring",
+            Store::File => "file",
+        };
+        return Ok(Some((key, source)));
+    }
+    Ok(environment_key(kind, base_url).map(|key| (key, "environment")))
+}
+
+fn environment_key(kind: ProviderKind, base_url: Option<&str>) -> Option<String> {
+    if !kind.own_address(base_url) {
+        return None;
+    }
+    std::env::var(kind.variable())
+        .ok()
+        .filter(|key| !key.is_empty())
+}
+
+fn required_api_key(kind: ProviderKind, base_url: Option<&str>) -> Result<String, String> {
+    let (name, provider) = match kind {
+        ProviderKind::OpenAi => ("OpenAI", "openai"),
+        ProviderKind::Anthropic => ("Anthropic", "anthropic"),
+        ProviderKind::Local => ("the local server", "local"),
+    };
+    let at = base_url
+        .map(|url| format!(" at {}", url))
+        .unwrap_or_default();
+    api_key(kind, base_url)?.map(|(key, _)| key).ok_or(format!(
+        "No API key for {}{}, set one with /key {} <key>",
+        name, at, provider
+    ))
+}
+
+/// Creates the provider a config describes
+pub fn build(config: &ProviderConfig) -> Result<Box<dyn ModelProvider>, String> {
+    let base_url = |default: &str| {
+        config
+            .base_url
+            .as_deref()
+            .unwrap_or(default)
+            .trim_end_matches('/')
+            .to_string()
+    };
+    Ok(match config.kind {
+        ProviderKind::OpenAi => Box::new(openai::OpenAiResponses::new(
+            base_url(openai::BASE_URL),
+            required_api_key(ProviderKind::OpenAi, config.base_url.as_deref())?,
+            config.model.clone().unwrap_or(openai::MODEL.to_string()),
+        )),
+        ProviderKind::Anthropic => Box::new(anthropic::Anthropic::new(
+            base_url(anthropic::BASE_URL),
+            required_api_key(ProviderKind::Anthropic, config.base_url.as_deref())?,
+            config.model.clone().unwrap_or(anthropic::MODEL.to_string()),
+        )),
+        ProviderKind::Local => Box::new(local::Local::new(
+            base_url(local::BASE_URL),
+            api_key(ProviderKind::Local, config.base_url.as_deref())?.map(|(key, _)| key),
+            config
+                .model
+                .clone()
+                .ok_or("Set the model the local server should use")?,
+        )),
+    })
+}
+
+// Posts a JSON body and reads the JSON reply, with the body of any error in the message
+async fn post<T: DeserializeOwned>(
+    request: reqwest::RequestBuilder,
+    body: &Value,
+) -> Result<T, String> {
+    let response = request
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to reach the model: {}", e))?;
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        return Err(format!(
+            "The model request failed with {}: {}",
+            status, text
+        ));
+    }
+    response
+        .json()
+        .await
+        .map_err(|e| format!("Invalid model response: {}", e))
+}
+
+#[command]
+pub fn get_provider_config(providers: State<'_, Providers>) -> ProviderConfig {
+    providers.config()
+}
+
+/// Switches the model, the next task starts a new conversation with it
+#[command]
+pub fn set_provider_config(
+    providers: State<'_, Providers>,
+    config: ProviderConfig,
+) -> Result<(), String> {
+    config::save(CONFIG_NAME, &config)?;
+    *providers.config.lock().unwrap() = config;
+    providers.changed();
+    Ok(())
+}
+
+/// Stores a provider's API key in the keyring, or encrypted in `~/.paimon` when there is none.
+/// The key is for the configured base URL, and only sent there. An empty or missing key removes
+/// it. Keys can be set but never read back.
+#[command]
+pub fn set_api_key(
+    providers: State<'_, Providers>,
+    provider: ProviderKind,
+    key: Option<String>,
+) -> Result<(), String> {
+    let credential = provider.credential(providers.base_url(provider).as_deref());
+    match key.as_deref().map(str::trim).filter(|key| !key.is_empty()) {
+        Some(key) => {
+            credentials::set(&credential, key)?;
+        }
+        None => credentials::delete(&credential)?,
+    }
+    providers.changed();
+    Ok(())
+}
+
+/// Which providers have a key for their base URL and where it comes from
+#[command]
+pub fn get_api_keys(providers: State<'_, Providers>) -> Result<Vec<ApiKeyStatus>, String> {
+    ProviderKind::ALL
+        .iter()
+        .map(|&provider| {
+            let base_url = providers.base_url(provider);
+            Ok(ApiKeyStatus {
+                provider,
+                source: api_key(provider, base_url.as_deref())?.map(|(_, source)| source),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{environment_key, ProviderKind};
+
+    #[test]
+    fn keys_only_go_to_their_own_address() {
+        for kind in [ProviderKind::OpenAi, ProviderKind::Anthropic] {
+            let own = kind.default_base_url();
+            assert!(kind.own_address(None));
+            assert!(kind.own_address(Some(own)));
+            assert!(kind.own_address(Some(&format!("{}/", own))));
+            assert!(!kind.own_address(Some("http://localhost:8080/v1")));
+            assert_eq!(kind.credential(None), kind.credential(Some(own)));
+            assert_ne!(
+                kind.credential(None),
+                kind.credential(Some("http://localhost:8080/v1"))
+            );
+        }
+        assert!(ProviderKind::Local.own_address(Some("http://192.168.1.2:1234/v1")));
+        assert_eq!(
+            ProviderKind::Local.credential(Some("http://192.168.1.2:1234/v1")),
+            ProviderKind::Local.credential(None)
+        );
+    }
+
+    #[test]
+    fn the_environment_key_stays_with_the_provider() {
+        // only this test reads ANTHROPIC_API_KEY
+        std::env::set_var("ANTHROPIC_API_KEY", "sk-ant-environment");
+        let kind = ProviderKind::Anthropic;
+        assert_eq!(
+            environment_key(kind, None).as_deref(),
+            Some("sk-ant-environment")
+        );
+        assert_eq!(
+            environment_key(kind, Some(kind.default_base_url())).as_deref(),
+            Some("sk-ant-environment")
+        );
+        assert_eq!(environment_key(kind, Some("https://example.com/v1")), None);
+        std::env::remove_var("ANTHROPIC_API_KEY");
+    }
+}
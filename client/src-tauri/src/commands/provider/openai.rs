@@ -0,0 +1,393 @@
+use serde_json::{json, Value};
+
+use super::{post, translate, Input, Item, ModelProvider, ProviderFuture, Setup};
+
+pub const BASE_URL: &str = "https://api.openai.com/v1";
+pub const MODEL: &str = "computer-use-preview";
+
+/// The Responses API, which keeps the conversation on the server
+pub struct OpenAiResponses {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: String,
+    model: String,
+    previous_response_id: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Response {
+    id: String,
+    output: Vec<OutputItem>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OutputItem {
+    Message {
+        content: Vec<Content>,
+    },
+    ComputerCall {
+        call_id: String,
+        action: Value,
+        #[serde(default)]
+        pending_safety_checks: Vec<Value>,
+    },
+    FunctionCall {
+        call_id: String,
+        name: String,
+        arguments: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Content {
+    OutputText {
+        text: String,
+    },
+    Refusal {
+        refusal: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+impl OpenAiResponses {
+    pub fn new(base_url: String, api_key: String, model: String) -> Self {
+        OpenAiResponses {
+            client: reqwest::Client::new(),
+            base_url,
+            api_key,
+            model,
+            previous_response_id: None,
+        }
+    }
+
+    fn tools(setup: &Setup) -> Vec<Value> {
+        let mut tools = vec![json!({
+            "type": "computer-preview",
+            "display_width": setup.display_width,
+            "display_height": setup.display_height,
+            "environment": setup.environment,
+        })];
+        tools.extend(setup.functions.iter().map(|function| {
+            json!({
+                "type": "function",
+                "name": function.name,
+                "description": function.description,
+                "parameters": function.parameters,
+                "strict": true,
+            })
+        }));
+        tools
+    }
+
+    fn input(&self, setup: &Setup, input: Vec<Input>) -> Vec<Value> {
+        let mut items = Vec::new();
+        for input in input {
+            match input {
+                Input::Task(task) if self.previous_response_id.is_none() => {
+                    items.push(json!({ "role": "system", "content": setup.system_prompt }));
+                    items.push(json!({ "role": "user", "content": task }));
+                    items.push(
+                        json!({ "role": "assistant", "content": "I'll help you with that task." }),
+                    );
+                }
+                Input::Task(task) => items.push(json!({ "role": "user", "content": task })),
+                Input::ComputerResult {
+                    call_id,
+                    screenshot,
+                    output,
+                    refusal,
+                    safety_checks,
+                } => {
+                    items.push(json!({
+                        "type": "computer_call_output",
+                        "call_id": call_id,
+                        "acknowledged_safety_checks": safety_checks,
+                        "output": { "type": "computer_screenshot", "image_url": screenshot },
+                    }));
+                    for text in output.into_iter().chain(refusal) {
+                        items.push(json!({ "role": "system", "content": text }));
+                    }
+                }
+                Input::FunctionResult { call_id, output } => items.push(json!({
+                    "type": "function_call_output",
+                    "call_id": call_id,
+                    "output": output,
+                })),
+            }
+        }
+        items
+    }
+}
+
+impl ModelProvider for OpenAiResponses {
+    fn respond<'a>(
+        &'a mut self,
+        setup: &'a Setup,
+        input: Vec<Input>,
+    ) -> ProviderFuture<'a, Vec<Item>> {
+        Box::pin(async move {
+            let mut body = json!({
+                "model": self.model,
+                "input": self.input(setup, input),
+                "tools": Self::tools(setup),
+                "truncation": "auto",
+            });
+            if let Some(id) = &self.previous_response_id {
+                body["previous_response_id"] = json!(id);
+            }
+            let request = self
+                .client
+                .post(format!("{}/responses", self.base_url))
+                .bearer_auth(&self.api_key);
+            let response: Response = post(request, &body).await?;
+            self.previous_response_id = Some(response.id);
+
+            let mut items = Vec::new();
+            for item in response.output {
+                match item {
+                    OutputItem::Message { content } => {
+                        items.extend(content.into_iter().filter_map(|content| match content {
+                            Content::OutputText { text } => Some(Item::Message(text)),
+                            Content::Refusal { refusal } => Some(Item::Refusal(refusal)),
+                            Content::Other => None,
+                        }))
+                    }
+                    OutputItem::ComputerCall {
+                        call_id,
+                        action,
+                        pending_safety_checks,
+                    } => items.push(Item::Computer {
+                        call_id,
+                        call: translate::openai(action),
+                        safety_checks: pending_safety_checks,
+                    }),
+                    OutputItem::FunctionCall {
+                        call_id,
+                        name,
+                        arguments,
+                    } => items.push(Item::Function {
+                        arguments: serde_json::from_str(&arguments)
+                            .map_err(|e| format!("Invalid arguments for {}: {}", name, e)),
+                        call_id,
+                        name,
+                    }),
+                    OutputItem::Other => {}
+                }
+            }
+            Ok(items)
+        })
+    }
+
+    fn reset(&mut self) {
+        self.previous_response_id = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tauri::async_runtime::block_on;
+
+    use super::super::mock::{screenshot, setup, Server};
+    use super::super::{ComputerCall, Input, Item, ModelProvider};
+    use super::OpenAiResponses;
+    use crate::commands::action::Action;
+
+    fn provider(server: &Server) -> OpenAiResponses {
+        OpenAiResponses::new(
+            server.base_url.clone(),
+            "sk-test".to_string(),
+            "computer-use-preview".to_string(),
+        )
+    }
+
+    #[test]
+    fn continues_from_the_previous_response() {
+        let server = Server::start(vec![
+            (
+                200,
+                json!({
+                    "id": "resp_1",
+                    "output": [
+                        { "type": "reasoning", "summary": [] },
+                        {
+                            "type": "computer_call",
+                            "call_id": "call_1",
+                            "action": { "type": "click", "button": "left", "x": 10, "y": 20 },
+                            "pending_safety_checks": [{ "id": "check_1", "code": "malicious_instructions" }],
+                        },
+                    ],
+                }),
+            ),
+            (
+                200,
+                json!({
+                    "id": "resp_2",
+                    "output": [{
+                        "type": "message",
+                        "content": [
+                            { "type": "output_text", "text": "Done" },
+                            { "type": "refusal", "refusal": "Not that" },
+                        ],
+                    }],
+                }),
+            ),
+        ]);
+        let setup = setup();
+        let mut provider = provider(&server);
+
+        let items =
+            block_on(provider.respond(&setup, vec![Input::Task("Open it".to_string())])).unwrap();
+        assert_eq!(
+            items,
+            vec![Item::Computer {
+                call_id: "call_1".to_string(),
+                call: Ok(ComputerCall::Perform(Action::Click {
+                    button: "left".to_string(),
+                    x: 10.0,
+                    y: 20.0,
+                })),
+                safety_checks: vec![json!({ "id": "check_1", "code": "malicious_instructions" })],
+            }]
+        );
+
+        let items = block_on(provider.respond(
+            &setup,
+            vec![Input::ComputerResult {
+                call_id: "call_1".to_string(),
+                screenshot: screenshot(),
+                output: None,
+                refusal: None,
+                safety_checks: vec![json!({ "id": "check_1" })],
+            }],
+        ))
+        .unwrap();
+        assert_eq!(
+            items,
+            vec![
+                Item::Message("Done".to_string()),
+                Item::Refusal("Not that".to_string()),
+            ]
+        );
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        for request in requests.iter() {
+            assert_eq!(request.path, "/responses");
+            assert_eq!(request.headers["authorization"], "Bearer sk-test");
+            assert_eq!(request.body["tools"][0]["type"], "computer-preview");
+            assert_eq!(request.body["tools"][1]["name"], "click_mark");
+        }
+        let first = &requests[0].body;
+        assert!(first.get("previous_response_id").is_none());
+        assert_eq!(first["input"][0]["role"], "system");
+        assert_eq!(first["input"][1]["content"], "Open it");
+        let second = &requests[1].body;
+        assert_eq!(second["previous_response_id"], "resp_1");
+        assert_eq!(
+            second["input"],
+            json!([{
+                "type": "computer_call_output",
+                "call_id": "call_1",
+                "acknowledged_safety_checks": [{ "id": "check_1" }],
+                "output": { "type": "computer_screenshot", "image_url": screenshot() },
+            }])
+        );
+    }
+
+    #[test]
+    fn parses_function_calls_and_keeps_bad_arguments_for_the_model() {
+        let server = Server::start(vec![(
+            200,
+            json!({
+                "id": "resp_1",
+                "output": [
+                    {
+                        "type": "function_call",
+                        "call_id": "call_1",
+                        "name": "click_mark",
+                        "arguments": "{\"mark\": 3}",
+                    },
+                    {
+                        "type": "function_call",
+                        "call_id": "call_2",
+                        "name": "click_mark",
+                        "arguments": "{\"mark\": ",
+                    },
+                    {
+                        "type": "computer_call",
+                        "call_id": "call_3",
+                        "action": { "type": "fly" },
+                    },
+                ],
+            }),
+        )]);
+        let setup = setup();
+        let mut provider = provider(&server);
+
+        let items =
+            block_on(provider.respond(&setup, vec![Input::Task("Click".to_string())])).unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(
+            items[0],
+            Item::Function {
+                call_id: "call_1".to_string(),
+                name: "click_mark".to_string(),
+                arguments: Ok(json!({ "mark": 3 })),
+            }
+        );
+        assert!(matches!(
+            &items[1],
+            Item::Function { call_id, arguments: Err(_), .. } if call_id == "call_2"
+        ));
+        assert!(matches!(
+            &items[2],
+            Item::Computer { call_id, call: Err(_), .. } if call_id == "call_3"
+        ));
+    }
+
+    #[test]
+    fn keeps_the_previous_response_after_an_error() {
+        let server = Server::start(vec![
+            (200, json!({ "id": "resp_1", "output": [] })),
+            (500, json!({ "error": { "message": "overloaded" } })),
+            (200, json!({ "id": "resp_2", "output": [] })),
+        ]);
+        let setup = setup();
+        let mut provider = provider(&server);
+
+        block_on(provider.respond(&setup, vec![Input::Task("One".to_string())])).unwrap();
+        let error =
+            block_on(provider.respond(&setup, vec![Input::Task("Two".to_string())])).unwrap_err();
+        assert!(error.contains("500"), "{}", error);
+        assert!(error.contains("overloaded"), "{}", error);
+        block_on(provider.respond(&setup, vec![Input::Task("Two".to_string())])).unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[1].body["previous_response_id"], "resp_1");
+        assert_eq!(requests[2].body["previous_response_id"], "resp_1");
+    }
+
+    #[test]
+    fn starts_over_after_a_reset() {
+        let server = Server::start(vec![
+            (200, json!({ "id": "resp_1", "output": [] })),
+            (200, json!({ "id": "resp_2", "output": [] })),
+        ]);
+        let setup = setup();
+        let mut provider = provider(&server);
+
+        block_on(provider.respond(&setup, vec![Input::Task("One".to_string())])).unwrap();
+        provider.reset();
+        block_on(provider.respond(&setup, vec![Input::Task("Two".to_string())])).unwrap();
+
+        let second = &server.requests()[1].body;
+        assert!(second.get("previous_response_id").is_none());
+        assert_eq!(second["input"][0]["role"], "system");
+    }
+}
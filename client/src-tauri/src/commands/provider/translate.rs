@@ -0,0 +1,610 @@
+use serde_json::Value;
+
+use super::{ComputerCall, PointerAction};
+use crate::commands::action::Action;
+
+// One wheel tick scrolls about this many pixels
+const PIXELS_PER_TICK: f64 = 25.0;
+
+/// What a provider's scroll amounts count
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScrollUnit {
+    Pixels,
+    /// Wheel ticks, what scroll actions take
+    Ticks,
+}
+
+pub fn scroll_ticks(amount: f64, unit: ScrollUnit) -> f64 {
+    match unit {
+        ScrollUnit::Pixels => amount / PIXELS_PER_TICK,
+        ScrollUnit::Ticks => amount,
+    }
+}
+
+/// The name `keypress` knows a key by, from xdotool keysyms like `Page_Down` or `super` and
+/// browser names like `ArrowLeft`. Anything else, like single characters, is kept as it is.
+pub fn key_name(key: &str) -> String {
+    let name = match key.to_lowercase().as_str() {
+        "super" | "super_l" | "super_r" | "meta" | "meta_l" | "meta_r" | "cmd" | "command"
+        | "win" | "windows" => "meta",
+        "ctrl" | "control" | "control_l" | "control_r" => "ctrl",
+        "alt" | "alt_l" | "alt_r" => "alt",
+        "shift" | "shift_l" | "shift_r" => "shift",
+        "return" | "enter" | "kp_enter" => "enter",
+        "backspace" => "backspace",
+        "delete" | "kp_delete" => "delete",
+        "escape" | "esc" => "escape",
+        "tab" | "iso_left_tab" => "tab",
+        "space" => "space",
+        "caps_lock" | "capslock" => "capslock",
+        "home" | "kp_home" => "home",
+        "end" | "kp_end" => "end",
+        "page_up" | "pageup" | "prior" | "kp_prior" => "pageup",
+        "page_down" | "pagedown" | "next" | "kp_next" => "pagedown",
+        "up" | "arrowup" | "kp_up" => "up",
+        "down" | "arrowdown" | "kp_down" => "down",
+        "left" | "arrowleft" | "kp_left" => "left",
+        "right" | "arrowright" | "kp_right" => "right",
+        "minus" | "kp_subtract" => "-",
+        "plus" | "kp_add" => "+",
+        "equal" => "=",
+        "period" | "kp_decimal" => ".",
+        "comma" => ",",
+        "slash" | "kp_divide" => "/",
+        "backslash" => "\\",
+        "semicolon" => ";",
+        "apostrophe" => "'",
+        "grave" => "`",
+        "bracketleft" => "[",
+        "bracketright" => "]",
+        "asterisk" | "kp_multiply" => "*",
+        lower => match lower.strip_prefix("kp_") {
+            // keypad digits
+            Some(digit) if digit.len() == 1 => return digit.to_string(),
+            _ => return key.to_string(),
+        },
+    };
+    name.to_string()
+}
+
+/// The keys of an xdotool chord like `ctrl+shift+t`, one chord at a time
+pub fn chord(text: &str) -> Result<Vec<String>, String> {
+    let text = text.trim();
+    if text.split_whitespace().count() > 1 {
+        return Err(format!(
+            "Press one key combination at a time instead of {:?}",
+            text
+        ));
+    }
+    let mut keys: Vec<String> = text
+        .split('+')
+        .filter(|key| !key.is_empty())
+        .map(key_name)
+        .collect();
+    // a trailing + is the plus key itself, as in ctrl++
+    if text.ends_with('+') {
+        keys.push("+".to_string());
+    }
+    if keys.is_empty() {
+        return Err("No keys to press".to_string());
+    }
+    Ok(keys)
+}
+
+/// A computer action in the Responses API's vocabulary, which local models are offered too
+#[derive(Debug, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OpenAiAction {
+    Click {
+        #[serde(default = "left")]
+        button: String,
+        x: f64,
+        y: f64,
+    },
+    DoubleClick {
+        x: f64,
+        y: f64,
+    },
+    Drag {
+        path: Vec<Point>,
+    },
+    Keypress {
+        keys: Vec<String>,
+    },
+    Move {
+        x: f64,
+        y: f64,
+    },
+    Screenshot,
+    Scroll {
+        x: f64,
+        y: f64,
+        scroll_x: f64,
+        scroll_y: f64,
+    },
+    Type {
+        text: String,
+    },
+    Wait,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+fn left() -> String {
+    "left".to_string()
+}
+
+/// Translates a Responses API computer action, which scrolls in pixels
+pub fn openai(action: Value) -> Result<ComputerCall, String> {
+    let action: OpenAiAction =
+        serde_json::from_value(action).map_err(|e| format!("Unknown computer action: {}", e))?;
+    let action = match action {
+        OpenAiAction::Click { button, x, y } => Action::Click {
+            button: match button.as_str() {
+                // the wheel button is the middle one
+                "wheel" => "middle".to_string(),
+                "left" | "right" | "middle" => button,
+                _ => return Err(format!("Unsupported mouse button: {}", button)),
+            },
+            x,
+            y,
+        },
+        OpenAiAction::DoubleClick { x, y } => Action::DoubleClick { x, y },
+        OpenAiAction::Drag { path } => Action::Drag {
+            path: path.into_iter().map(|p| (p.x, p.y)).collect(),
+        },
+        OpenAiAction::Keypress { keys } => Action::Keypress {
+            keys: keys.iter().map(|key| key_name(key)).collect(),
+        },
+        OpenAiAction::Move { x, y } => Action::MoveMouse { x, y },
+        OpenAiAction::Screenshot => return Ok(ComputerCall::Screenshot),
+        OpenAiAction::Scroll {
+            x,
+            y,
+            scroll_x,
+            scroll_y,
+        } => Action::Scroll {
+            x,
+            y,
+            scroll_x: scroll_ticks(scroll_x, ScrollUnit::Pixels),
+            scroll_y: scroll_ticks(scroll_y, ScrollUnit::Pixels),
+        },
+        OpenAiAction::Type { text } => Action::TypeText { text },
+        OpenAiAction::Wait => return Ok(ComputerCall::Wait),
+    };
+    Ok(ComputerCall::Perform(action))
+}
+
+fn coordinate(input: &Value, key: &str) -> Result<Option<(f64, f64)>, String> {
+    match &input[key] {
+        Value::Null => Ok(None),
+        Value::Array(point) => match point.as_slice() {
+            [x, y] => match (x.as_f64(), y.as_f64()) {
+                (Some(x), Some(y)) => Ok(Some((x, y))),
+                _ => Err(format!("Invalid {}: {}", key, input[key])),
+            },
+            _ => Err(format!("Invalid {}: {}", key, input[key])),
+        },
+        other => Err(format!("Invalid {}: {}", key, other)),
+    }
+}
+
+fn required_coordinate(input: &Value, key: &str) -> Result<(f64, f64), String> {
+    coordinate(input, key)?.ok_or(format!("Missing {}", key))
+}
+
+fn text(input: &Value) -> Result<&str, String> {
+    input["text"].as_str().ok_or("Missing text".to_string())
+}
+
+fn duration_ms(input: &Value) -> Option<u64> {
+    input["duration"]
+        .as_f64()
+        .map(|seconds| (seconds * 1000.0) as u64)
+}
+
+/// Translates an Anthropic computer-use tool input, which scrolls in wheel ticks and names keys
+/// the way xdotool does
+pub fn anthropic(input: &Value) -> Result<ComputerCall, String> {
+    let action = input["action"].as_str().unwrap_or_default();
+    let button = |button: &str| button.to_string();
+    let pointer = match action {
+        "left_click" => Some(PointerAction::Click {
+            button: button("left"),
+        }),
+        "right_click" => Some(PointerAction::Click {
+            button: button("right"),
+        }),
+        "middle_click" => Some(PointerAction::Click {
+            button: button("middle"),
+        }),
+        "double_click" => Some(PointerAction::DoubleClick),
+        "triple_click" => Some(PointerAction::TripleClick),
+        "left_mouse_down" => Some(PointerAction::MouseDown {
+            button: button("left"),
+        }),
+        "left_mouse_up" => Some(PointerAction::MouseUp {
+            button: button("left"),
+        }),
+        "scroll" => {
+            let amount = scroll_ticks(
+                input["scroll_amount"].as_f64().unwrap_or(1.0),
+                ScrollUnit::Ticks,
+            );
+            let (scroll_x, scroll_y) = match input["scroll_direction"].as_str() {
+                Some("up") => (0.0, -amount),
+                Some("down") => (0.0, amount),
+                Some("left") => (-amount, 0.0),
+                Some("right") => (amount, 0.0),
+                other => return Err(format!("Unknown scroll direction: {:?}", other)),
+            };
+            Some(PointerAction::Scroll { scroll_x, scroll_y })
+        }
+        _ => None,
+    };
+    if let Some(pointer) = pointer {
+        // these take keys to hold in `text`, which isn't supported
+        if let Some(keys) = input["text"].as_str().filter(|keys| !keys.is_empty()) {
+            return Err(format!(
+                "Holding {} during {} is not supported",
+                keys, action
+            ));
+        }
+        return Ok(match coordinate(input, "coordinate")? {
+            Some((x, y)) => ComputerCall::Perform(pointer.at(x, y)),
+            None => ComputerCall::AtPointer(pointer),
+        });
+    }
+    match action {
+        "screenshot" => Ok(ComputerCall::Screenshot),
+        "cursor_position" => Ok(ComputerCall::CursorPosition),
+        "mouse_move" => {
+            let (x, y) = required_coordinate(input, "coordinate")?;
+            Ok(ComputerCall::Perform(Action::MoveMouse { x, y }))
+        }
+        "left_click_drag" => Ok(ComputerCall::Perform(Action::Drag {
+            path: vec![
+                required_coordinate(input, "start_coordinate")?,
+                required_coordinate(input, "coordinate")?,
+            ],
+        })),
+        "type" => Ok(ComputerCall::Perform(Action::TypeText {
+            text: text(input)?.to_string(),
+        })),
+        "key" => Ok(ComputerCall::Perform(Action::Keypress {
+            keys: chord(text(input)?)?,
+        })),
+        "hold_key" => Ok(ComputerCall::Perform(Action::HoldKey {
+            keys: chord(text(input)?)?,
+            ms: duration_ms(input).ok_or("Missing duration")?,
+        })),
+        "wait" => match duration_ms(input) {
+            Some(ms) => Ok(ComputerCall::Perform(Action::Wait { ms })),
+            None => Ok(ComputerCall::Wait),
+        },
+        _ => Err(format!("Unsupported computer action: {:?}", action)),
+    }
+}
+
+/// Translates a local model's computer call, which is offered the Responses API's vocabulary but
+/// may answer in Anthropic's, with an `action` instead of a `type`
+pub fn local(arguments: Value) -> Result<ComputerCall, String> {
+    if arguments.get("action").is_some() && arguments.get("type").is_none() {
+        anthropic(&arguments)
+    } else {
+        openai(arguments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::super::{ComputerCall, PointerAction};
+    use super::{anthropic, chord, local, openai};
+    use crate::commands::action::Action;
+
+    fn perform(action: Action) -> Result<ComputerCall, String> {
+        Ok(ComputerCall::Perform(action))
+    }
+
+    fn keys(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    fn click(button: &str) -> PointerAction {
+        PointerAction::Click {
+            button: button.to_string(),
+        }
+    }
+
+    #[test]
+    fn anthropic_clicks() {
+        for (action, pointer) in [
+            ("left_click", click("left")),
+            ("right_click", click("right")),
+            ("middle_click", click("middle")),
+            ("double_click", PointerAction::DoubleClick),
+            ("triple_click", PointerAction::TripleClick),
+        ] {
+            assert_eq!(
+                anthropic(&json!({ "action": action, "coordinate": [10, 20] })),
+                perform(pointer.clone().at(10.0, 20.0)),
+                "{}",
+                action
+            );
+            assert_eq!(
+                anthropic(&json!({ "action": action })),
+                Ok(ComputerCall::AtPointer(pointer)),
+                "{}",
+                action
+            );
+        }
+    }
+
+    #[test]
+    fn anthropic_mouse_buttons_and_pointer() {
+        assert_eq!(
+            anthropic(&json!({ "action": "left_mouse_down", "coordinate": [1, 2] })),
+            perform(Action::MouseDown {
+                button: "left".to_string(),
+                x: 1.0,
+                y: 2.0,
+            })
+        );
+        assert_eq!(
+            anthropic(&json!({ "action": "left_mouse_up" })),
+            Ok(ComputerCall::AtPointer(PointerAction::MouseUp {
+                button: "left".to_string(),
+            }))
+        );
+        assert_eq!(
+            anthropic(&json!({ "action": "cursor_position" })),
+            Ok(ComputerCall::CursorPosition)
+        );
+        assert_eq!(
+            anthropic(&json!({ "action": "mouse_move", "coordinate": [3, 4] })),
+            perform(Action::MoveMouse { x: 3.0, y: 4.0 })
+        );
+        assert!(anthropic(&json!({ "action": "mouse_move" })).is_err());
+        assert_eq!(
+            anthropic(&json!({
+                "action": "left_click_drag",
+                "start_coordinate": [1, 2],
+                "coordinate": [30, 40],
+            })),
+            perform(Action::Drag {
+                path: vec![(1.0, 2.0), (30.0, 40.0)],
+            })
+        );
+        assert!(
+            anthropic(&json!({ "action": "left_click_drag", "coordinate": [30, 40] })).is_err()
+        );
+        assert!(anthropic(&json!({ "action": "left_click", "coordinate": [1] })).is_err());
+        assert!(anthropic(&json!({ "action": "left_click", "coordinate": "1,2" })).is_err());
+    }
+
+    #[test]
+    fn anthropic_keys() {
+        for (text, expected) in [
+            ("Return", keys(&["enter"])),
+            ("ctrl+shift+t", keys(&["ctrl", "shift", "t"])),
+            ("ctrl++", keys(&["ctrl", "+"])),
+            ("Page_Down", keys(&["pagedown"])),
+            ("super", keys(&["meta"])),
+            ("super+l", keys(&["meta", "l"])),
+            ("KP_1", keys(&["1"])),
+            ("alt+F4", keys(&["alt", "F4"])),
+            ("BackSpace", keys(&["backspace"])),
+        ] {
+            assert_eq!(
+                anthropic(&json!({ "action": "key", "text": text })),
+                perform(Action::Keypress {
+                    keys: expected.clone()
+                }),
+                "{}",
+                text
+            );
+            assert_eq!(chord(text), Ok(expected), "{}", text);
+        }
+        assert_eq!(
+            anthropic(&json!({ "action": "type", "text": "hello world" })),
+            perform(Action::TypeText {
+                text: "hello world".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn anthropic_holds_keys_for_the_duration() {
+        assert_eq!(
+            anthropic(&json!({ "action": "hold_key", "text": "shift", "duration": 1.5 })),
+            perform(Action::HoldKey {
+                keys: keys(&["shift"]),
+                ms: 1500,
+            })
+        );
+        assert!(anthropic(&json!({ "action": "hold_key", "text": "shift" })).is_err());
+        assert_eq!(
+            anthropic(&json!({ "action": "wait", "duration": 2 })),
+            perform(Action::Wait { ms: 2000 })
+        );
+        assert_eq!(
+            anthropic(&json!({ "action": "wait" })),
+            Ok(ComputerCall::Wait)
+        );
+    }
+
+    #[test]
+    fn anthropic_scrolls_in_ticks() {
+        for (direction, scroll_x, scroll_y) in [
+            ("up", 0.0, -3.0),
+            ("down", 0.0, 3.0),
+            ("left", -3.0, 0.0),
+            ("right", 3.0, 0.0),
+        ] {
+            assert_eq!(
+                anthropic(&json!({
+                    "action": "scroll",
+                    "coordinate": [100, 200],
+                    "scroll_direction": direction,
+                    "scroll_amount": 3,
+                })),
+                perform(Action::Scroll {
+                    x: 100.0,
+                    y: 200.0,
+                    scroll_x,
+                    scroll_y,
+                }),
+                "{}",
+                direction
+            );
+        }
+        // one tick when no amount is given
+        assert_eq!(
+            anthropic(&json!({ "action": "scroll", "scroll_direction": "down" })),
+            Ok(ComputerCall::AtPointer(PointerAction::Scroll {
+                scroll_x: 0.0,
+                scroll_y: 1.0,
+            }))
+        );
+    }
+
+    #[test]
+    fn openai_scrolls_in_pixels() {
+        assert_eq!(
+            openai(json!({ "type": "scroll", "x": 5, "y": 6, "scroll_x": -50, "scroll_y": 100 })),
+            perform(Action::Scroll {
+                x: 5.0,
+                y: 6.0,
+                scroll_x: -2.0,
+                scroll_y: 4.0,
+            })
+        );
+    }
+
+    #[test]
+    fn openai_actions() {
+        assert_eq!(
+            openai(json!({ "type": "click", "x": 1, "y": 2 })),
+            perform(Action::Click {
+                button: "left".to_string(),
+                x: 1.0,
+                y: 2.0,
+            })
+        );
+        assert_eq!(
+            openai(json!({ "type": "click", "button": "wheel", "x": 1, "y": 2 })),
+            perform(Action::Click {
+                button: "middle".to_string(),
+                x: 1.0,
+                y: 2.0,
+            })
+        );
+        assert!(openai(json!({ "type": "click", "button": "back", "x": 1, "y": 2 })).is_err());
+        assert_eq!(
+            openai(json!({ "type": "double_click", "x": 1, "y": 2 })),
+            perform(Action::DoubleClick { x: 1.0, y: 2.0 })
+        );
+        assert_eq!(
+            openai(json!({ "type": "keypress", "keys": ["CTRL", "ArrowLeft"] })),
+            perform(Action::Keypress {
+                keys: keys(&["ctrl", "left"]),
+            })
+        );
+        assert_eq!(
+            openai(json!({ "type": "drag", "path": [{ "x": 1, "y": 2 }, { "x": 3, "y": 4 }] })),
+            perform(Action::Drag {
+                path: vec![(1.0, 2.0), (3.0, 4.0)],
+            })
+        );
+        assert_eq!(
+            openai(json!({ "type": "move", "x": 7, "y": 8 })),
+            perform(Action::MoveMouse { x: 7.0, y: 8.0 })
+        );
+        assert_eq!(
+            openai(json!({ "type": "type", "text": "hi" })),
+            perform(Action::TypeText {
+                text: "hi".to_string(),
+            })
+        );
+        assert_eq!(
+            openai(json!({ "type": "screenshot" })),
+            Ok(ComputerCall::Screenshot)
+        );
+        assert_eq!(openai(json!({ "type": "wait" })), Ok(ComputerCall::Wait));
+        assert!(openai(json!({ "type": "fly" })).is_err());
+    }
+
+    #[test]
+    fn rejects_what_cannot_be_done() {
+        assert!(anthropic(&json!({
+            "action": "scroll",
+            "scroll_direction": "sideways",
+        }))
+        .is_err());
+        assert!(anthropic(&json!({ "action": "scroll" })).is_err());
+        // keys held during a click
+        assert!(anthropic(&json!({
+            "action": "left_click",
+            "coordinate": [1, 2],
+            "text": "shift",
+        }))
+        .is_err());
+        assert!(anthropic(
+            &json!({ "action": "scroll", "scroll_direction": "up", "text": "ctrl" })
+        )
+        .is_err());
+        // an empty text holds nothing
+        assert!(anthropic(&json!({ "action": "left_click", "text": "" })).is_ok());
+        // one chord at a time
+        assert!(anthropic(&json!({ "action": "key", "text": "ctrl+a ctrl+c" })).is_err());
+        assert!(anthropic(&json!({ "action": "key", "text": "" })).is_err());
+        assert!(anthropic(&json!({ "action": "key" })).is_err());
+        assert!(anthropic(&json!({ "action": "zoom" })).is_err());
+        assert!(anthropic(&json!({})).is_err());
+    }
+
+    #[test]
+    fn local_takes_either_vocabulary() {
+        assert_eq!(
+            local(json!({ "type": "scroll", "x": 0, "y": 0, "scroll_x": 0, "scroll_y": 50 })),
+            perform(Action::Scroll {
+                x: 0.0,
+                y: 0.0,
+                scroll_x: 0.0,
+                scroll_y: 2.0,
+            })
+        );
+        assert_eq!(
+            local(json!({
+                "action": "scroll",
+                "coordinate": [0, 0],
+                "scroll_direction": "down",
+                "scroll_amount": 50,
+            })),
+            perform(Action::Scroll {
+                x: 0.0,
+                y: 0.0,
+                scroll_x: 0.0,
+                scroll_y: 50.0,
+            })
+        );
+        assert_eq!(
+            local(json!({ "action": "key", "text": "Page_Down" })),
+            perform(Action::Keypress {
+                keys: keys(&["pagedown"]),
+            })
+        );
+        // a `type` wins over an `action`
+        assert_eq!(
+            local(json!({ "type": "screenshot", "action": "cursor_position" })),
+            Ok(ComputerCall::Screenshot)
+        );
+        assert!(local(json!({ "x": 1 })).is_err());
+    }
+}
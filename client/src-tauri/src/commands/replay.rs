@@ -0,0 +1,546 @@
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{async_runtime, command, AppHandle, Emitter, Manager, Runtime, State};
+
+use super::action::{perform, Action};
+use super::error::CommandError;
+use super::image::capture_screen;
+use super::journal::{self, parse_time, screenshots_dir, Journal, JournalEntry, JournalQuery};
+use super::redact::redact;
+use super::watch::{frame_difference, thumbnail};
+
+/// What to do when the screen no longer looks like it did when the actions were recorded
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnDivergence {
+    /// Wait for `resume_replay` or `stop_replay`
+    Pause,
+    #[default]
+    Stop,
+}
+
+fn default_speed() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ReplayOptions {
+    /// The journaled task to replay
+    pub task_id: String,
+    /// How much faster than recorded, 1 keeps the original timing and 0 leaves out the pauses
+    #[serde(default = "default_speed")]
+    pub speed: f64,
+    /// Before each recorded screenshot's actions, compare the screen with it and diverge
+    /// when a larger fraction than this differs. Unchecked when unset.
+    #[serde(default)]
+    pub tolerance: Option<f64>,
+    #[serde(default)]
+    pub on_divergence: OnDivergence,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayStatus {
+    Completed,
+    Diverged,
+    Stopped,
+    Failed,
+}
+
+/// Where the screen stopped matching the recording
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Divergence {
+    pub index: usize,
+    pub step_id: Option<String>,
+    pub screenshot_id: String,
+    pub difference: f64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ReplayReport {
+    pub id: u64,
+    pub task_id: String,
+    pub status: ReplayStatus,
+    pub total: usize,
+    pub replayed: usize,
+    pub divergence: Option<Divergence>,
+    pub error: Option<CommandError>,
+}
+
+enum ReplayControl {
+    Resume,
+    Stop,
+}
+
+/// Replays in progress, keyed by id so they can be resumed or stopped
+#[derive(Default)]
+pub struct Replays {
+    next_id: AtomicU64,
+    active: Mutex<HashMap<u64, mpsc::Sender<ReplayControl>>>,
+}
+
+// The actions worth repeating: the ones that passed their checks, dry runs included
+fn replayable(task_id: &str) -> Result<Vec<JournalEntry>, String> {
+    let entries = journal::query(&JournalQuery {
+        task_id: Some(task_id.to_string()),
+        ..Default::default()
+    })?;
+    Ok(entries.into_iter().filter(|e| e.result.is_some()).collect())
+}
+
+// How long to wait before an entry, the recorded gap since the previous one scaled by the speed
+fn delay(previous: Option<&JournalEntry>, entry: &JournalEntry, speed: f64) -> Duration {
+    let Some(previous) = previous else {
+        return Duration::ZERO;
+    };
+    if speed == 0.0 {
+        return Duration::ZERO;
+    }
+    match (
+        parse_time(&previous.finished_at),
+        parse_time(&entry.started_at),
+    ) {
+        (Ok(finished), Ok(started)) => (started - finished)
+            .to_std()
+            .map_or(Duration::ZERO, |gap| gap.div_f64(speed)),
+        _ => Duration::ZERO,
+    }
+}
+
+// Fraction of the screen that differs from a recorded screenshot, redacted the same way.
+// `None` when the screenshot is no longer on disk, leaving nothing to compare with.
+fn difference<R: Runtime>(
+    handle: &AppHandle<R>,
+    screenshot_id: &str,
+) -> Result<Option<f64>, CommandError> {
+    let path = screenshots_dir()?.join(format!("{}.webp", screenshot_id));
+    if !path.exists() {
+        return Ok(None);
+    }
+    let recorded = image::open(&path)
+        .map_err(|e| format!("Failed to open screenshot {}: {}", screenshot_id, e))?;
+    let mut capture = capture_screen(handle)?;
+    redact(handle, &mut capture)?;
+    Ok(Some(frame_difference(
+        &thumbnail(&recorded),
+        &thumbnail(&capture.image),
+    )))
+}
+
+// Waits out a delay unless the replay is stopped in the meantime
+fn sleep(receiver: &mpsc::Receiver<ReplayControl>, duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match receiver.recv_timeout(left) {
+            Ok(ReplayControl::Stop) => return false,
+            Ok(ReplayControl::Resume) => continue,
+            Err(mpsc::RecvTimeoutError::Timeout) => return true,
+            Err(mpsc::RecvTimeoutError::Disconnected) => return false,
+        }
+    }
+}
+
+fn replay_entries<R: Runtime>(
+    handle: &AppHandle<R>,
+    id: u64,
+    options: &ReplayOptions,
+    entries: &[JournalEntry],
+    receiver: &mpsc::Receiver<ReplayControl>,
+) -> ReplayReport {
+    let mut report = ReplayReport {
+        id,
+        task_id: options.task_id.clone(),
+        status: ReplayStatus::Completed,
+        total: entries.len(),
+        replayed: 0,
+        divergence: None,
+        error: None,
+    };
+    let journal = handle.state::<Journal>();
+    journal.start_task(format!("replay-{}-{}", id, options.task_id));
+
+    let mut previous: Option<&JournalEntry> = None;
+    for (index, entry) in entries.iter().enumerate() {
+        if !sleep(receiver, delay(previous, entry, options.speed)) {
+            report.status = ReplayStatus::Stopped;
+            break;
+        }
+
+        // Actions after the same screenshot were decided on the same screen, check it once
+        let first_after_screenshot =
+            previous.is_none_or(|p| p.screenshot_id != entry.screenshot_id);
+        let mut difference_seen = None;
+        let check = options
+            .tolerance
+            .zip(entry.screenshot_id.as_ref())
+            .filter(|_| first_after_screenshot);
+        if let Some((tolerance, screenshot_id)) = check {
+            difference_seen = match difference(handle, screenshot_id) {
+                Ok(diff) => diff,
+                Err(e) => {
+                    log::warn!("replay: {}", e);
+                    report.status = ReplayStatus::Failed;
+                    report.error = Some(e);
+                    break;
+                }
+            };
+            if difference_seen.is_none() {
+                log::warn!(
+                    "replay {}: screenshot {} is missing, not checking step {}",
+                    id,
+                    screenshot_id,
+                    index
+                );
+            }
+            if let Some(diff) = difference_seen.filter(|diff| *diff > tolerance) {
+                log::warn!(
+                    "replay {}: step {} differs by {:.3} from screenshot {}",
+                    id,
+                    index,
+                    diff,
+                    screenshot_id
+                );
+                report.divergence = Some(Divergence {
+                    index,
+                    step_id: entry.step_id.clone(),
+                    screenshot_id: screenshot_id.clone(),
+                    difference: diff,
+                });
+                if options.on_divergence == OnDivergence::Stop {
+                    report.status = ReplayStatus::Diverged;
+                    break;
+                }
+                let _ = handle.emit("agent_replay_paused", &report);
+                match receiver.recv() {
+                    Ok(ReplayControl::Resume) => {
+                        log::info!("replay {}: resumed at step {}", id, index)
+                    }
+                    Ok(ReplayControl::Stop) | Err(_) => {
+                        report.status = ReplayStatus::Diverged;
+                        break;
+                    }
+                }
+            }
+        }
+
+        let action = match &entry.action {
+            Action::Wait { ms } if options.speed == 0.0 => Action::Wait { ms: 0 },
+            Action::Wait { ms } => Action::Wait {
+                ms: (*ms as f64 / options.speed) as u64,
+            },
+            action => action.clone(),
+        };
+        journal.set_step(entry.step_id.clone());
+        if let Err(e) = async_runtime::block_on(perform(handle, action)) {
+            log::warn!("replay {}: step {} failed: {}", id, index, e);
+            report.status = ReplayStatus::Failed;
+            report.error = Some(e);
+            break;
+        }
+        report.replayed += 1;
+        let _ = handle.emit(
+            "agent_replay_step",
+            json!({
+                "id": id,
+                "index": index,
+                "total": entries.len(),
+                "action": entry.action,
+                "difference": difference_seen,
+            }),
+        );
+        previous = Some(entry);
+    }
+    report
+}
+
+/// Repeats a journaled task's actions through the same checks as the agent's.
+/// Emits `agent_replay_step` after each action, `agent_replay_paused` on divergence
+/// when pausing, and `agent_replay_finished` with the report.
+pub async fn replay<R: Runtime>(
+    handle: &AppHandle<R>,
+    options: ReplayOptions,
+) -> Result<ReplayReport, String> {
+    if !options.speed.is_finite() || options.speed < 0.0 {
+        return Err(format!("Invalid replay speed {}", options.speed));
+    }
+    let entries = replayable(&options.task_id)?;
+    if entries.is_empty() {
+        return Err(format!(
+            "No replayable actions recorded for task {}",
+            options.task_id
+        ));
+    }
+
+    let replays = handle.state::<Replays>();
+    let id = replays.next_id.fetch_add(1, Ordering::Relaxed);
+    let (sender, receiver) = mpsc::channel();
+    replays.active.lock().unwrap().insert(id, sender);
+    log::info!(
+        "replay {}: replaying {} actions of task {} at {}x",
+        id,
+        entries.len(),
+        options.task_id,
+        options.speed
+    );
+
+    let replayer = handle.clone();
+    let report = async_runtime::spawn_blocking(move || {
+        replay_entries(&replayer, id, &options, &entries, &receiver)
+    })
+    .await;
+    replays.active.lock().unwrap().remove(&id);
+    let report = report.map_err(|e| format!("Failed to replay: {}", e))?;
+    log::info!(
+        "replay {}: {:?} after {} of {} actions",
+        id,
+        report.status,
+        report.replayed,
+        report.total
+    );
+    let _ = handle.emit("agent_replay_finished", &report);
+    Ok(report)
+}
+
+/// Parses `replay <task_id> [--speed <factor>] [--tolerance <fraction>] [--on-divergence pause|stop]`
+/// from the command line, `None` unless it is the first argument after the program, flags aside
+pub fn parse_cli(args: &[String]) -> Result<Option<ReplayOptions>, String> {
+    let Some(start) = args
+        .iter()
+        .skip(1)
+        .position(|arg| !arg.starts_with('-'))
+        .map(|position| position + 1)
+        .filter(|start| args[*start] == "replay")
+    else {
+        return Ok(None);
+    };
+    let mut args = args[start + 1..].iter();
+    let mut options = ReplayOptions {
+        task_id: String::new(),
+        speed: default_speed(),
+        tolerance: None,
+        on_divergence: OnDivergence::default(),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or(format!("Missing value for {}", arg))
+                .cloned()
+        };
+        match arg.as_str() {
+            "--speed" => {
+                let speed = value()?;
+                options.speed = speed
+                    .parse()
+                    .map_err(|_| format!("Invalid speed {}", speed))?;
+            }
+            "--tolerance" => {
+                let tolerance = value()?;
+                options.tolerance = Some(
+                    tolerance
+                        .parse()
+                        .map_err(|_| format!("Invalid tolerance {}", tolerance))?,
+                );
+            }
+            "--on-divergence" => {
+                options.on_divergence = match value()?.as_str() {
+                    "pause" => OnDivergence::Pause,
+                    "stop" => OnDivergence::Stop,
+                    other => return Err(format!("Invalid --on-divergence {}", other)),
+                };
+            }
+            task_id if options.task_id.is_empty() && !task_id.starts_with("--") => {
+                options.task_id = task_id.to_string();
+            }
+            other => return Err(format!("Unexpected replay argument {}", other)),
+        }
+    }
+    if options.task_id.is_empty() {
+        return Err("Usage: replay <task_id> [--speed <factor>] [--tolerance <fraction>] [--on-divergence pause|stop]".to_string());
+    }
+    Ok(Some(options))
+}
+
+/// Starts the replay given on the command line, if any, logging the report once it is done.
+/// `exit` quits the app afterwards, with a non-zero code unless every action was replayed.
+pub fn run_cli<R: Runtime>(handle: &AppHandle<R>, args: &[String], exit: bool) {
+    let options = match parse_cli(args) {
+        Ok(Some(options)) => options,
+        Ok(None) => return,
+        Err(e) => {
+            log::error!("replay: {}", e);
+            if exit {
+                handle.exit(2);
+            }
+            return;
+        }
+    };
+    let handle = handle.clone();
+    async_runtime::spawn(async move {
+        let code = match replay(&handle, options).await {
+            Ok(report) => {
+                log::info!(
+                    "replay: finished\n{}",
+                    serde_json::to_string_pretty(&report).unwrap_or_default()
+                );
+                if report.status == ReplayStatus::Completed {
+                    0
+                } else {
+                    1
+                }
+            }
+            Err(e) => {
+                log::error!("replay: {}", e);
+                1
+            }
+        };
+        if exit {
+            handle.exit(code);
+        }
+    });
+}
+
+#[command]
+pub async fn replay_journal<R: Runtime>(
+    handle: AppHandle<R>,
+    options: ReplayOptions,
+) -> Result<ReplayReport, String> {
+    replay(&handle, options).await
+}
+
+fn control(replays: &Replays, id: u64, message: ReplayControl) -> Result<(), String> {
+    let active = replays.active.lock().unwrap();
+    let sender = active
+        .get(&id)
+        .ok_or(format!("Replay {} is not running", id))?;
+    sender
+        .send(message)
+        .map_err(|_| format!("Replay {} is not running", id))
+}
+
+/// Continues a replay paused on divergence
+#[command]
+pub fn resume_replay(replays: State<'_, Replays>, id: u64) -> Result<(), String> {
+    control(&replays, id, ReplayControl::Resume)
+}
+
+#[command]
+pub fn stop_replay(replays: State<'_, Replays>, id: u64) -> Result<(), String> {
+    control(&replays, id, ReplayControl::Stop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn entry(started_at: &str, finished_at: &str) -> JournalEntry {
+        JournalEntry {
+            task_id: Some("task".to_string()),
+            step_id: None,
+            action: Action::Wait { ms: 0 },
+            started_at: started_at.to_string(),
+            finished_at: finished_at.to_string(),
+            duration_ms: 0,
+            result: None,
+            error: None,
+            cursor_before: None,
+            cursor_after: None,
+            screenshot_id: None,
+        }
+    }
+
+    #[test]
+    fn parse_cli_without_replay() {
+        assert!(parse_cli(&args(&["paimon"])).unwrap().is_none());
+        assert!(parse_cli(&args(&[])).unwrap().is_none());
+        assert!(parse_cli(&args(&["paimon", "--verbose"]))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn parse_cli_only_takes_replay_first() {
+        assert!(parse_cli(&args(&["paimon", "open", "replay", "task"]))
+            .unwrap()
+            .is_none());
+        // The program itself is never the command
+        assert!(parse_cli(&args(&["replay", "task"])).unwrap().is_none());
+        let options = parse_cli(&args(&["paimon", "--verbose", "replay", "task"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(options.task_id, "task");
+    }
+
+    #[test]
+    fn parse_cli_options() {
+        let options = parse_cli(&args(&["paimon", "replay", "task"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(options.task_id, "task");
+        assert_eq!(options.speed, 1.0);
+        assert_eq!(options.tolerance, None);
+        assert_eq!(options.on_divergence, OnDivergence::Stop);
+
+        let options = parse_cli(&args(&[
+            "paimon",
+            "replay",
+            "--speed",
+            "2.5",
+            "task",
+            "--tolerance",
+            "0.1",
+            "--on-divergence",
+            "pause",
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(options.task_id, "task");
+        assert_eq!(options.speed, 2.5);
+        assert_eq!(options.tolerance, Some(0.1));
+        assert_eq!(options.on_divergence, OnDivergence::Pause);
+    }
+
+    #[test]
+    fn parse_cli_errors() {
+        assert!(parse_cli(&args(&["paimon", "replay"])).is_err());
+        assert!(parse_cli(&args(&["paimon", "replay", "task", "--speed"])).is_err());
+        assert!(parse_cli(&args(&["paimon", "replay", "task", "--speed", "fast"])).is_err());
+        assert!(parse_cli(&args(&[
+            "paimon",
+            "replay",
+            "task",
+            "--on-divergence",
+            "skip"
+        ]))
+        .is_err());
+        assert!(parse_cli(&args(&["paimon", "replay", "task", "other"])).is_err());
+        assert!(parse_cli(&args(&["paimon", "replay", "task", "--unknown"])).is_err());
+    }
+
+    #[test]
+    fn delay_scales_the_recorded_gap() {
+        let previous = entry("2024-01-01T00:00:00.000Z", "2024-01-01T00:00:01.000Z");
+        let next = entry("2024-01-01T00:00:03.000Z", "2024-01-01T00:00:04.000Z");
+        assert_eq!(delay(None, &next, 1.0), Duration::ZERO);
+        assert_eq!(delay(Some(&previous), &next, 1.0), Duration::from_secs(2));
+        assert_eq!(delay(Some(&previous), &next, 2.0), Duration::from_secs(1));
+        assert_eq!(delay(Some(&previous), &next, 0.5), Duration::from_secs(4));
+        assert_eq!(delay(Some(&previous), &next, 0.0), Duration::ZERO);
+    }
+
+    #[test]
+    fn delay_without_a_usable_gap() {
+        let previous = entry("2024-01-01T00:00:00.000Z", "2024-01-01T00:00:05.000Z");
+        let overlapping = entry("2024-01-01T00:00:03.000Z", "2024-01-01T00:00:06.000Z");
+        assert_eq!(delay(Some(&previous), &overlapping, 1.0), Duration::ZERO);
+        let unparsable = entry("yesterday", "today");
+        assert_eq!(delay(Some(&previous), &unparsable, 1.0), Duration::ZERO);
+    }
+}
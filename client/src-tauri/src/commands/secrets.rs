@@ -0,0 +1,335 @@
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::sync::{LazyLock, Mutex};
+use tauri::{command, State};
+
+use super::error::{CommandError, ErrorKind};
+use super::windowing::{foreground_window, WindowInfo};
+use crate::{config, credentials};
+
+// Only the names and where they may be typed are kept here, the values are credentials like the
+// API keys
+const CONFIG_NAME: &str = "secrets";
+
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*secret:([A-Za-z0-9_.-]+)\s*\}\}").unwrap());
+static NAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[A-Za-z0-9_.-]+$").unwrap());
+
+fn credential(name: &str) -> String {
+    format!("secret:{}", name)
+}
+
+/// The windows a secret may be typed into. With both set, the window must match both.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SecretScope {
+    /// The application name, matched case-insensitively
+    pub app: Option<String>,
+    /// A fragment of the window title, matched case-insensitively
+    pub title: Option<String>,
+}
+
+impl SecretScope {
+    fn is_empty(&self) -> bool {
+        self.app.is_none() && self.title.is_none()
+    }
+
+    fn allows(&self, window: &WindowInfo) -> bool {
+        let app = self
+            .app
+            .as_ref()
+            .is_none_or(|app| app.eq_ignore_ascii_case(&window.app_name));
+        let title = self.title.as_ref().is_none_or(|fragment| {
+            window
+                .title
+                .to_lowercase()
+                .contains(&fragment.to_lowercase())
+        });
+        !self.is_empty() && app && title
+    }
+}
+
+impl fmt::Display for SecretScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.app, &self.title) {
+            (Some(app), Some(title)) => write!(f, "{} windows titled like {:?}", app, title),
+            (Some(app), None) => write!(f, "{}", app),
+            (None, Some(title)) => write!(f, "windows titled like {:?}", title),
+            (None, None) => write!(f, "nowhere"),
+        }
+    }
+}
+
+/// Passwords and other values the agent can type as `{{secret:name}}` without ever seeing them.
+/// Actions, logs, the journal and events keep the placeholder, the value is only filled in for
+/// the input itself, and only while the focused window is one the secret is meant for.
+pub struct Secrets {
+    scopes: Mutex<BTreeMap<String, SecretScope>>,
+    // Values typed so far, hidden from later screenshots. Never written anywhere.
+    typed: Mutex<BTreeSet<(String, String)>>,
+}
+
+impl Default for Secrets {
+    fn default() -> Self {
+        Secrets {
+            scopes: Mutex::new(config::load(CONFIG_NAME)),
+            typed: Mutex::new(BTreeSet::new()),
+        }
+    }
+}
+
+impl Secrets {
+    /// The stored secrets and where each may be typed
+    pub fn scopes(&self) -> BTreeMap<String, SecretScope> {
+        self.scopes.lock().unwrap().clone()
+    }
+
+    /// Checks that every secret `text` refers to exists, without reading any of them
+    pub fn check(&self, text: &str) -> Result<(), String> {
+        let scopes = self.scopes.lock().unwrap();
+        for captures in PLACEHOLDER.captures_iter(text) {
+            if !scopes.contains_key(&captures[1]) {
+                return Err(format!("Unknown secret {:?}", &captures[1]));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fills the secrets into `text`, refusing unless the focused window is one every secret is
+    /// meant for. The result must go nowhere but the keyboard, after another `check_focus`.
+    pub fn resolve(&self, text: &str) -> Result<Resolved, CommandError> {
+        let window = foreground_window()?;
+        let scopes = self.scopes();
+        let mut values = HashMap::new();
+        let mut used = Vec::new();
+        for captures in PLACEHOLDER.captures_iter(text) {
+            let name = &captures[1];
+            if values.contains_key(name) {
+                continue;
+            }
+            let scope = scopes
+                .get(name)
+                .ok_or(format!("Unknown secret {:?}", name))?;
+            check_scope(name, scope, &window)?;
+            let (value, _) =
+                credentials::get(&credential(name))?.ok_or(format!("Unknown secret {:?}", name))?;
+            values.insert(name.to_string(), value);
+            used.push((name.to_string(), scope.clone()));
+        }
+        let text = fill(text, &values);
+        log::info!(
+            "secrets: filled in {}",
+            values.keys().cloned().collect::<Vec<_>>().join(", ")
+        );
+        self.typed.lock().unwrap().extend(values);
+        Ok(Resolved { text, scopes: used })
+    }
+
+    /// The secrets typed so far and their values, for redaction
+    pub fn typed(&self) -> Vec<(String, String)> {
+        self.typed.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Text with its secrets filled in, and where they may go
+pub struct Resolved {
+    pub text: String,
+    scopes: Vec<(String, SecretScope)>,
+}
+
+impl Resolved {
+    /// Checks the focused window again, it may have changed since the secrets were read.
+    /// Meant to run on the thread that sends the input, right before it does.
+    pub fn check_focus(&self) -> Result<(), CommandError> {
+        let window = foreground_window()?;
+        for (name, scope) in &self.scopes {
+            check_scope(name, scope, &window)?;
+        }
+        Ok(())
+    }
+}
+
+fn check_scope(name: &str, scope: &SecretScope, window: &WindowInfo) -> Result<(), CommandError> {
+    if scope.allows(window) {
+        return Ok(());
+    }
+    log::warn!(
+        "secrets: refusing to type {} into {} ({:?})",
+        name,
+        window.app_name,
+        window.title
+    );
+    Err(CommandError::new(
+        ErrorKind::SecretNotAllowed {
+            secret: name.to_string(),
+            app: window.app_name.clone(),
+            title: window.title.clone(),
+            allowed: scope.to_string(),
+        },
+        format!("{} may only be typed into {}", name, scope),
+    ))
+}
+
+// Replaces each placeholder with its value, every name in `text` must be in `values`
+fn fill(text: &str, values: &HashMap<String, String>) -> String {
+    PLACEHOLDER
+        .replace_all(text, |captures: &regex::Captures| {
+            values[&captures[1]].clone()
+        })
+        .into_owned()
+}
+
+pub fn has_placeholders(text: &str) -> bool {
+    PLACEHOLDER.is_match(text)
+}
+
+/// Stores a secret in the keyring, or encrypted in `~/.paimon` when there is none, along with the
+/// app or window title it may be typed into. A missing or empty value removes it. Values can be
+/// set but never read back.
+#[command]
+pub fn set_secret(
+    secrets: State<'_, Secrets>,
+    name: String,
+    value: Option<String>,
+    app: Option<String>,
+    title: Option<String>,
+) -> Result<(), String> {
+    if !NAME.is_match(&name) {
+        return Err(format!(
+            "Invalid secret name {:?}, use letters, digits, _, . and -",
+            name
+        ));
+    }
+    let mut scopes = secrets.scopes.lock().unwrap();
+    match value.filter(|value| !value.is_empty()) {
+        Some(value) => {
+            let scope = SecretScope {
+                app: app.filter(|app| !app.trim().is_empty()),
+                title: title.filter(|title| !title.trim().is_empty()),
+            };
+            if scope.is_empty() {
+                return Err(format!(
+                    "Say which app or window title {} may be typed into",
+                    name
+                ));
+            }
+            credentials::set(&credential(&name), &value)?;
+            scopes.insert(name, scope);
+        }
+        None => {
+            credentials::delete(&credential(&name))?;
+            scopes.remove(&name);
+        }
+    }
+    config::save(CONFIG_NAME, &*scopes)
+}
+
+/// The names of the stored secrets and where each may be typed
+#[command]
+pub fn list_secrets(secrets: State<'_, Secrets>) -> BTreeMap<String, SecretScope> {
+    secrets.scopes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::image::Region;
+
+    fn window(app: &str, title: &str) -> WindowInfo {
+        WindowInfo {
+            app_name: app.to_string(),
+            title: title.to_string(),
+            bounds: Region {
+                x: 0.0,
+                y: 0.0,
+                width: 800.0,
+                height: 600.0,
+            },
+        }
+    }
+
+    fn scope(app: Option<&str>, title: Option<&str>) -> SecretScope {
+        SecretScope {
+            app: app.map(str::to_string),
+            title: title.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn finds_placeholders() {
+        assert!(has_placeholders("{{secret:password}}"));
+        assert!(has_placeholders("user\t{{ secret:bank.pin-2 }}\n"));
+        assert!(!has_placeholders("{{password}}"));
+        assert!(!has_placeholders("{{secret:}}"));
+        assert!(!has_placeholders("{{secret:two words}}"));
+    }
+
+    #[test]
+    fn fills_in_every_placeholder() {
+        let values = HashMap::from([
+            ("password".to_string(), "p$1 {{x}}".to_string()),
+            ("pin".to_string(), "1234".to_string()),
+        ]);
+        assert_eq!(
+            fill(
+                "{{secret:password}}, {{ secret:pin }} and {{secret:password}}",
+                &values
+            ),
+            "p$1 {{x}}, 1234 and p$1 {{x}}"
+        );
+    }
+
+    #[test]
+    fn check_refuses_unknown_secrets() {
+        let secrets = Secrets {
+            scopes: Mutex::new(BTreeMap::from([(
+                "password".to_string(),
+                scope(Some("Safari"), None),
+            )])),
+            typed: Mutex::new(BTreeSet::new()),
+        };
+        assert!(secrets.check("{{secret:password}} and {{other}}").is_ok());
+        assert_eq!(
+            secrets.check("{{secret:password}}{{secret:pin}}"),
+            Err("Unknown secret \"pin\"".to_string())
+        );
+        assert!(secrets.typed().is_empty());
+    }
+
+    #[test]
+    fn scope_matches_app_and_title() {
+        let app = scope(Some("safari"), None);
+        assert!(app.allows(&window("Safari", "Anything")));
+        assert!(!app.allows(&window("Safari Technology Preview", "Anything")));
+
+        let title = scope(None, Some("Bank Login"));
+        assert!(title.allows(&window("Firefox", "My bank login - Firefox")));
+        assert!(!title.allows(&window("Firefox", "Bank statements")));
+
+        let both = scope(Some("Firefox"), Some("bank login"));
+        assert!(both.allows(&window("firefox", "Bank Login")));
+        assert!(!both.allows(&window("Chrome", "Bank Login")));
+        assert!(!both.allows(&window("Firefox", "Mail")));
+    }
+
+    #[test]
+    fn empty_scope_allows_nothing() {
+        assert!(!SecretScope::default().allows(&window("Safari", "Bank Login")));
+    }
+
+    #[test]
+    fn refusal_names_the_secret_and_where_it_may_go() {
+        let refused = check_scope(
+            "password",
+            &scope(Some("Safari"), None),
+            &window("Slack", "general"),
+        )
+        .unwrap_err();
+        assert_eq!(refused.message, "password may only be typed into Safari");
+        assert!(matches!(
+            refused.kind,
+            ErrorKind::SecretNotAllowed { ref secret, ref app, .. } if secret == "password" && app == "Slack"
+        ));
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{command, AppHandle, Manager, Runtime};
+
+use super::agent;
+use super::workflow::{self, substitute, Step, Workflow, WorkflowFormat, WorkflowReport};
+
+/// A named task that can be run again with different parameters, either a prompt for the agent
+/// or a workflow. Stored as one file per skill in `~/.paimon/skills`.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Skill {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Values that must be given when running the skill, filled into `{{name}}` placeholders
+    #[serde(default)]
+    pub parameters: Vec<String>,
+    /// Defaults for parameters and any other variables
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+    #[serde(default)]
+    pub prompt: Option<String>,
+    #[serde(default)]
+    pub steps: Option<Vec<Step>>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SkillSummary {
+    pub name: String,
+    pub description: Option<String>,
+    pub parameters: Vec<String>,
+    // "prompt" or "workflow"
+    pub kind: &'static str,
+    pub path: String,
+}
+
+/// How a skill ran: a workflow runs to the end here, a prompt is handed to the agent
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SkillRun {
+    Prompt { run_id: u64, prompt: String },
+    Workflow { report: WorkflowReport },
+}
+
+fn skills_dir() -> Result<PathBuf, String> {
+    let dir = crate::dot_paimon_dir().join("skills");
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create skills directory: {}", e))?;
+    Ok(dir)
+}
+
+fn format_of(path: &Path) -> Result<WorkflowFormat, String> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => Ok(WorkflowFormat::Toml),
+        Some("yaml") | Some("yml") => Ok(WorkflowFormat::Yaml),
+        _ => Err(format!("Unknown skill format: {}", path.display())),
+    }
+}
+
+fn extension(format: WorkflowFormat) -> &'static str {
+    match format {
+        WorkflowFormat::Yaml => "yaml",
+        WorkflowFormat::Toml => "toml",
+    }
+}
+
+impl Skill {
+    pub fn parse(source: &str, format: WorkflowFormat) -> Result<Skill, String> {
+        let skill: Skill = match format {
+            WorkflowFormat::Yaml => {
+                serde_yaml::from_str(source).map_err(|e| format!("Invalid skill: {}", e))?
+            }
+            WorkflowFormat::Toml => {
+                toml::from_str(source).map_err(|e| format!("Invalid skill: {}", e))?
+            }
+        };
+        if skill.name.is_empty()
+            || !skill
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!(
+                "Invalid skill name {:?}, use letters, digits, - and _",
+                skill.name
+            ));
+        }
+        if skill.prompt.is_some() == skill.steps.is_some() {
+            return Err(format!(
+                "Skill {} needs either a prompt or steps",
+                skill.name
+            ));
+        }
+        Ok(skill)
+    }
+
+    fn summary(&self, path: &Path) -> SkillSummary {
+        SkillSummary {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            parameters: self.parameters.clone(),
+            kind: if self.prompt.is_some() {
+                "prompt"
+            } else {
+                "workflow"
+            },
+            path: path.to_string_lossy().to_string(),
+        }
+    }
+
+    // The variables to run with: the defaults, then the arguments, every parameter given
+    fn variables(
+        &self,
+        arguments: HashMap<String, String>,
+    ) -> Result<HashMap<String, String>, String> {
+        let mut variables = self.variables.clone();
+        variables.extend(arguments);
+        let missing: Vec<&str> = self
+            .parameters
+            .iter()
+            .filter(|p| !variables.contains_key(*p))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!(
+                "Skill {} is missing parameters: {}",
+                self.name,
+                missing.join(", ")
+            ));
+        }
+        Ok(variables)
+    }
+}
+
+// Every skill file with what it parsed to, invalid ones are logged and left out
+fn read_skills() -> Result<Vec<(PathBuf, Skill)>, String> {
+    let entries =
+        fs::read_dir(skills_dir()?).map_err(|e| format!("Failed to list skills: {}", e))?;
+    let mut skills = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(format) = format_of(&path) else {
+            continue;
+        };
+        let parsed = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| Skill::parse(&source, format));
+        match parsed {
+            Ok(skill) => skills.push((path, skill)),
+            Err(e) => log::warn!("skills: skipping {}: {}", path.display(), e),
+        }
+    }
+    skills.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+    Ok(skills)
+}
+
+fn find_skill(name: &str) -> Result<(PathBuf, Skill), String> {
+    read_skills()?
+        .into_iter()
+        .find(|(_, skill)| skill.name == name)
+        .ok_or(format!("No skill named {}", name))
+}
+
+// Validates the source and writes it under the skill's name, replacing `previous` when renamed
+fn write_skill(
+    source: &str,
+    format: WorkflowFormat,
+    previous: Option<&Path>,
+) -> Result<SkillSummary, String> {
+    let skill = Skill::parse(source, format)?;
+    let path = skills_dir()?.join(format!("{}.{}", skill.name, extension(format)));
+    fs::write(&path, source)
+        .map_err(|e| format!("Failed to write skill {}: {}", path.display(), e))?;
+    if let Some(previous) = previous.filter(|p| *p != path) {
+        fs::remove_file(previous)
+            .map_err(|e| format!("Failed to remove {}: {}", previous.display(), e))?;
+    }
+    log::info!("skills: saved {} to {}", skill.name, path.display());
+    Ok(skill.summary(&path))
+}
+
+fn ensure_new(name: &str) -> Result<(), String> {
+    if read_skills()?.iter().any(|(_, skill)| skill.name == name) {
+        return Err(format!("A skill named {} already exists", name));
+    }
+    Ok(())
+}
+
+#[command]
+pub fn list_skills() -> Result<Vec<SkillSummary>, String> {
+    Ok(read_skills()?
+        .iter()
+        .map(|(path, skill)| skill.summary(path))
+        .collect())
+}
+
+/// The source of a skill, for editing
+#[command]
+pub fn get_skill(name: String) -> Result<String, String> {
+    let (path, _) = find_skill(&name)?;
+    fs::read_to_string(&path).map_err(|e| format!("Failed to read skill {}: {}", name, e))
+}
+
+#[command]
+pub fn create_skill(
+    source: String,
+    format: Option<WorkflowFormat>,
+) -> Result<SkillSummary, String> {
+    let format = format.unwrap_or(WorkflowFormat::Yaml);
+    ensure_new(&Skill::parse(&source, format)?.name)?;
+    write_skill(&source, format, None)
+}
+
+/// Replaces a skill's source, which may also rename it
+#[command]
+pub fn edit_skill(
+    name: String,
+    source: String,
+    format: Option<WorkflowFormat>,
+) -> Result<SkillSummary, String> {
+    let (previous, _) = find_skill(&name)?;
+    let format = match format {
+        Some(format) => format,
+        None => format_of(&previous)?,
+    };
+    let renamed = Skill::parse(&source, format)?.name;
+    if renamed != name {
+        ensure_new(&renamed)?;
+    }
+    write_skill(&source, format, Some(&previous))
+}
+
+/// Copies a skill file into the library
+#[command]
+pub fn import_skill(path: String, overwrite: Option<bool>) -> Result<SkillSummary, String> {
+    let path = PathBuf::from(path);
+    let format = format_of(&path)?;
+    let source = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read skill {}: {}", path.display(), e))?;
+    let name = Skill::parse(&source, format)?.name;
+    match find_skill(&name) {
+        Ok(_) if !overwrite.unwrap_or(false) => {
+            Err(format!("A skill named {} already exists", name))
+        }
+        Ok((previous, _)) => write_skill(&source, format, Some(&previous)),
+        Err(_) => write_skill(&source, format, None),
+    }
+}
+
+/// Copies a skill file out of the library
+#[command]
+pub fn export_skill(name: String, path: String) -> Result<(), String> {
+    let (source, _) = find_skill(&name)?;
+    fs::copy(&source, &path)
+        .map(|_| ())
+        .map_err(|e| format!("Failed to export skill {} to {}: {}", name, path, e))
+}
+
+/// Runs a skill with its parameters. Workflows run to the end, prompts start an agent run
+/// like any typed task and return once it has started.
+#[command]
+pub async fn run_skill<R: Runtime>(
+    handle: AppHandle<R>,
+    name: String,
+    arguments: Option<HashMap<String, String>>,
+) -> Result<SkillRun, String> {
+    let (_, skill) = find_skill(&name)?;
+    let variables = skill.variables(arguments.unwrap_or_default())?;
+    log::info!("skills: running {}", skill.name);
+    match (skill.prompt, skill.steps) {
+        (Some(prompt), _) => {
+            let prompt = substitute(&prompt, &variables)?;
+            let run_id = agent::start_agent(handle.clone(), handle.state(), prompt.clone(), None)?;
+            Ok(SkillRun::Prompt { run_id, prompt })
+        }
+        (None, Some(steps)) => {
+            let workflow = Workflow {
+                name: skill.name,
+                description: skill.description,
+                variables: HashMap::new(),
+                steps,
+            };
+            let report = workflow::run(&handle, &workflow, variables).await;
+            Ok(SkillRun::Workflow { report })
+        }
+        (None, None) => Err(format!("Skill {} has nothing to run", name)),
+    }
+}
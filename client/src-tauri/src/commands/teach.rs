@@ -0,0 +1,764 @@
+use chrono::{DateTime, Utc};
+use rdev::{Button, Event, EventType, Key};
+use serde_json::json;
+use std::io::Write;
+#[cfg(target_os = "macos")]
+use std::io::{BufRead, BufReader};
+#[cfg(target_os = "macos")]
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+use tauri::{async_runtime, command, AppHandle, Emitter, Manager, Runtime, State};
+
+use super::action::{Action, ActionResult};
+use super::error::ErrorKind;
+use super::image::{action_scale, capture_screen};
+use super::journal::{append, store_screenshot, timestamp, JournalEntry};
+use super::privacy::check_privacy;
+use super::redact::redact;
+use super::windowing::password_field_focused;
+
+// Starts the app as the helper process the input hook runs in, see `run_input_hook`
+const INPUT_HOOK_ARG: &str = "--input-hook";
+const SPOTLIGHT_WINDOW: &str = "spotlight";
+// Presses and releases closer than this are a click, further apart a drag
+const CLICK_DISTANCE: f64 = 5.0;
+// Drag paths keep a point every this far
+const PATH_SPACING: f64 = 8.0;
+const DOUBLE_CLICK_MS: u128 = 500;
+const MAX_NAME_LENGTH: usize = 48;
+
+enum Message {
+    Input(Event),
+    Stop(mpsc::Sender<TeachSummary>),
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TeachSummary {
+    /// The task the demonstration is journaled under, for `replay_journal` and `query_journal`
+    pub task_id: String,
+    pub actions: usize,
+}
+
+/// Records the user's own input as journal entries, in the format the actions are replayed from.
+/// The input hook is installed on first use and stays, events are dropped while nothing records.
+#[derive(Default)]
+pub struct Teacher {
+    recorder: RecorderSlot,
+    listening: AtomicBool,
+}
+
+// A screenshot being taken on a worker, so capturing, redacting and reading text doesn't hold up
+// the input that follows. Several entries can share it.
+struct Screenshot {
+    worker: Mutex<Option<JoinHandle<Option<String>>>>,
+    id: OnceLock<Option<String>>,
+}
+
+impl Screenshot {
+    fn take<R: Runtime>(handle: &AppHandle<R>) -> Arc<Screenshot> {
+        let handle = handle.clone();
+        Arc::new(Screenshot {
+            worker: Mutex::new(Some(thread::spawn(move || screenshot(&handle)))),
+            id: OnceLock::new(),
+        })
+    }
+
+    // Waits for the worker the first time
+    fn id(&self) -> Option<String> {
+        self.id
+            .get_or_init(|| {
+                let worker = self.worker.lock().unwrap().take();
+                worker.and_then(|worker| worker.join().ok()).flatten()
+            })
+            .clone()
+    }
+}
+
+// A redacted screenshot for the journal, none while a blocked app is on screen
+fn screenshot<R: Runtime>(handle: &AppHandle<R>) -> Option<String> {
+    let mut capture = match capture_screen(handle) {
+        Ok(capture) => capture,
+        Err(e) if matches!(e.kind, ErrorKind::PrivacyBlocked { .. }) => return None,
+        Err(e) => {
+            log::warn!("teach: failed to take screenshot: {}", e);
+            return None;
+        }
+    };
+    let result = redact(handle, &mut capture).and_then(|_| store_screenshot(&capture.image));
+    result
+        .inspect_err(|e| log::warn!("teach: failed to take screenshot: {}", e))
+        .ok()
+}
+
+// The last entry, held back in case the next input extends it, like a second click or more text
+struct Pending {
+    entry: JournalEntry,
+    screenshot: Option<Arc<Screenshot>>,
+    started: SystemTime,
+    finished: SystemTime,
+}
+
+struct Press {
+    button: &'static str,
+    at: SystemTime,
+    path: Vec<(f64, f64)>,
+}
+
+struct Recorder<R: Runtime> {
+    handle: AppHandle<R>,
+    task_id: String,
+    position: (f64, f64),
+    modifiers: Vec<&'static str>,
+    press: Option<Press>,
+    pending: Option<Pending>,
+    screenshot: Option<Arc<Screenshot>>,
+    actions: usize,
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+fn button_name(button: Button) -> &'static str {
+    match button {
+        Button::Right => "right",
+        Button::Middle => "middle",
+        _ => "left",
+    }
+}
+
+fn modifier_name(key: Key) -> Option<&'static str> {
+    match key {
+        Key::ShiftLeft | Key::ShiftRight => Some("shift"),
+        Key::ControlLeft | Key::ControlRight => Some("control"),
+        Key::Alt | Key::AltGr => Some("alt"),
+        Key::MetaLeft | Key::MetaRight => Some("meta"),
+        _ => None,
+    }
+}
+
+// Names a key the way `parse_key` understands it
+fn key_name(key: Key, typed: Option<&str>) -> Option<String> {
+    let name = match key {
+        Key::Backspace => "backspace",
+        Key::CapsLock => "capslock",
+        Key::Delete | Key::KpDelete => "delete",
+        Key::DownArrow => "down",
+        Key::End => "end",
+        Key::Escape => "escape",
+        Key::F1 => "f1",
+        Key::F2 => "f2",
+        Key::F3 => "f3",
+        Key::F4 => "f4",
+        Key::F5 => "f5",
+        Key::F6 => "f6",
+        Key::F7 => "f7",
+        Key::F8 => "f8",
+        Key::F9 => "f9",
+        Key::F10 => "f10",
+        Key::F11 => "f11",
+        Key::F12 => "f12",
+        Key::Home => "home",
+        Key::LeftArrow => "left",
+        Key::PageDown => "pagedown",
+        Key::PageUp => "pageup",
+        Key::Return | Key::KpReturn => "enter",
+        Key::RightArrow => "right",
+        Key::Space => "space",
+        Key::Tab => "tab",
+        Key::UpArrow => "up",
+        other => {
+            // Letters and digits by their position, so modifiers don't change them
+            let debug = format!("{:?}", other);
+            let position = debug
+                .strip_prefix("Key")
+                .or(debug.strip_prefix("Num"))
+                .filter(|c| c.len() == 1)
+                .map(str::to_lowercase);
+            return position.or_else(|| {
+                typed
+                    .filter(|t| t.chars().count() == 1 && !t.chars().any(char::is_control))
+                    .map(str::to_string)
+            });
+        }
+    };
+    Some(name.to_string())
+}
+
+// Merges a new entry into the one held back, when it continues it
+fn merge(pending: &Pending, entry: &JournalEntry, at: SystemTime) -> Option<Action> {
+    match (&pending.entry.action, &entry.action) {
+        (Action::TypeText { text }, Action::TypeText { text: more }) => Some(Action::TypeText {
+            text: format!("{}{}", text, more),
+        }),
+        (
+            Action::Scroll {
+                x,
+                y,
+                scroll_x,
+                scroll_y,
+            },
+            Action::Scroll {
+                x: x2,
+                y: y2,
+                scroll_x: more_x,
+                scroll_y: more_y,
+            },
+        ) if distance((*x, *y), (*x2, *y2)) < CLICK_DISTANCE => Some(Action::Scroll {
+            x: *x,
+            y: *y,
+            scroll_x: scroll_x + more_x,
+            scroll_y: scroll_y + more_y,
+        }),
+        (Action::Click { button, x, y }, Action::Click { button: second, .. })
+            if button == "left"
+                && second == "left"
+                && entry
+                    .cursor_before
+                    .is_some_and(|p| distance(p, (*x, *y)) < CLICK_DISTANCE)
+                && at
+                    .duration_since(pending.finished)
+                    .is_ok_and(|gap| gap.as_millis() < DOUBLE_CLICK_MS) =>
+        {
+            Some(Action::DoubleClick { x: *x, y: *y })
+        }
+        _ => None,
+    }
+}
+
+impl<R: Runtime> Recorder<R> {
+    // Typing into the spotlight window is talking to Paimon, not part of the demonstration
+    fn spotlight_focused(&self) -> bool {
+        self.handle
+            .get_webview_window(SPOTLIGHT_WINDOW)
+            .is_some_and(|window| window.is_focused().unwrap_or(false))
+    }
+
+    // Likewise for the pointer, like clicking the button that stops teaching
+    fn over_spotlight(&self) -> bool {
+        let Some(window) = self.handle.get_webview_window(SPOTLIGHT_WINDOW) else {
+            return false;
+        };
+        let (Ok(position), Ok(size), Ok(scale)) = (
+            window.outer_position(),
+            window.outer_size(),
+            window.scale_factor(),
+        ) else {
+            return false;
+        };
+        let scale = action_scale(scale);
+        let (x, y) = self.position;
+        x >= position.x as f64 / scale
+            && y >= position.y as f64 / scale
+            && x < (position.x as f64 + size.width as f64) / scale
+            && y < (position.y as f64 + size.height as f64) / scale
+    }
+
+    fn flush(&mut self) {
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+        self.actions += 1;
+        let entry = JournalEntry {
+            step_id: Some(self.actions.to_string()),
+            screenshot_id: pending.screenshot.and_then(|screenshot| screenshot.id()),
+            ..pending.entry
+        };
+        if let Err(e) = append(&entry) {
+            log::warn!("teach: failed to record {}: {}", entry.action.name(), e);
+        }
+        let _ = self.handle.emit(
+            "agent_teach_step",
+            json!({ "task_id": self.task_id, "index": self.actions, "action": entry.action }),
+        );
+    }
+
+    fn add(
+        &mut self,
+        action: Action,
+        started: SystemTime,
+        finished: SystemTime,
+        before: (f64, f64),
+    ) {
+        let entry = JournalEntry {
+            task_id: Some(self.task_id.clone()),
+            step_id: None,
+            action,
+            started_at: timestamp(DateTime::<Utc>::from(started)),
+            finished_at: timestamp(DateTime::<Utc>::from(finished)),
+            duration_ms: finished
+                .duration_since(started)
+                .map_or(0, |d| d.as_millis() as u64),
+            result: Some(ActionResult::Executed),
+            error: None,
+            cursor_before: Some(before),
+            cursor_after: Some(self.position),
+            // filled in when the entry is written, the screenshot may still be in the works
+            screenshot_id: None,
+        };
+        if let Some(pending) = &mut self.pending {
+            if let Some(merged) = merge(pending, &entry, started) {
+                pending.entry.action = merged;
+                pending.entry.finished_at = entry.finished_at;
+                pending.entry.duration_ms = finished
+                    .duration_since(pending.started)
+                    .map_or(0, |d| d.as_millis() as u64);
+                pending.entry.cursor_after = entry.cursor_after;
+                pending.finished = finished;
+                return;
+            }
+        }
+        self.flush();
+        self.pending = Some(Pending {
+            entry,
+            screenshot: self.screenshot.clone(),
+            started,
+            finished,
+        });
+    }
+
+    fn handle_event(&mut self, event: Event) {
+        match event.event_type {
+            EventType::MouseMove { x, y } => {
+                self.position = (x, y);
+                if let Some(press) = &mut self.press {
+                    if press
+                        .path
+                        .last()
+                        .is_none_or(|last| distance(*last, (x, y)) >= PATH_SPACING)
+                    {
+                        press.path.push((x, y));
+                    }
+                }
+            }
+            EventType::ButtonPress(button) => {
+                if self.over_spotlight() {
+                    return;
+                }
+                // Clicks in a blocked app aren't recorded either, nor is the release that follows
+                if check_privacy(&self.handle).is_err() {
+                    return;
+                }
+                // The screen the user decided to click on, shared by the input that follows
+                self.screenshot = Some(Screenshot::take(&self.handle));
+                self.press = Some(Press {
+                    button: button_name(button),
+                    at: event.time,
+                    path: vec![self.position],
+                });
+            }
+            EventType::ButtonRelease(_) => {
+                let Some(mut press) = self.press.take() else {
+                    return;
+                };
+                let start = press.path[0];
+                let action =
+                    if press.button == "left" && distance(start, self.position) >= CLICK_DISTANCE {
+                        press.path.push(self.position);
+                        Action::Drag { path: press.path }
+                    } else {
+                        Action::Click {
+                            button: press.button.to_string(),
+                            x: start.0,
+                            y: start.1,
+                        }
+                    };
+                self.add(action, press.at, event.time, start);
+            }
+            EventType::Wheel { delta_x, delta_y } => {
+                if self.over_spotlight() || check_privacy(&self.handle).is_err() {
+                    return;
+                }
+                let (x, y) = self.position;
+                // Wheels report notches with up as positive, positive scroll amounts go down
+                let action = Action::Scroll {
+                    x,
+                    y,
+                    scroll_x: delta_x as f64,
+                    scroll_y: -delta_y as f64,
+                };
+                self.add(action, event.time, event.time, self.position);
+            }
+            EventType::KeyPress(key) => {
+                if let Some(modifier) = modifier_name(key) {
+                    if !self.modifiers.contains(&modifier) {
+                        self.modifiers.push(modifier);
+                    }
+                    return;
+                }
+                if self.spotlight_focused() {
+                    return;
+                }
+                // Never record keys while a blocked app is on screen
+                if check_privacy(&self.handle).is_err() {
+                    return;
+                }
+                let shortcut = self.modifiers.iter().any(|m| *m != "shift");
+                let text = event
+                    .name
+                    .as_deref()
+                    .filter(|t| !t.is_empty() && !t.chars().any(char::is_control));
+                let action = match (shortcut, text) {
+                    // Nor what goes into a password field. The text typed before ends there, so
+                    // what comes after isn't joined to it.
+                    (false, Some(_)) if password_field_focused() => {
+                        log::debug!("teach: skipping a key typed into a password field");
+                        self.flush();
+                        return;
+                    }
+                    (false, Some(text)) => Action::TypeText {
+                        text: text.to_string(),
+                    },
+                    _ => {
+                        let Some(name) = key_name(key, event.name.as_deref()) else {
+                            log::warn!("teach: skipping unsupported key {:?}", key);
+                            return;
+                        };
+                        let mut keys: Vec<String> =
+                            self.modifiers.iter().map(|m| m.to_string()).collect();
+                        keys.push(name);
+                        Action::Keypress { keys }
+                    }
+                };
+                self.add(action, event.time, event.time, self.position);
+            }
+            EventType::KeyRelease(key) => {
+                if let Some(modifier) = modifier_name(key) {
+                    self.modifiers.retain(|m| *m != modifier);
+                }
+            }
+        }
+    }
+
+    fn run(mut self, receiver: mpsc::Receiver<Message>) {
+        while let Ok(message) = receiver.recv() {
+            match message {
+                Message::Input(event) => self.handle_event(event),
+                Message::Stop(reply) => {
+                    self.flush();
+                    let _ = reply.send(TeachSummary {
+                        task_id: self.task_id.clone(),
+                        actions: self.actions,
+                    });
+                    return;
+                }
+            }
+        }
+    }
+}
+
+type RecorderSlot = Arc<Mutex<Option<mpsc::Sender<Message>>>>;
+
+fn forward(recorder: &RecorderSlot, event: Event) {
+    if let Some(sender) = recorder.lock().unwrap().as_ref() {
+        let _ = sender.send(Message::Input(event));
+    }
+}
+
+/// When the app was started as teaching's helper process, runs the input hook and writes its
+/// events to stdout as JSON lines until the app goes away. Returns false for a normal start.
+pub fn run_input_hook() -> bool {
+    if !std::env::args().any(|arg| arg == INPUT_HOOK_ARG) {
+        return false;
+    }
+    let result = rdev::listen(|event| {
+        let Ok(line) = serde_json::to_string(&event) else {
+            return;
+        };
+        let mut stdout = std::io::stdout().lock();
+        // the app closed its end of the pipe
+        if writeln!(stdout, "{}", line)
+            .and_then(|_| stdout.flush())
+            .is_err()
+        {
+            std::process::exit(0);
+        }
+    });
+    std::process::exit(if result.is_ok() { 0 } else { 1 });
+}
+
+// macOS only translates keys on the main thread, which the app's event loop owns, so the hook
+// runs on the main thread of a helper process instead
+#[cfg(target_os = "macos")]
+fn listen_to_input(recorder: RecorderSlot) {
+    let child = std::env::current_exe().and_then(|exe| {
+        Command::new(exe)
+            .arg(INPUT_HOOK_ARG)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+    });
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            log::error!("teach: failed to start the input hook: {}", e);
+            return;
+        }
+    };
+    let Some(stdout) = child.stdout.take() else {
+        return;
+    };
+    for line in BufReader::new(stdout).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        match serde_json::from_str(&line) {
+            Ok(event) => forward(&recorder, event),
+            Err(e) => log::warn!("teach: invalid input event: {}", e),
+        }
+    }
+    log::error!("teach: the input hook stopped: {:?}", child.wait());
+}
+
+#[cfg(not(target_os = "macos"))]
+fn listen_to_input(recorder: RecorderSlot) {
+    if let Err(e) = rdev::listen(move |event| forward(&recorder, event)) {
+        log::error!("teach: failed to listen to input: {:?}", e);
+    }
+}
+
+impl Teacher {
+    // Installs the global input hook, which can't be removed again
+    fn listen(&self) {
+        if self.listening.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let recorder = self.recorder.clone();
+        thread::spawn(move || listen_to_input(recorder));
+    }
+}
+
+// Keeps a name to letters, digits, - and _ so task ids stay safe in file names and queries
+fn task_name(name: &str) -> String {
+    let name: String = name
+        .trim()
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+            _ => '-',
+        })
+        .take(MAX_NAME_LENGTH)
+        .collect();
+    name.trim_matches('-').to_string()
+}
+
+/// Starts recording the user's mouse and keyboard input under a new task, named after `name` when given.
+/// Emits `agent_teach_step` for each recorded action.
+#[command]
+pub fn start_teaching<R: Runtime>(
+    handle: AppHandle<R>,
+    teacher: State<'_, Teacher>,
+    name: Option<String>,
+) -> Result<String, String> {
+    let mut current = teacher.recorder.lock().unwrap();
+    if current.is_some() {
+        return Err("Already recording a demonstration".to_string());
+    }
+    let stamp = Utc::now().format("%Y%m%dT%H%M%S");
+    let task_id = match name.as_deref().map(task_name) {
+        Some(name) if !name.is_empty() => format!("teach-{}-{}", name, stamp),
+        _ => format!("teach-{}", stamp),
+    };
+    log::info!("teach: recording demonstration {}", task_id);
+
+    let (sender, receiver) = mpsc::channel();
+    let recorder = Recorder {
+        handle: handle.clone(),
+        task_id: task_id.clone(),
+        position: (0.0, 0.0),
+        modifiers: Vec::new(),
+        press: None,
+        pending: None,
+        screenshot: None,
+        actions: 0,
+    };
+    thread::spawn(move || recorder.run(receiver));
+    *current = Some(sender);
+    drop(current);
+    teacher.listen();
+    let _ = handle.emit("agent_teach_started", json!({ "task_id": task_id }));
+    Ok(task_id)
+}
+
+/// Stops recording and returns where the demonstration was journaled.
+/// Async so the recorder can still reach the windows while it finishes.
+#[command]
+pub async fn stop_teaching<R: Runtime>(
+    handle: AppHandle<R>,
+    teacher: State<'_, Teacher>,
+) -> Result<TeachSummary, String> {
+    let sender = teacher
+        .recorder
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or("Not recording a demonstration")?;
+    let (reply, summary) = mpsc::channel();
+    sender
+        .send(Message::Stop(reply))
+        .map_err(|_| "The recorder stopped unexpectedly")?;
+    let summary =
+        async_runtime::spawn_blocking(move || summary.recv_timeout(Duration::from_secs(5)))
+            .await
+            .map_err(|e| format!("Failed to stop teaching: {}", e))?
+            .map_err(|_| "The recorder stopped unexpectedly")?;
+    log::info!(
+        "teach: recorded {} actions as {}",
+        summary.actions,
+        summary.task_id
+    );
+    let _ = handle.emit("agent_teach_stopped", &summary);
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(ms: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_000 + ms)
+    }
+
+    fn entry(action: Action, cursor_before: (f64, f64)) -> JournalEntry {
+        JournalEntry {
+            task_id: Some("teach-test".to_string()),
+            step_id: None,
+            action,
+            started_at: String::new(),
+            finished_at: String::new(),
+            duration_ms: 0,
+            result: Some(ActionResult::Executed),
+            error: None,
+            cursor_before: Some(cursor_before),
+            cursor_after: Some(cursor_before),
+            screenshot_id: None,
+        }
+    }
+
+    fn pending(action: Action, finished: SystemTime) -> Pending {
+        Pending {
+            entry: entry(action, (0.0, 0.0)),
+            screenshot: None,
+            started: finished,
+            finished,
+        }
+    }
+
+    fn click(button: &str, x: f64, y: f64) -> Action {
+        Action::Click {
+            button: button.to_string(),
+            x,
+            y,
+        }
+    }
+
+    fn scroll(x: f64, y: f64, scroll_y: f64) -> Action {
+        Action::Scroll {
+            x,
+            y,
+            scroll_x: 0.0,
+            scroll_y,
+        }
+    }
+
+    #[test]
+    fn typing_continues_the_text() {
+        let typed = pending(
+            Action::TypeText {
+                text: "hel".to_string(),
+            },
+            at(0),
+        );
+        let more = entry(
+            Action::TypeText {
+                text: "lo".to_string(),
+            },
+            (0.0, 0.0),
+        );
+        assert_eq!(
+            merge(&typed, &more, at(5000)),
+            Some(Action::TypeText {
+                text: "hello".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn scrolling_in_place_adds_up() {
+        let scrolled = pending(scroll(100.0, 100.0, 3.0), at(0));
+        assert_eq!(
+            merge(
+                &scrolled,
+                &entry(scroll(102.0, 101.0, 2.0), (0.0, 0.0)),
+                at(100)
+            ),
+            Some(scroll(100.0, 100.0, 5.0))
+        );
+        assert_eq!(
+            merge(
+                &scrolled,
+                &entry(scroll(300.0, 100.0, 2.0), (0.0, 0.0)),
+                at(100)
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn two_quick_left_clicks_in_place_are_a_double_click() {
+        let clicked = pending(click("left", 50.0, 60.0), at(0));
+        let second = entry(click("left", 51.0, 60.0), (51.0, 60.0));
+        assert_eq!(
+            merge(&clicked, &second, at(200)),
+            Some(Action::DoubleClick { x: 50.0, y: 60.0 })
+        );
+        // too slow, too far away, or not the left button
+        assert_eq!(merge(&clicked, &second, at(800)), None);
+        let elsewhere = entry(click("left", 90.0, 60.0), (90.0, 60.0));
+        assert_eq!(merge(&clicked, &elsewhere, at(200)), None);
+        let right = pending(click("right", 50.0, 60.0), at(0));
+        let second_right = entry(click("right", 50.0, 60.0), (50.0, 60.0));
+        assert_eq!(merge(&right, &second_right, at(200)), None);
+    }
+
+    #[test]
+    fn different_actions_stay_apart() {
+        let typed = pending(
+            Action::TypeText {
+                text: "a".to_string(),
+            },
+            at(0),
+        );
+        assert_eq!(
+            merge(&typed, &entry(click("left", 0.0, 0.0), (0.0, 0.0)), at(10)),
+            None
+        );
+    }
+
+    #[test]
+    fn names_keys_like_parse_key() {
+        assert_eq!(key_name(Key::Return, None).as_deref(), Some("enter"));
+        assert_eq!(key_name(Key::KpReturn, None).as_deref(), Some("enter"));
+        assert_eq!(key_name(Key::UpArrow, None).as_deref(), Some("up"));
+        assert_eq!(key_name(Key::F5, None).as_deref(), Some("f5"));
+        // letters and digits by position, whatever the modifiers made of them
+        assert_eq!(key_name(Key::KeyA, Some("Å")).as_deref(), Some("a"));
+        assert_eq!(key_name(Key::Num1, Some("!")).as_deref(), Some("1"));
+        // other keys by what they typed, when that is a single printable character
+        assert_eq!(key_name(Key::Comma, Some(",")).as_deref(), Some(","));
+        assert_eq!(key_name(Key::Comma, None), None);
+        assert_eq!(key_name(Key::Unknown(42), Some("\u{1b}")), None);
+        assert_eq!(key_name(Key::NumLock, None), None);
+    }
+
+    #[test]
+    fn task_names_are_kept_safe() {
+        assert_eq!(task_name("Book a flight"), "Book-a-flight");
+        assert_eq!(task_name("  ../../etc/passwd "), "etc-passwd");
+        assert_eq!(task_name("日本"), "");
+        assert_eq!(task_name(&"x".repeat(100)).len(), MAX_NAME_LENGTH);
+    }
+}
@@ -0,0 +1,448 @@
+use image::{imageops::FilterType, DynamicImage, GrayImage, ImageReader};
+use std::path::PathBuf;
+use tauri::{async_runtime, command, AppHandle, Runtime};
+
+use super::error::CommandError;
+use super::image::{capture_path, capture_screen, Capture, Region};
+
+// Matching is done on a pyramid level where the template is about this small,
+// then refined at full resolution around the candidates
+const COARSE_TEMPLATE_SIZE: u32 = 16;
+// Candidates at the coarse level only need to come close to the requested threshold
+const COARSE_THRESHOLD_RATIO: f64 = 0.8;
+const DEFAULT_THRESHOLD: f64 = 0.9;
+const DEFAULT_SCALES: [f64; 5] = [0.5, 0.75, 1.0, 1.5, 2.0];
+
+/// Where a reference image comes from
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageSource {
+    /// Any image file on disk
+    File(String),
+    /// A capture previously stored with save_capture
+    Capture(String),
+}
+
+impl ImageSource {
+    pub fn load(&self) -> Result<DynamicImage, String> {
+        let path = match self {
+            ImageSource::File(path) => PathBuf::from(path),
+            ImageSource::Capture(name) => capture_path(name)?,
+        };
+        ImageReader::open(&path)
+            .map_err(|e| format!("Failed to open image {}: {}", path.display(), e))?
+            .decode()
+            .map_err(|e| format!("Failed to decode image {}: {}", path.display(), e))
+    }
+}
+
+/// A place on screen where the reference image was found, in action coordinates
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ImageMatch {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub center_x: f64,
+    pub center_y: f64,
+    pub confidence: f64,
+    pub scale: f64,
+}
+
+// Summed-area tables for fast window sums of a grayscale image and its square
+struct Integral {
+    width: usize,
+    sum: Vec<f64>,
+    sum_sq: Vec<f64>,
+}
+
+impl Integral {
+    fn new(image: &GrayImage) -> Self {
+        let width = image.width() as usize + 1;
+        let height = image.height() as usize + 1;
+        let mut sum = vec![0.0; width * height];
+        let mut sum_sq = vec![0.0; width * height];
+        for y in 1..height {
+            let mut row = 0.0;
+            let mut row_sq = 0.0;
+            for x in 1..width {
+                let value = image.get_pixel(x as u32 - 1, y as u32 - 1).0[0] as f64;
+                row += value;
+                row_sq += value * value;
+                sum[y * width + x] = sum[(y - 1) * width + x] + row;
+                sum_sq[y * width + x] = sum_sq[(y - 1) * width + x] + row_sq;
+            }
+        }
+        Integral { width, sum, sum_sq }
+    }
+
+    fn window(&self, table: &[f64], x: u32, y: u32, w: u32, h: u32) -> f64 {
+        let (x, y, w, h) = (x as usize, y as usize, w as usize, h as usize);
+        table[(y + h) * self.width + x + w]
+            - table[y * self.width + x + w]
+            - table[(y + h) * self.width + x]
+            + table[y * self.width + x]
+    }
+}
+
+// A template with its mean removed, ready for zero-normalized cross-correlation
+struct Template {
+    width: u32,
+    height: u32,
+    centered: Vec<f64>,
+    norm: f64,
+}
+
+impl Template {
+    fn new(image: &GrayImage) -> Option<Self> {
+        let n = (image.width() * image.height()) as f64;
+        let mean = image.pixels().map(|p| p.0[0] as f64).sum::<f64>() / n;
+        let centered: Vec<f64> = image.pixels().map(|p| p.0[0] as f64 - mean).collect();
+        let norm = centered.iter().map(|v| v * v).sum::<f64>().sqrt();
+        // A flat template correlates equally with everything
+        if norm < 1e-6 {
+            return None;
+        }
+        Some(Template {
+            width: image.width(),
+            height: image.height(),
+            centered,
+            norm,
+        })
+    }
+
+    // Zero-normalized cross-correlation of the template placed at (x, y), in [-1, 1]
+    fn score(&self, image: &GrayImage, integral: &Integral, x: u32, y: u32) -> f64 {
+        let n = (self.width * self.height) as f64;
+        let sum = integral.window(&integral.sum, x, y, self.width, self.height);
+        let sum_sq = integral.window(&integral.sum_sq, x, y, self.width, self.height);
+        let variance = sum_sq - sum * sum / n;
+        if variance < 1e-6 {
+            return 0.0;
+        }
+
+        let mut cross = 0.0;
+        for ty in 0..self.height {
+            let row = (ty * self.width) as usize;
+            for tx in 0..self.width {
+                let value = image.get_pixel(x + tx, y + ty).0[0] as f64;
+                cross += value * self.centered[row + tx as usize];
+            }
+        }
+        cross / (variance.sqrt() * self.norm)
+    }
+}
+
+fn resize(image: &GrayImage, factor: f64) -> GrayImage {
+    let width = ((image.width() as f64 * factor).round() as u32).max(1);
+    let height = ((image.height() as f64 * factor).round() as u32).max(1);
+    image::imageops::resize(image, width, height, FilterType::Triangle)
+}
+
+/// Finds every placement of `needle` in `haystack` scoring at least `threshold`,
+/// as (x, y, score) in haystack pixels, best first and without overlaps
+pub fn match_template(
+    haystack: &GrayImage,
+    needle: &GrayImage,
+    threshold: f64,
+) -> Vec<(u32, u32, f64)> {
+    if needle.width() > haystack.width() || needle.height() > haystack.height() {
+        return Vec::new();
+    }
+    let Some(template) = Template::new(needle) else {
+        return Vec::new();
+    };
+
+    // Small templates are cheap enough to match directly
+    let factor = COARSE_TEMPLATE_SIZE as f64 / needle.width().min(needle.height()) as f64;
+    if factor >= 1.0 {
+        let matches = local_maxima(haystack, &template, threshold);
+        return suppress_overlaps(matches, needle.width(), needle.height(), threshold);
+    }
+
+    // Otherwise search everywhere on a downsampled pyramid level first, keeping local maxima as candidates
+    let Some(coarse_template) = Template::new(&resize(needle, factor)) else {
+        return Vec::new();
+    };
+    let candidates = local_maxima(
+        &resize(haystack, factor),
+        &coarse_template,
+        threshold * COARSE_THRESHOLD_RATIO,
+    );
+
+    // Refine each candidate at full resolution within one coarse pixel of where it was found
+    let integral = Integral::new(haystack);
+    let radius = (1.0 / factor).ceil() as u32;
+    let max_x = haystack.width() - needle.width();
+    let max_y = haystack.height() - needle.height();
+    let mut matches: Vec<(u32, u32, f64)> = Vec::new();
+    for (cx, cy, _) in candidates {
+        let cx = (cx as f64 / factor) as u32;
+        let cy = (cy as f64 / factor) as u32;
+        let mut best: Option<(u32, u32, f64)> = None;
+        for y in cy.saturating_sub(radius)..=(cy + radius).min(max_y) {
+            for x in cx.saturating_sub(radius)..=(cx + radius).min(max_x) {
+                let score = template.score(haystack, &integral, x, y);
+                if best.is_none_or(|(_, _, s)| score > s) {
+                    best = Some((x, y, score));
+                }
+            }
+        }
+        matches.extend(best);
+    }
+    suppress_overlaps(matches, needle.width(), needle.height(), threshold)
+}
+
+// Scores every placement of the template and returns those that beat their 8 neighbours
+fn local_maxima(image: &GrayImage, template: &Template, threshold: f64) -> Vec<(u32, u32, f64)> {
+    if template.width > image.width() || template.height > image.height() {
+        return Vec::new();
+    }
+    let integral = Integral::new(image);
+    let cols = image.width() - template.width + 1;
+    let rows = image.height() - template.height + 1;
+    let mut scores = vec![0.0; (cols * rows) as usize];
+    for y in 0..rows {
+        for x in 0..cols {
+            scores[(y * cols + x) as usize] = template.score(image, &integral, x, y);
+        }
+    }
+
+    let mut maxima = Vec::new();
+    for y in 0..rows {
+        for x in 0..cols {
+            let score = scores[(y * cols + x) as usize];
+            if score < threshold {
+                continue;
+            }
+            let is_max = (y.saturating_sub(1)..=(y + 1).min(rows - 1)).all(|ny| {
+                (x.saturating_sub(1)..=(x + 1).min(cols - 1))
+                    .all(|nx| scores[(ny * cols + nx) as usize] <= score)
+            });
+            if is_max {
+                maxima.push((x, y, score));
+            }
+        }
+    }
+    maxima
+}
+
+// Drops matches under the threshold and, among matches overlapping by more than
+// half the template, keeps only the best one
+fn suppress_overlaps(
+    mut matches: Vec<(u32, u32, f64)>,
+    width: u32,
+    height: u32,
+    threshold: f64,
+) -> Vec<(u32, u32, f64)> {
+    matches.retain(|m| m.2 >= threshold);
+    matches.sort_by(|a, b| b.2.total_cmp(&a.2));
+    let mut kept: Vec<(u32, u32, f64)> = Vec::new();
+    for m in matches {
+        let overlaps = kept
+            .iter()
+            .any(|k| k.0.abs_diff(m.0) < width / 2 && k.1.abs_diff(m.1) < height / 2);
+        if !overlaps {
+            kept.push(m);
+        }
+    }
+    kept
+}
+
+/// Searches a capture for the reference image at several scales
+pub fn find_image(
+    capture: &Capture,
+    reference: &DynamicImage,
+    region: Option<&Region>,
+    scales: &[f64],
+    threshold: f64,
+) -> Vec<ImageMatch> {
+    let (haystack, offset_x, offset_y) = match region {
+        Some(region) => (
+            capture.crop(region).to_luma8(),
+            region.x.max(0.0),
+            region.y.max(0.0),
+        ),
+        None => (capture.image.to_luma8(), 0.0, 0.0),
+    };
+    let reference = reference.to_luma8();
+
+    let mut matches: Vec<ImageMatch> = Vec::new();
+    for &scale in scales {
+        let needle = if scale == 1.0 {
+            reference.clone()
+        } else {
+            resize(&reference, scale)
+        };
+        for (x, y, confidence) in match_template(&haystack, &needle, threshold) {
+            let width = needle.width() as f64 / capture.scale;
+            let height = needle.height() as f64 / capture.scale;
+            let x = offset_x + x as f64 / capture.scale;
+            let y = offset_y + y as f64 / capture.scale;
+            matches.push(ImageMatch {
+                x,
+                y,
+                width,
+                height,
+                center_x: x + width / 2.0,
+                center_y: y + height / 2.0,
+                confidence,
+                scale,
+            });
+        }
+    }
+
+    // The same element tends to match at neighbouring scales, keep the most confident one
+    matches.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    let mut kept: Vec<ImageMatch> = Vec::new();
+    for m in matches {
+        let overlaps = kept.iter().any(|k| {
+            (k.center_x - m.center_x).abs() < k.width / 2.0
+                && (k.center_y - m.center_y).abs() < k.height / 2.0
+        });
+        if !overlaps {
+            kept.push(m);
+        }
+    }
+    kept
+}
+
+/// Looks for a reference image on the screen and returns where it was found, most confident first
+#[command]
+pub async fn find_image_on_screen<R: Runtime>(
+    handle: AppHandle<R>,
+    reference: ImageSource,
+    region: Option<Region>,
+    scales: Option<Vec<f64>>,
+    threshold: Option<f64>,
+    max_results: Option<usize>,
+) -> Result<Vec<ImageMatch>, CommandError> {
+    log::info!("finding image {:?} on screen in {:?}", reference, region);
+    let scales = scales.unwrap_or(DEFAULT_SCALES.to_vec());
+    let threshold = threshold.unwrap_or(DEFAULT_THRESHOLD);
+
+    let mut matches = async_runtime::spawn_blocking(move || {
+        let reference = reference.load()?;
+        let capture = capture_screen(&handle)?;
+        Ok::<_, CommandError>(find_image(
+            &capture,
+            &reference,
+            region.as_ref(),
+            &scales,
+            threshold,
+        ))
+    })
+    .await
+    .map_err(|e| format!("Failed to search for image: {}", e))??;
+
+    if let Some(max_results) = max_results {
+        matches.truncate(max_results);
+    }
+    log::info!("found {} matches", matches.len());
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    // A smooth but irregular picture, like a screen full of soft edges, from a fixed seed
+    fn landscape(width: u32, height: u32) -> GrayImage {
+        let mut seed: u32 = 12345;
+        let coarse = GrayImage::from_fn(width / 8 + 2, height / 8 + 2, |_, _| {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            Luma([(seed >> 24) as u8])
+        });
+        image::imageops::resize(&coarse, width, height, FilterType::Triangle)
+    }
+
+    #[test]
+    fn integral_window_sums_match_the_pixels() {
+        let image = GrayImage::from_fn(7, 5, |x, y| Luma([(x * 10 + y * 3) as u8]));
+        let integral = Integral::new(&image);
+        let (x, y, w, h) = (2, 1, 4, 3);
+        let pixels: Vec<f64> = (y..y + h)
+            .flat_map(|py| (x..x + w).map(move |px| (px, py)))
+            .map(|(px, py)| image.get_pixel(px, py).0[0] as f64)
+            .collect();
+        assert_eq!(
+            integral.window(&integral.sum, x, y, w, h),
+            pixels.iter().sum::<f64>()
+        );
+        assert_eq!(
+            integral.window(&integral.sum_sq, x, y, w, h),
+            pixels.iter().map(|v| v * v).sum::<f64>()
+        );
+        assert_eq!(
+            integral.window(&integral.sum, 0, 0, 7, 5),
+            image.pixels().map(|p| p.0[0] as f64).sum::<f64>()
+        );
+    }
+
+    #[test]
+    fn finds_a_small_patch_where_it_was_cut_from() {
+        let haystack = landscape(120, 80);
+        let needle = image::imageops::crop_imm(&haystack, 53, 29, 12, 12).to_image();
+        let matches = match_template(&haystack, &needle, 0.95);
+        let (x, y, score) = matches[0];
+        assert_eq!((x, y), (53, 29));
+        assert!(score > 0.999, "score {}", score);
+    }
+
+    #[test]
+    fn finds_a_large_patch_through_the_coarse_level() {
+        let haystack = landscape(240, 160);
+        let needle = image::imageops::crop_imm(&haystack, 101, 67, 48, 40).to_image();
+        let matches = match_template(&haystack, &needle, 0.95);
+        assert_eq!((matches[0].0, matches[0].1), (101, 67));
+    }
+
+    #[test]
+    fn flat_needles_match_nothing() {
+        let haystack = landscape(60, 40);
+        let needle = GrayImage::from_pixel(8, 8, Luma([128]));
+        assert!(match_template(&haystack, &needle, 0.5).is_empty());
+        let too_big = landscape(80, 40);
+        assert!(match_template(&haystack, &too_big, 0.5).is_empty());
+    }
+
+    #[test]
+    fn finds_the_scale_the_reference_was_taken_at() {
+        let screen = landscape(240, 160);
+        let patch = image::imageops::crop_imm(&screen, 80, 40, 64, 64).to_image();
+        // The reference was saved on a screen with half the resolution
+        let reference = DynamicImage::ImageLuma8(resize(&patch, 0.5));
+        let capture = Capture {
+            image: DynamicImage::ImageLuma8(screen),
+            scale: 2.0,
+        };
+        let matches = find_image(&capture, &reference, None, &DEFAULT_SCALES, 0.9);
+        let best = &matches[0];
+        assert_eq!(best.scale, 2.0);
+        assert_eq!((best.x, best.y), (40.0, 20.0));
+        assert_eq!((best.width, best.height), (32.0, 32.0));
+        assert_eq!((best.center_x, best.center_y), (56.0, 36.0));
+    }
+
+    #[test]
+    fn overlapping_matches_keep_the_best() {
+        let matches = vec![
+            (10, 10, 0.92),
+            (12, 11, 0.97),
+            (40, 10, 0.95),
+            (14, 13, 0.91),
+            (70, 70, 0.5),
+        ];
+        assert_eq!(
+            suppress_overlaps(matches, 10, 10, 0.9),
+            vec![(12, 11, 0.97), (40, 10, 0.95)]
+        );
+    }
+
+    #[test]
+    fn matches_half_a_template_apart_are_both_kept() {
+        let matches = vec![(0, 0, 0.95), (5, 0, 0.94), (0, 5, 0.93)];
+        assert_eq!(suppress_overlaps(matches.clone(), 10, 10, 0.9), matches);
+    }
+}
@@ -0,0 +1,200 @@
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{async_runtime, command, AppHandle, Manager, Runtime};
+
+use super::action::{self, Action};
+use super::error::CommandError;
+use super::image::{capture_screen, Region};
+use super::ocr::{Ocr, OcrResult};
+use super::watch::Watchers;
+
+const POLL_INTERVAL_MS: u64 = 250;
+
+/// A run of recognized words containing the searched text
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TextMatch {
+    // the whole line the text was found in
+    pub line: String,
+    pub bounds: Region,
+    pub center_x: f64,
+    pub center_y: f64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TextAssertion {
+    pub present: bool,
+    pub matches: Vec<TextMatch>,
+    // hash of the capture that was read, the same one recognize_text reports
+    pub capture_hash: String,
+    pub checked_at: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TextWaitResult {
+    pub found: bool,
+    pub cancelled: bool,
+    pub elapsed_ms: u64,
+    pub matches: Vec<TextMatch>,
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn union(a: &Region, b: &Region) -> Region {
+    let x = a.x.min(b.x);
+    let y = a.y.min(b.y);
+    Region {
+        x,
+        y,
+        width: (a.x + a.width).max(b.x + b.width) - x,
+        height: (a.y + a.height).max(b.y + b.height) - y,
+    }
+}
+
+/// Finds every place the text appears, case-insensitively, in reading order.
+/// Each match covers the smallest run of words on a line that contains the text.
+pub fn find_text(result: &OcrResult, text: &str) -> Vec<TextMatch> {
+    let query = normalize(text);
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let mut matches = Vec::new();
+    for line in &result.lines {
+        let words: Vec<String> = line.words.iter().map(|w| normalize(&w.text)).collect();
+        let contains = |start: usize, end: usize| {
+            start <= end && words[start..=end].join(" ").contains(&query)
+        };
+        for start in 0..words.len() {
+            let Some(end) = (start..words.len()).find(|&end| contains(start, end)) else {
+                continue;
+            };
+            // Skip spans that would still match without their first word, the next start covers them
+            if start < end && contains(start + 1, end) {
+                continue;
+            }
+            let bounds = line.words[start + 1..=end]
+                .iter()
+                .fold(line.words[start].bounds, |acc, w| union(&acc, &w.bounds));
+            matches.push(TextMatch {
+                line: line.text.clone(),
+                bounds,
+                center_x: bounds.x + bounds.width / 2.0,
+                center_y: bounds.y + bounds.height / 2.0,
+            });
+        }
+    }
+    matches
+}
+
+// Captures the screen and looks for the text, returning the OCR result alongside the matches
+fn read_and_find<R: Runtime>(
+    handle: &AppHandle<R>,
+    text: &str,
+    region: Option<&Region>,
+) -> Result<(Vec<TextMatch>, String), CommandError> {
+    let capture = capture_screen(handle)?;
+    let result = handle.state::<Ocr>().recognize(&capture, region)?;
+    Ok((find_text(&result, text), result.hash.clone()))
+}
+
+/// Clicks the centre of the nth (0-based) occurrence of some text on screen
+#[command]
+pub async fn click_text<R: Runtime>(
+    handle: AppHandle<R>,
+    text: String,
+    nth: Option<usize>,
+    region: Option<Region>,
+    button: Option<String>,
+) -> Result<TextMatch, CommandError> {
+    let nth = nth.unwrap_or(0);
+    log::info!("agent: clicking text {:?} (#{}) in {:?}", text, nth, region);
+
+    let reader = handle.clone();
+    let query = text.clone();
+    let (matches, _) =
+        async_runtime::spawn_blocking(move || read_and_find(&reader, &query, region.as_ref()))
+            .await
+            .map_err(|e| format!("Failed to find text: {}", e))??;
+    let target = matches.get(nth).cloned().ok_or(format!(
+        "Text {:?} not found on screen ({} occurrences, wanted #{})",
+        text,
+        matches.len(),
+        nth
+    ))?;
+
+    let action = Action::Click {
+        button: button.unwrap_or_else(|| "left".to_string()),
+        x: target.center_x,
+        y: target.center_y,
+    };
+    action::perform(&handle, action).await?;
+    Ok(target)
+}
+
+/// Blocks until some text shows up on screen. Can be cancelled with cancel_watch.
+#[command]
+pub async fn wait_for_text<R: Runtime>(
+    handle: AppHandle<R>,
+    text: String,
+    region: Option<Region>,
+    timeout_ms: Option<u64>,
+) -> Result<TextWaitResult, CommandError> {
+    let timeout_ms = timeout_ms.unwrap_or(10000);
+    let (id, cancelled) = handle.state::<Watchers>().register();
+    log::info!(
+        "agent: waiting for text {:?} in {:?} (watch {})",
+        text,
+        region,
+        id
+    );
+
+    let reader = handle.clone();
+    let result = async_runtime::spawn_blocking(move || {
+        let start = Instant::now();
+        loop {
+            let (matches, _) = read_and_find(&reader, &text, region.as_ref())?;
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+            let cancelled = cancelled.load(Ordering::Relaxed);
+            if !matches.is_empty() || cancelled || elapsed_ms >= timeout_ms {
+                return Ok::<_, CommandError>(TextWaitResult {
+                    found: !matches.is_empty(),
+                    cancelled,
+                    elapsed_ms,
+                    matches,
+                });
+            }
+            thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+        }
+    })
+    .await
+    .map_err(|e| format!("Failed to wait for text: {}", e));
+    handle.state::<Watchers>().unregister(id);
+    result?
+}
+
+/// Checks whether some text is on screen and returns where it was seen as evidence
+#[command]
+pub async fn assert_text_present<R: Runtime>(
+    handle: AppHandle<R>,
+    text: String,
+    region: Option<Region>,
+) -> Result<TextAssertion, CommandError> {
+    log::info!("asserting text {:?} is present in {:?}", text, region);
+    let (matches, capture_hash) =
+        async_runtime::spawn_blocking(move || read_and_find(&handle, &text, region.as_ref()))
+            .await
+            .map_err(|e| format!("Failed to find text: {}", e))??;
+
+    Ok(TextAssertion {
+        present: !matches.is_empty(),
+        matches,
+        capture_hash,
+        checked_at: chrono::Local::now().to_rfc3339(),
+    })
+}
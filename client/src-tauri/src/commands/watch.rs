@@ -0,0 +1,403 @@
+use image::{imageops::FilterType, DynamicImage, GrayImage};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{async_runtime, command, AppHandle, Emitter, Manager, Runtime, State};
+
+use super::error::CommandError;
+use super::image::{capture_screen, Capture, Region};
+use super::template::{find_image, ImageSource};
+
+const POLL_INTERVAL_MS: u64 = 50;
+// Frames are compared at this width, which is plenty to notice a page load
+// while ignoring things like a blinking caret
+const THUMBNAIL_WIDTH: u32 = 160;
+// How far apart two grayscale pixels have to be before they count as different
+const PIXEL_TOLERANCE: u8 = 12;
+// Fraction of differing pixels above which two frames are considered changed
+const CHANGE_THRESHOLD: f64 = 0.002;
+const TEMPLATE_THRESHOLD: f64 = 0.9;
+
+/// What a watcher waits for
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WatchTrigger {
+    /// The region differs from how it looked when the watch started
+    Change,
+    /// The region has not changed for `stable_ms`
+    Stable { stable_ms: u64 },
+    /// The reference image shows up in the region, e.g. a dialog that should appear
+    Template {
+        reference: ImageSource,
+        threshold: Option<f64>,
+    },
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct WatchResult {
+    id: u64,
+    triggered: bool,
+    cancelled: bool,
+    elapsed_ms: u64,
+}
+
+/// Watchers currently polling the screen, keyed by id so they can be cancelled
+#[derive(Default)]
+pub struct Watchers {
+    next_id: AtomicU64,
+    active: Mutex<HashMap<u64, Arc<AtomicBool>>>,
+}
+
+impl Watchers {
+    pub(crate) fn register(&self) -> (u64, Arc<AtomicBool>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.active.lock().unwrap().insert(id, cancelled.clone());
+        (id, cancelled)
+    }
+
+    pub(crate) fn unregister(&self, id: u64) {
+        self.active.lock().unwrap().remove(&id);
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct StableResult {
+    waited_ms: u64,
+    stable: bool,
+    cancelled: bool,
+}
+
+// Captures the screen (or a region of it) as a small grayscale frame for cheap comparisons
+pub(crate) fn capture_thumbnail<R: Runtime>(
+    handle: &AppHandle<R>,
+    region: Option<&Region>,
+) -> Result<GrayImage, CommandError> {
+    Ok(capture_frame(handle, region)?.1)
+}
+
+// Captures the screen once, with the privacy check that comes with it, and its small frame.
+// The capture itself is kept for triggers that need the full resolution.
+fn capture_frame<R: Runtime>(
+    handle: &AppHandle<R>,
+    region: Option<&Region>,
+) -> Result<(Capture, GrayImage), CommandError> {
+    let capture = capture_screen(handle)?;
+    let cropped;
+    let image = match region {
+        Some(region) => {
+            cropped = capture.crop(region);
+            &cropped
+        }
+        None => &capture.image,
+    };
+    if image.width() == 0 || image.height() == 0 {
+        return Err("Region is outside of the screen".into());
+    }
+    let frame = thumbnail(image);
+    Ok((capture, frame))
+}
+
+// Shrinks an image to a small grayscale frame, see `capture_thumbnail`
+pub(crate) fn thumbnail(image: &DynamicImage) -> GrayImage {
+    let height = (image.height() * THUMBNAIL_WIDTH / image.width().max(1)).max(1);
+    image
+        .resize_exact(THUMBNAIL_WIDTH, height, FilterType::Triangle)
+        .to_luma8()
+}
+
+// Returns the fraction of pixels that differ between two frames
+pub(crate) fn frame_difference(a: &GrayImage, b: &GrayImage) -> f64 {
+    if a.dimensions() != b.dimensions() {
+        return 1.0;
+    }
+    let changed = a
+        .pixels()
+        .zip(b.pixels())
+        .filter(|(p, q)| p.0[0].abs_diff(q.0[0]) > PIXEL_TOLERANCE)
+        .count();
+    changed as f64 / (a.width() * a.height()) as f64
+}
+
+fn poll_until_stable<R: Runtime>(
+    handle: &AppHandle<R>,
+    stable_ms: u64,
+    timeout_ms: u64,
+    region: Option<Region>,
+    cancelled: &AtomicBool,
+) -> Result<StableResult, CommandError> {
+    let start = Instant::now();
+    let mut previous = capture_thumbnail(handle, region.as_ref())?;
+    let mut last_change = Instant::now();
+
+    let result = |stable: bool, cancelled: bool| StableResult {
+        waited_ms: start.elapsed().as_millis() as u64,
+        stable,
+        cancelled,
+    };
+
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            return Ok(result(false, true));
+        }
+        if last_change.elapsed() >= Duration::from_millis(stable_ms) {
+            return Ok(result(true, false));
+        }
+        if start.elapsed() >= Duration::from_millis(timeout_ms) {
+            return Ok(result(false, false));
+        }
+
+        thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+        let current = capture_thumbnail(handle, region.as_ref())?;
+        if frame_difference(&previous, &current) > CHANGE_THRESHOLD {
+            last_change = Instant::now();
+        }
+        previous = current;
+    }
+}
+
+fn poll_until_triggered<R: Runtime>(
+    handle: &AppHandle<R>,
+    id: u64,
+    trigger: &WatchTrigger,
+    region: Option<Region>,
+    timeout_ms: u64,
+    cancelled: &AtomicBool,
+) -> Result<WatchResult, CommandError> {
+    let start = Instant::now();
+    let template = match trigger {
+        WatchTrigger::Template { reference, .. } => Some(reference.load()?),
+        _ => None,
+    };
+    let initial = capture_thumbnail(handle, region.as_ref())?;
+    let mut previous = initial.clone();
+    let mut last_change = Instant::now();
+
+    let result = |triggered: bool, cancelled: bool| WatchResult {
+        id,
+        triggered,
+        cancelled,
+        elapsed_ms: start.elapsed().as_millis() as u64,
+    };
+
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            return Ok(result(false, true));
+        }
+        if start.elapsed() >= Duration::from_millis(timeout_ms) {
+            return Ok(result(false, false));
+        }
+
+        thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+        let (capture, current) = capture_frame(handle, region.as_ref())?;
+        if frame_difference(&previous, &current) > CHANGE_THRESHOLD {
+            last_change = Instant::now();
+        }
+
+        let triggered = match trigger {
+            WatchTrigger::Change => frame_difference(&initial, &current) > CHANGE_THRESHOLD,
+            WatchTrigger::Stable { stable_ms } => {
+                last_change.elapsed() >= Duration::from_millis(*stable_ms)
+            }
+            WatchTrigger::Template { threshold, .. } => template.as_ref().is_some_and(|template| {
+                let threshold = threshold.unwrap_or(TEMPLATE_THRESHOLD);
+                !find_image(&capture, template, region.as_ref(), &[1.0], threshold).is_empty()
+            }),
+        };
+        if triggered {
+            return Ok(result(true, false));
+        }
+        previous = current;
+    }
+}
+
+// Runs a watcher to completion on the blocking pool, keeping it cancellable while it runs
+async fn run_watch<R: Runtime>(
+    handle: AppHandle<R>,
+    id: u64,
+    cancelled: Arc<AtomicBool>,
+    trigger: WatchTrigger,
+    region: Option<Region>,
+    timeout_ms: u64,
+) -> Result<WatchResult, CommandError> {
+    let poller = handle.clone();
+    let result = async_runtime::spawn_blocking(move || {
+        poll_until_triggered(&poller, id, &trigger, region, timeout_ms, &cancelled)
+    })
+    .await
+    .map_err(|e| format!("Failed to run watcher: {}", e));
+    handle.state::<Watchers>().unregister(id);
+    result?
+}
+
+/// Starts watching a region in the background and returns the watcher id.
+/// A `watch_finished` event carrying the WatchResult is emitted once it triggers, times out or is cancelled.
+#[command]
+pub fn watch_region<R: Runtime>(
+    handle: AppHandle<R>,
+    watchers: State<'_, Watchers>,
+    trigger: WatchTrigger,
+    region: Option<Region>,
+    timeout_ms: Option<u64>,
+) -> Result<u64, String> {
+    let timeout_ms = timeout_ms.unwrap_or(30000);
+    let (id, cancelled) = watchers.register();
+    log::info!("watch {}: watching {:?} for {:?}", id, region, trigger);
+
+    async_runtime::spawn(async move {
+        let result = run_watch(handle.clone(), id, cancelled, trigger, region, timeout_ms).await;
+        match result {
+            Ok(result) => {
+                log::info!("watch {}: finished {:?}", id, result);
+                let _ = handle.emit("watch_finished", result);
+            }
+            Err(e) => {
+                log::error!("watch {}: failed: {}", id, e);
+                let _ = handle.emit("watch_failed", json!({ "id": id, "error": e.message }));
+            }
+        }
+    });
+
+    Ok(id)
+}
+
+/// Blocks until the region changes (or the given trigger fires), the timeout passes or the watch is cancelled
+#[command]
+pub async fn wait_for_change<R: Runtime>(
+    handle: AppHandle<R>,
+    watchers: State<'_, Watchers>,
+    trigger: Option<WatchTrigger>,
+    region: Option<Region>,
+    timeout_ms: Option<u64>,
+) -> Result<WatchResult, CommandError> {
+    let trigger = trigger.unwrap_or(WatchTrigger::Change);
+    let timeout_ms = timeout_ms.unwrap_or(10000);
+    let (id, cancelled) = watchers.register();
+    log::info!(
+        "agent: waiting for {:?} in {:?} (watch {})",
+        trigger,
+        region,
+        id
+    );
+    if let Err(e) = handle.emit("watch_started", json!({ "id": id })) {
+        watchers.unregister(id);
+        return Err(format!("Failed to emit watch event: {}", e).into());
+    }
+
+    let result = run_watch(handle.clone(), id, cancelled, trigger, region, timeout_ms).await?;
+    handle
+        .emit("watch_finished", result.clone())
+        .map_err(|e| format!("Failed to emit watch event: {}", e))?;
+    Ok(result)
+}
+
+/// Cancels a single watcher, or all of them when no id is given
+#[command]
+pub fn cancel_watch(watchers: State<'_, Watchers>, id: Option<u64>) -> Result<(), String> {
+    let active = watchers.active.lock().unwrap();
+    match id {
+        Some(id) => active
+            .get(&id)
+            .ok_or(format!("No active watch with id {}", id))?
+            .store(true, Ordering::Relaxed),
+        None => active
+            .values()
+            .for_each(|cancelled| cancelled.store(true, Ordering::Relaxed)),
+    }
+    Ok(())
+}
+
+/// Blocks until the region stops changing for `stable_ms` or the timeout passes. Can be cancelled
+/// with cancel_watch.
+#[command]
+pub async fn wait_until_stable<R: Runtime>(
+    handle: AppHandle<R>,
+    stable_ms: Option<u64>,
+    timeout_ms: Option<u64>,
+    region: Option<Region>,
+) -> Result<StableResult, CommandError> {
+    let stable_ms = stable_ms.unwrap_or(300);
+    let timeout_ms = timeout_ms.unwrap_or(5000);
+    let (id, cancelled) = handle.state::<Watchers>().register();
+    log::info!(
+        "agent: waiting for screen to be stable for {}ms (timeout {}ms, region {:?}, watch {})",
+        stable_ms,
+        timeout_ms,
+        region,
+        id
+    );
+
+    let poller = handle.clone();
+    let result = async_runtime::spawn_blocking(move || {
+        poll_until_stable(&poller, stable_ms, timeout_ms, region, &cancelled)
+    })
+    .await
+    .map_err(|e| format!("Failed to wait for stable screen: {}", e));
+    handle.state::<Watchers>().unregister(id);
+    let result = result??;
+
+    if !result.stable && !result.cancelled {
+        log::warn!("screen did not settle within {}ms", timeout_ms);
+    }
+    handle
+        .emit("agent_wait", json!({ "ms": result.waited_ms }))
+        .map_err(|e| format!("Failed to emit wait event: {}", e))?;
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    fn frame(pixels: &[u8]) -> GrayImage {
+        GrayImage::from_raw(pixels.len() as u32 / 2, 2, pixels.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn identical_frames_do_not_differ() {
+        let a = frame(&[0, 50, 100, 150, 200, 250]);
+        assert_eq!(frame_difference(&a, &a.clone()), 0.0);
+    }
+
+    #[test]
+    fn small_changes_are_within_tolerance() {
+        let a = frame(&[0, 50, 100, 150, 200, 250]);
+        let b = frame(&[
+            PIXEL_TOLERANCE,
+            50 - PIXEL_TOLERANCE,
+            100,
+            150,
+            200,
+            250 - PIXEL_TOLERANCE,
+        ]);
+        assert_eq!(frame_difference(&a, &b), 0.0);
+    }
+
+    #[test]
+    fn counts_the_fraction_of_changed_pixels() {
+        let a = frame(&[0, 50, 100, 150, 200, 250]);
+        let b = frame(&[PIXEL_TOLERANCE + 1, 50, 255, 150, 200, 0]);
+        assert_eq!(frame_difference(&a, &b), 0.5);
+        assert_eq!(frame_difference(&b, &a), 0.5);
+    }
+
+    #[test]
+    fn frames_of_different_sizes_differ_entirely() {
+        let a = GrayImage::from_pixel(4, 3, Luma([10]));
+        let b = GrayImage::from_pixel(3, 4, Luma([10]));
+        assert_eq!(frame_difference(&a, &b), 1.0);
+    }
+
+    #[test]
+    fn thumbnails_keep_the_aspect_ratio() {
+        let image = DynamicImage::ImageLuma8(GrayImage::from_pixel(1600, 900, Luma([0])));
+        assert_eq!(thumbnail(&image).dimensions(), (THUMBNAIL_WIDTH, 90));
+        let sliver = DynamicImage::ImageLuma8(GrayImage::from_pixel(4000, 1, Luma([0])));
+        assert_eq!(thumbnail(&sliver).dimensions(), (THUMBNAIL_WIDTH, 1));
+    }
+}
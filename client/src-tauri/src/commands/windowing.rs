@@ -9,6 +9,8 @@ use windows::Win32::UI::WindowsAndMessaging::WDA_NONE;
 #[cfg(target_os = "windows")]
 use windows::Win32::UI::WindowsAndMessaging::{SetWindowDisplayAffinity, WDA_EXCLUDEFROMCAPTURE};
 
+use super::image::Region;
+
 #[command]
 pub fn set_hide_from_screenshot<R: Runtime>(handle: AppHandle<R>, hide: bool) {
     #[cfg(not(any(target_os = "windows", target_os = "macos")))]
@@ -42,3 +44,83 @@ pub fn set_hide_from_screenshot<R: Runtime>(handle: AppHandle<R>, hide: bool) {
         }
     }
 }
+
+/// A window of some application on screen, as reported by the OS
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct WindowInfo {
+    pub app_name: String,
+    pub title: String,
+    pub bounds: Region,
+}
+
+/// Lists the windows that are not minimized, frontmost first
+pub fn visible_windows() -> Result<Vec<WindowInfo>, String> {
+    let windows = xcap::Window::all().map_err(|e| format!("Failed to list windows: {}", e))?;
+    Ok(windows
+        .iter()
+        .filter(|w| !w.is_minimized())
+        .map(|w| WindowInfo {
+            app_name: w.app_name().to_string(),
+            title: w.title().to_string(),
+            bounds: Region {
+                x: w.x() as f64,
+                y: w.y() as f64,
+                width: w.width() as f64,
+                height: w.height() as f64,
+            },
+        })
+        .collect())
+}
+
+/// Whether keyboard input is going into a password field. On macOS password fields turn on
+/// secure event input. On Windows only standard password edit controls are recognized.
+pub fn password_field_focused() -> bool {
+    #[cfg(target_os = "macos")]
+    {
+        #[link(name = "Carbon", kind = "framework")]
+        extern "C" {
+            fn IsSecureEventInputEnabled() -> u8;
+        }
+        unsafe { IsSecureEventInputEnabled() != 0 }
+    }
+
+    #[cfg(target_os = "windows")]
+    unsafe {
+        use windows::Win32::UI::WindowsAndMessaging::{
+            GetForegroundWindow, GetGUIThreadInfo, GetWindowLongW, GetWindowThreadProcessId,
+            GUITHREADINFO, GWL_STYLE,
+        };
+        const ES_PASSWORD: i32 = 0x20;
+        let thread = GetWindowThreadProcessId(GetForegroundWindow(), None);
+        let mut info = GUITHREADINFO {
+            cbSize: std::mem::size_of::<GUITHREADINFO>() as u32,
+            ..Default::default()
+        };
+        if thread == 0
+            || GetGUIThreadInfo(thread, &mut info).is_err()
+            || info.hwndFocus.is_invalid()
+        {
+            return false;
+        }
+        GetWindowLongW(info.hwndFocus, GWL_STYLE) & ES_PASSWORD != 0
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    false
+}
+
+/// The window with keyboard focus, belonging to the frontmost application
+pub fn foreground_window() -> Result<WindowInfo, String> {
+    let window = active_win_pos_rs::get_active_window()
+        .map_err(|_| "Failed to get the foreground window".to_string())?;
+    Ok(WindowInfo {
+        app_name: window.app_name,
+        title: window.title,
+        bounds: Region {
+            x: window.position.x,
+            y: window.position.y,
+            width: window.position.width,
+            height: window.position.height,
+        },
+    })
+}
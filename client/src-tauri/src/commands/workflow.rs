@@ -0,0 +1,891 @@
+use regex::Regex;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{async_runtime, command, AppHandle, Emitter, Manager, Runtime, State};
+
+use super::action::{self, Action};
+use super::agent::{self, AgentOptions, AgentRunner};
+use super::approval::Approvals;
+use super::error::CommandError;
+use super::governor::Governor;
+use super::journal::Journal;
+use super::template::{find_image_on_screen, ImageSource};
+use super::text::{assert_text_present, click_text};
+
+const POLL_INTERVAL_MS: u64 = 250;
+const DEFAULT_WAIT_TIMEOUT_MS: u64 = 10000;
+// Loops without a limit stop here, a condition that never changes shouldn't spin forever
+const DEFAULT_MAX_ITERATIONS: u32 = 100;
+
+static VARIABLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*([A-Za-z0-9_:.-]+)\s*\}\}").unwrap());
+
+/// Something about the screen or the variables a step can check
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    /// The text is somewhere on screen, matched like `click_text`
+    TextVisible(String),
+    /// The reference image is somewhere on screen
+    ImageVisible(ImageSource),
+    /// Both values are the same once variables are filled in
+    Equals(String, String),
+    Not(Box<Condition>),
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+}
+
+/// What a step does, named like the commands where there is one
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepKind {
+    Click {
+        x: f64,
+        y: f64,
+        #[serde(default)]
+        button: Option<String>,
+    },
+    DoubleClick {
+        x: f64,
+        y: f64,
+    },
+    Scroll {
+        x: f64,
+        y: f64,
+        #[serde(default)]
+        scroll_x: f64,
+        #[serde(default)]
+        scroll_y: f64,
+    },
+    Keypress(Vec<String>),
+    TypeText(String),
+    MoveMouse {
+        x: f64,
+        y: f64,
+    },
+    Drag(Vec<(f64, f64)>),
+    ClickText {
+        text: String,
+        #[serde(default)]
+        nth: Option<usize>,
+        #[serde(default)]
+        button: Option<String>,
+    },
+    ClickImage {
+        reference: ImageSource,
+        #[serde(default)]
+        threshold: Option<f64>,
+        #[serde(default)]
+        button: Option<String>,
+    },
+    /// Milliseconds
+    Wait(u64),
+    WaitFor {
+        condition: Condition,
+        #[serde(default)]
+        timeout_ms: Option<u64>,
+    },
+    Assert(Condition),
+    /// Sets variables, non-string values are stored as their JSON text
+    Set(HashMap<String, Value>),
+    /// Runs the steps a number of times with `{{index}}` counting from 0
+    Repeat {
+        times: u32,
+        steps: Vec<Step>,
+    },
+    While {
+        condition: Condition,
+        steps: Vec<Step>,
+        #[serde(default)]
+        max_iterations: Option<u32>,
+    },
+    If {
+        condition: Condition,
+        #[serde(default)]
+        then: Vec<Step>,
+        #[serde(default, rename = "else")]
+        otherwise: Vec<Step>,
+    },
+    /// Hands a fuzzy part to the agent, optionally keeping its final message in a variable
+    AskAgent {
+        prompt: String,
+        #[serde(default)]
+        save_as: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Step {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Keep going when the step fails
+    #[serde(default)]
+    pub optional: bool,
+    #[serde(flatten)]
+    pub kind: StepKind,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Workflow {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Defaults for `{{variables}}`, overridden by the ones passed to `run_workflow`
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkflowFormat {
+    Yaml,
+    Toml,
+}
+
+impl Workflow {
+    pub fn parse(source: &str, format: WorkflowFormat) -> Result<Workflow, String> {
+        match format {
+            WorkflowFormat::Yaml => {
+                serde_yaml::from_str(source).map_err(|e| format!("Invalid workflow: {}", e))
+            }
+            WorkflowFormat::Toml => {
+                toml::from_str(source).map_err(|e| format!("Invalid workflow: {}", e))
+            }
+        }
+    }
+
+    /// Reads a `.yaml`, `.yml` or `.toml` file
+    pub fn load(path: &Path) -> Result<Workflow, String> {
+        let format = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => WorkflowFormat::Toml,
+            Some("yaml") | Some("yml") => WorkflowFormat::Yaml,
+            _ => return Err(format!("Unknown workflow format: {}", path.display())),
+        };
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read workflow {}: {}", path.display(), e))?;
+        Workflow::parse(&source, format)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Passed,
+    Failed,
+    Stopped,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct StepReport {
+    /// Where the step is, like "3" or "3.then.1", counting from 1
+    pub path: String,
+    pub name: Option<String>,
+    pub kind: &'static str,
+    pub status: StepStatus,
+    pub duration_ms: u64,
+    /// What the step found or did, like a text match or the agent's answer
+    pub detail: Option<Value>,
+    pub error: Option<CommandError>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct WorkflowReport {
+    pub run_id: u64,
+    pub workflow: String,
+    pub status: StepStatus,
+    pub steps: Vec<StepReport>,
+    pub variables: HashMap<String, String>,
+    pub duration_ms: u64,
+}
+
+struct Run {
+    stopped: Arc<AtomicBool>,
+    // the agent is working on an ask_agent step
+    asking: bool,
+}
+
+/// Workflows in progress, keyed by run id so they can be stopped
+#[derive(Default)]
+pub struct Workflows {
+    next_id: AtomicU64,
+    active: Mutex<HashMap<u64, Run>>,
+}
+
+impl StepKind {
+    fn name(&self) -> &'static str {
+        match self {
+            StepKind::Click { .. } => "click",
+            StepKind::DoubleClick { .. } => "double_click",
+            StepKind::Scroll { .. } => "scroll",
+            StepKind::Keypress(_) => "keypress",
+            StepKind::TypeText(_) => "type_text",
+            StepKind::MoveMouse { .. } => "move_mouse",
+            StepKind::Drag(_) => "drag",
+            StepKind::ClickText { .. } => "click_text",
+            StepKind::ClickImage { .. } => "click_image",
+            StepKind::Wait(_) => "wait",
+            StepKind::WaitFor { .. } => "wait_for",
+            StepKind::Assert(_) => "assert",
+            StepKind::Set(_) => "set",
+            StepKind::Repeat { .. } => "repeat",
+            StepKind::While { .. } => "while",
+            StepKind::If { .. } => "if",
+            StepKind::AskAgent { .. } => "ask_agent",
+        }
+    }
+}
+
+/// Fills in `{{name}}` placeholders. `{{secret:…}}` is left for `type_text` to resolve.
+pub fn substitute(text: &str, variables: &HashMap<String, String>) -> Result<String, String> {
+    let mut missing = None;
+    let result = VARIABLE.replace_all(text, |captures: &regex::Captures| {
+        let name = &captures[1];
+        if name.starts_with("secret:") {
+            return captures[0].to_string();
+        }
+        match variables.get(name) {
+            Some(value) => value.clone(),
+            None => {
+                missing.get_or_insert_with(|| name.to_string());
+                String::new()
+            }
+        }
+    });
+    match missing {
+        Some(name) => Err(format!("Unknown variable {:?}", name)),
+        None => Ok(result.into_owned()),
+    }
+}
+
+type StepFuture<'a> = Pin<Box<dyn Future<Output = Result<StepStatus, ()>> + Send + 'a>>;
+
+struct Interpreter<'a, R: Runtime> {
+    handle: &'a AppHandle<R>,
+    run_id: u64,
+    task_id: String,
+    stopped: Arc<AtomicBool>,
+    variables: HashMap<String, String>,
+    reports: Vec<StepReport>,
+}
+
+impl<'a, R: Runtime> Interpreter<'a, R> {
+    fn text(&self, text: &str) -> Result<String, CommandError> {
+        Ok(substitute(text, &self.variables)?)
+    }
+
+    async fn perform(&self, action: Action) -> Result<Option<Value>, CommandError> {
+        action::perform(self.handle, action).await?;
+        Ok(None)
+    }
+
+    fn evaluate<'b>(
+        &'b self,
+        condition: &'b Condition,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, CommandError>> + Send + 'b>> {
+        Box::pin(async move {
+            Ok(match condition {
+                Condition::TextVisible(text) => {
+                    assert_text_present(self.handle.clone(), self.text(text)?, None)
+                        .await?
+                        .present
+                }
+                Condition::ImageVisible(reference) => !find_image_on_screen(
+                    self.handle.clone(),
+                    reference.clone(),
+                    None,
+                    None,
+                    None,
+                    Some(1),
+                )
+                .await?
+                .is_empty(),
+                Condition::Equals(a, b) => self.text(a)? == self.text(b)?,
+                Condition::Not(condition) => !self.evaluate(condition).await?,
+                Condition::All(conditions) => {
+                    for condition in conditions {
+                        if !self.evaluate(condition).await? {
+                            return Ok(false);
+                        }
+                    }
+                    true
+                }
+                Condition::Any(conditions) => {
+                    for condition in conditions {
+                        if self.evaluate(condition).await? {
+                            return Ok(true);
+                        }
+                    }
+                    false
+                }
+            })
+        })
+    }
+
+    fn check_stopped(&self) -> Result<(), CommandError> {
+        if self.stopped.load(Ordering::Relaxed) {
+            return Err("The workflow was stopped".into());
+        }
+        Ok(())
+    }
+
+    // Sleeps in short slices so that stopping the workflow cuts the wait short
+    async fn sleep(&self, ms: u64) -> Result<(), CommandError> {
+        let deadline = Instant::now() + Duration::from_millis(ms);
+        loop {
+            self.check_stopped()?;
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(());
+            }
+            let slice = left.min(Duration::from_millis(POLL_INTERVAL_MS));
+            async_runtime::spawn_blocking(move || thread::sleep(slice))
+                .await
+                .map_err(|e| format!("Failed to wait: {}", e))?;
+        }
+    }
+
+    // Runs the prompt as an agent task and waits for its final message
+    async fn ask_agent(&self, prompt: String) -> Result<String, CommandError> {
+        let workflows = self.handle.state::<Workflows>();
+        let set_asking = |asking: bool| {
+            if let Some(run) = workflows.active.lock().unwrap().get_mut(&self.run_id) {
+                run.asking = asking;
+            }
+        };
+        log::info!("workflow {}: asking the agent {:?}", self.run_id, prompt);
+        let governor = self.handle.state::<Governor>();
+        let mut usage = governor.usage();
+        set_asking(true);
+        let answer = agent::run_task(self.handle, prompt, AgentOptions::default()).await;
+        set_asking(false);
+        // The agent ran as its own task, the workflow's next actions are ours again. What the
+        // agent used counts against the workflow too, or a loop of questions would never run out.
+        self.handle
+            .state::<Journal>()
+            .start_task(self.task_id.clone());
+        let asked = governor.usage();
+        if asked.task_id != usage.task_id {
+            usage.actions += asked.actions;
+            usage.typed_chars += asked.typed_chars;
+        }
+        governor.resume_task(usage);
+        Ok(answer?.unwrap_or_default())
+    }
+
+    // Runs one step that doesn't contain others, returning what it found
+    async fn run_simple(&mut self, kind: &StepKind) -> Result<Option<Value>, CommandError> {
+        match kind {
+            StepKind::Click { x, y, button } => {
+                self.perform(Action::Click {
+                    button: button.clone().unwrap_or_else(|| "left".to_string()),
+                    x: *x,
+                    y: *y,
+                })
+                .await
+            }
+            StepKind::DoubleClick { x, y } => {
+                self.perform(Action::DoubleClick { x: *x, y: *y }).await
+            }
+            StepKind::Scroll {
+                x,
+                y,
+                scroll_x,
+                scroll_y,
+            } => {
+                self.perform(Action::Scroll {
+                    x: *x,
+                    y: *y,
+                    scroll_x: *scroll_x,
+                    scroll_y: *scroll_y,
+                })
+                .await
+            }
+            StepKind::Keypress(keys) => {
+                let keys = keys
+                    .iter()
+                    .map(|key| self.text(key))
+                    .collect::<Result<_, _>>()?;
+                self.perform(Action::Keypress { keys }).await
+            }
+            StepKind::TypeText(text) => {
+                self.perform(Action::TypeText {
+                    text: self.text(text)?,
+                })
+                .await
+            }
+            StepKind::MoveMouse { x, y } => self.perform(Action::MoveMouse { x: *x, y: *y }).await,
+            StepKind::Drag(path) => self.perform(Action::Drag { path: path.clone() }).await,
+            StepKind::ClickText { text, nth, button } => {
+                let found = click_text(
+                    self.handle.clone(),
+                    self.text(text)?,
+                    *nth,
+                    None,
+                    button.clone(),
+                )
+                .await?;
+                Ok(Some(json!(found)))
+            }
+            StepKind::ClickImage {
+                reference,
+                threshold,
+                button,
+            } => {
+                let found = find_image_on_screen(
+                    self.handle.clone(),
+                    reference.clone(),
+                    None,
+                    None,
+                    *threshold,
+                    Some(1),
+                )
+                .await?
+                .into_iter()
+                .next()
+                .ok_or(format!("Image {:?} not found on screen", reference))?;
+                self.perform(Action::Click {
+                    button: button.clone().unwrap_or_else(|| "left".to_string()),
+                    x: found.center_x,
+                    y: found.center_y,
+                })
+                .await?;
+                Ok(Some(json!(found)))
+            }
+            StepKind::Wait(ms) => {
+                self.sleep(*ms).await?;
+                Ok(None)
+            }
+            StepKind::WaitFor {
+                condition,
+                timeout_ms,
+            } => {
+                let timeout = Duration::from_millis(timeout_ms.unwrap_or(DEFAULT_WAIT_TIMEOUT_MS));
+                let start = Instant::now();
+                loop {
+                    self.check_stopped()?;
+                    if self.evaluate(condition).await? {
+                        break;
+                    }
+                    if start.elapsed() >= timeout {
+                        return Err(format!(
+                            "Condition not met after {}ms",
+                            start.elapsed().as_millis()
+                        )
+                        .into());
+                    }
+                    self.sleep(POLL_INTERVAL_MS).await?;
+                }
+                Ok(Some(
+                    json!({ "waited_ms": start.elapsed().as_millis() as u64 }),
+                ))
+            }
+            StepKind::Assert(condition) => {
+                if !self.evaluate(condition).await? {
+                    return Err(format!("Assertion failed: {:?}", condition).into());
+                }
+                Ok(None)
+            }
+            StepKind::Set(values) => {
+                for (name, value) in values {
+                    let value = match value {
+                        Value::String(text) => self.text(text)?,
+                        other => other.to_string(),
+                    };
+                    self.variables.insert(name.clone(), value);
+                }
+                Ok(None)
+            }
+            StepKind::AskAgent { prompt, save_as } => {
+                let answer = self.ask_agent(self.text(prompt)?).await?;
+                if let Some(name) = save_as {
+                    self.variables.insert(name.clone(), answer.clone());
+                }
+                Ok(Some(json!(answer)))
+            }
+            StepKind::Repeat { .. } | StepKind::While { .. } | StepKind::If { .. } => {
+                unreachable!("control flow steps are run by run_step")
+            }
+        }
+    }
+
+    fn report(
+        &mut self,
+        path: &str,
+        step: &Step,
+        started: Instant,
+        outcome: Result<Option<Value>, CommandError>,
+    ) -> StepStatus {
+        let (status, detail, error) = match outcome {
+            Ok(detail) => (StepStatus::Passed, detail, None),
+            Err(e) => (StepStatus::Failed, None, Some(e)),
+        };
+        let report = StepReport {
+            path: path.to_string(),
+            name: step.name.clone(),
+            kind: step.kind.name(),
+            status,
+            duration_ms: started.elapsed().as_millis() as u64,
+            detail,
+            error,
+        };
+        log::info!(
+            "workflow {}: step {} ({}) {:?}",
+            self.run_id,
+            path,
+            report.kind,
+            status
+        );
+        let _ = self.handle.emit(
+            "agent_workflow_step",
+            json!({ "run_id": self.run_id, "step": &report }),
+        );
+        self.reports.push(report);
+        status
+    }
+
+    // Runs a list of steps, returning Err once the workflow has to end
+    fn run_steps<'b>(&'b mut self, prefix: &'b str, steps: &'b [Step]) -> StepFuture<'b> {
+        Box::pin(async move {
+            for (index, step) in steps.iter().enumerate() {
+                let path = if prefix.is_empty() {
+                    (index + 1).to_string()
+                } else {
+                    format!("{}.{}", prefix, index + 1)
+                };
+                self.run_step(&path, step).await?;
+            }
+            Ok(StepStatus::Passed)
+        })
+    }
+
+    async fn run_step(&mut self, path: &str, step: &Step) -> Result<StepStatus, ()> {
+        if self.stopped.load(Ordering::Relaxed) {
+            return Err(());
+        }
+        let started = Instant::now();
+        let outcome: Result<Option<Value>, CommandError> = match &step.kind {
+            StepKind::Repeat { times, steps } => {
+                // Nested repeats and a user variable get their `index` back afterwards
+                let outer = self.variables.get("index").cloned();
+                let mut outcome = Ok(Some(json!({ "iterations": times })));
+                for index in 0..*times {
+                    self.variables
+                        .insert("index".to_string(), index.to_string());
+                    let prefix = format!("{}.{}", path, index + 1);
+                    if self.run_steps(&prefix, steps).await.is_err() {
+                        outcome = Err(format!("A step in iteration {} failed", index + 1).into());
+                        break;
+                    }
+                }
+                match outer {
+                    Some(value) => self.variables.insert("index".to_string(), value),
+                    None => self.variables.remove("index"),
+                };
+                outcome
+            }
+            StepKind::While {
+                condition,
+                steps,
+                max_iterations,
+            } => {
+                let max = max_iterations.unwrap_or(DEFAULT_MAX_ITERATIONS);
+                let mut iterations = 0;
+                loop {
+                    if let Err(e) = self.check_stopped() {
+                        break Err(e);
+                    }
+                    match self.evaluate(condition).await {
+                        Ok(false) => break Ok(Some(json!({ "iterations": iterations }))),
+                        Ok(true) if iterations >= max => {
+                            break Err(format!("Still looping after {} iterations", max).into())
+                        }
+                        Ok(true) => {
+                            iterations += 1;
+                            let prefix = format!("{}.{}", path, iterations);
+                            if self.run_steps(&prefix, steps).await.is_err() {
+                                break Err(
+                                    format!("A step in iteration {} failed", iterations).into()
+                                );
+                            }
+                        }
+                        Err(e) => break Err(e),
+                    }
+                }
+            }
+            StepKind::If {
+                condition,
+                then,
+                otherwise,
+            } => match self.evaluate(condition).await {
+                Ok(true) => match self.run_steps(&format!("{}.then", path), then).await {
+                    Ok(_) => Ok(Some(json!({ "branch": "then" }))),
+                    Err(()) => Err("A step in the then branch failed".into()),
+                },
+                Ok(false) => match self.run_steps(&format!("{}.else", path), otherwise).await {
+                    Ok(_) => Ok(Some(json!({ "branch": "else" }))),
+                    Err(()) => Err("A step in the else branch failed".into()),
+                },
+                Err(e) => Err(e),
+            },
+            kind => self.run_simple(kind).await,
+        };
+        match self.report(path, step, started, outcome) {
+            StepStatus::Failed if !step.optional || self.stopped.load(Ordering::Relaxed) => Err(()),
+            status => Ok(status),
+        }
+    }
+}
+
+/// Runs a workflow step by step, stopping at the first step that fails unless it is optional.
+/// Emits `agent_workflow_step` after each step and `agent_workflow_finished` with the report.
+pub async fn run<R: Runtime>(
+    handle: &AppHandle<R>,
+    workflow: &Workflow,
+    variables: HashMap<String, String>,
+) -> WorkflowReport {
+    let workflows = handle.state::<Workflows>();
+    let run_id = workflows.next_id.fetch_add(1, Ordering::Relaxed);
+    let stopped = Arc::new(AtomicBool::new(false));
+    workflows.active.lock().unwrap().insert(
+        run_id,
+        Run {
+            stopped: stopped.clone(),
+            asking: false,
+        },
+    );
+    // The actions are journaled and budgeted as a task of their own, so the run can be replayed
+    let task_id = format!(
+        "workflow-{}-{}",
+        run_id,
+        chrono::Utc::now().format("%Y%m%dT%H%M%S")
+    );
+    handle.state::<Journal>().start_task(task_id.clone());
+    handle.state::<Governor>().start_task(task_id.clone());
+    log::info!(
+        "workflow {}: running {:?} as {}",
+        run_id,
+        workflow.name,
+        task_id
+    );
+
+    let started = Instant::now();
+    let mut interpreter = Interpreter {
+        handle,
+        run_id,
+        task_id,
+        stopped: stopped.clone(),
+        variables: workflow.variables.clone(),
+        reports: Vec::new(),
+    };
+    interpreter.variables.extend(variables);
+    let outcome = interpreter.run_steps("", &workflow.steps).await;
+    workflows.active.lock().unwrap().remove(&run_id);
+
+    // Optional steps that failed are in the report but don't fail the run
+    let status = match outcome {
+        Ok(_) => StepStatus::Passed,
+        Err(()) if stopped.load(Ordering::Relaxed) => StepStatus::Stopped,
+        Err(()) => StepStatus::Failed,
+    };
+    let report = WorkflowReport {
+        run_id,
+        workflow: workflow.name.clone(),
+        status,
+        steps: interpreter.reports,
+        variables: interpreter.variables,
+        duration_ms: started.elapsed().as_millis() as u64,
+    };
+    log::info!("workflow {}: {:?}", run_id, report.status);
+    let _ = handle.emit("agent_workflow_finished", &report);
+    report
+}
+
+/// Runs a workflow from a file, or from source text in the given format
+#[command]
+pub async fn run_workflow<R: Runtime>(
+    handle: AppHandle<R>,
+    path: Option<String>,
+    source: Option<String>,
+    format: Option<WorkflowFormat>,
+    variables: Option<HashMap<String, String>>,
+) -> Result<WorkflowReport, String> {
+    let workflow = match (path, source) {
+        (Some(path), None) => Workflow::load(Path::new(&path))?,
+        (None, Some(source)) => Workflow::parse(&source, format.unwrap_or(WorkflowFormat::Yaml))?,
+        _ => return Err("Pass either a workflow path or its source".to_string()),
+    };
+    Ok(run(&handle, &workflow, variables.unwrap_or_default()).await)
+}
+
+#[command]
+pub fn stop_workflow(
+    workflows: State<'_, Workflows>,
+    runner: State<'_, AgentRunner>,
+    approvals: State<'_, Approvals>,
+    run_id: u64,
+) -> Result<(), String> {
+    let active = workflows.active.lock().unwrap();
+    let run = active
+        .get(&run_id)
+        .ok_or(format!("Workflow {} is not running", run_id))?;
+    run.stopped.store(true, Ordering::Relaxed);
+    // The agent's task is part of the workflow, it stops along with it
+    if run.asking {
+        agent::stop_agent(runner, approvals)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> HashMap<String, String> {
+        HashMap::from([
+            ("user".to_string(), "ada".to_string()),
+            ("index".to_string(), "2".to_string()),
+        ])
+    }
+
+    #[test]
+    fn substitute_fills_in_variables() {
+        assert_eq!(
+            substitute("Hi {{user}}, row {{ index }}", &variables()).unwrap(),
+            "Hi ada, row 2"
+        );
+        assert_eq!(
+            substitute("no placeholders", &variables()).unwrap(),
+            "no placeholders"
+        );
+    }
+
+    #[test]
+    fn substitute_leaves_secrets_for_type_text() {
+        assert_eq!(
+            substitute("{{user}}: {{secret:password}}", &variables()).unwrap(),
+            "ada: {{secret:password}}"
+        );
+    }
+
+    #[test]
+    fn substitute_names_the_first_unknown_variable() {
+        assert_eq!(
+            substitute("{{user}} {{missing}} {{other}}", &variables()),
+            Err("Unknown variable \"missing\"".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_flattened_steps_from_yaml() {
+        let workflow = Workflow::parse(
+            r#"
+name: sign in
+variables:
+  user: ada
+steps:
+  - click: { x: 10, y: 20 }
+    name: focus
+  - type_text: "{{user}}"
+  - wait: 500
+    optional: true
+  - repeat:
+      times: 2
+      steps:
+        - keypress: [tab]
+  - if:
+      condition: { text_visible: Welcome }
+      else:
+        - ask_agent: { prompt: Sign in, save_as: answer }
+"#,
+            WorkflowFormat::Yaml,
+        )
+        .unwrap();
+        assert_eq!(workflow.variables["user"], "ada");
+        let steps = &workflow.steps;
+        assert_eq!(steps.len(), 5);
+        assert_eq!(steps[0].name.as_deref(), Some("focus"));
+        assert!(matches!(
+            steps[0].kind,
+            StepKind::Click { x, y, button: None } if x == 10.0 && y == 20.0
+        ));
+        assert!(matches!(&steps[1].kind, StepKind::TypeText(text) if text == "{{user}}"));
+        assert!(steps[2].optional);
+        assert!(matches!(steps[2].kind, StepKind::Wait(500)));
+        let StepKind::Repeat {
+            times,
+            steps: inner,
+        } = &steps[3].kind
+        else {
+            panic!("expected a repeat, got {:?}", steps[3].kind);
+        };
+        assert_eq!(*times, 2);
+        assert!(matches!(&inner[0].kind, StepKind::Keypress(keys) if keys == &["tab"]));
+        let StepKind::If {
+            condition,
+            then,
+            otherwise,
+        } = &steps[4].kind
+        else {
+            panic!("expected an if, got {:?}", steps[4].kind);
+        };
+        assert!(matches!(condition, Condition::TextVisible(text) if text == "Welcome"));
+        assert!(then.is_empty());
+        assert!(matches!(
+            &otherwise[0].kind,
+            StepKind::AskAgent { prompt, save_as: Some(name) } if prompt == "Sign in" && name == "answer"
+        ));
+    }
+
+    #[test]
+    fn parses_flattened_steps_from_toml() {
+        let workflow = Workflow::parse(
+            r#"
+name = "scroll"
+
+[[steps]]
+name = "down"
+scroll = { x = 100, y = 200, scroll_y = 3 }
+
+[[steps]]
+wait_for = { condition = { equals = ["a", "a"] }, timeout_ms = 1000 }
+
+[[steps]]
+drag = [[0, 0], [10.5, 20]]
+"#,
+            WorkflowFormat::Toml,
+        )
+        .unwrap();
+        let steps = &workflow.steps;
+        assert_eq!(steps[0].name.as_deref(), Some("down"));
+        assert!(matches!(
+            steps[0].kind,
+            StepKind::Scroll { x, y, scroll_x, scroll_y }
+                if x == 100.0 && y == 200.0 && scroll_x == 0.0 && scroll_y == 3.0
+        ));
+        assert!(matches!(
+            &steps[1].kind,
+            StepKind::WaitFor { condition: Condition::Equals(a, b), timeout_ms: Some(1000) }
+                if a == "a" && b == "a"
+        ));
+        assert!(
+            matches!(&steps[2].kind, StepKind::Drag(path) if path == &[(0.0, 0.0), (10.5, 20.0)])
+        );
+    }
+
+    #[test]
+    fn rejects_a_step_without_a_kind() {
+        assert!(Workflow::parse(
+            "name: broken\nsteps:\n  - name: nothing\n",
+            WorkflowFormat::Yaml
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,191 @@
+use std::sync::Mutex;
+use tauri::{command, AppHandle, Manager, Runtime, State};
+
+use super::error::{CommandError, ErrorKind};
+use super::image::{action_scale, Region};
+use crate::config;
+
+const CONFIG_NAME: &str = "zones";
+
+/// The corner of a monitor a zone is measured from
+#[derive(Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// A rectangle actions must not land in, like a taskbar or a menu with a shut down button
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ForbiddenZone {
+    pub name: String,
+    /// Index of the monitor the zone is on, every monitor when unset
+    #[serde(default)]
+    pub monitor: Option<usize>,
+    #[serde(default)]
+    pub anchor: Anchor,
+    /// Distance from the anchor corner towards the middle of the monitor, in action coordinates
+    #[serde(default)]
+    pub x: f64,
+    #[serde(default)]
+    pub y: f64,
+    /// Size of the zone, reaching the opposite edge when unset
+    #[serde(default)]
+    pub width: Option<f64>,
+    #[serde(default)]
+    pub height: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ZoneConfig {
+    pub zones: Vec<ForbiddenZone>,
+}
+
+/// The forbidden zones, persisted to `~/.paimon/zones.json`
+pub struct Zones(Mutex<ZoneConfig>);
+
+impl Default for Zones {
+    fn default() -> Self {
+        Zones(Mutex::new(config::load(CONFIG_NAME)))
+    }
+}
+
+impl ForbiddenZone {
+    // Places the zone on a monitor, both in action coordinates
+    fn resolve(&self, monitor: &Region) -> Region {
+        let width = self.width.unwrap_or(monitor.width - self.x).max(0.0);
+        let height = self.height.unwrap_or(monitor.height - self.y).max(0.0);
+        let x = match self.anchor {
+            Anchor::TopLeft | Anchor::BottomLeft => monitor.x + self.x,
+            Anchor::TopRight | Anchor::BottomRight => monitor.x + monitor.width - self.x - width,
+        };
+        let y = match self.anchor {
+            Anchor::TopLeft | Anchor::TopRight => monitor.y + self.y,
+            Anchor::BottomLeft | Anchor::BottomRight => {
+                monitor.y + monitor.height - self.y - height
+            }
+        };
+        Region {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+fn contains(region: &Region, x: f64, y: f64) -> bool {
+    x >= region.x && y >= region.y && x < region.x + region.width && y < region.y + region.height
+}
+
+/// The bounds of every monitor in action coordinates, in the order the OS reports them
+pub fn monitor_bounds<R: Runtime>(handle: &AppHandle<R>) -> Result<Vec<Region>, String> {
+    let monitors = handle
+        .available_monitors()
+        .map_err(|e| format!("Failed to list monitors: {}", e))?;
+    Ok(monitors
+        .iter()
+        .map(|m| {
+            let scale = action_scale(m.scale_factor());
+            Region {
+                x: m.position().x as f64 / scale,
+                y: m.position().y as f64 / scale,
+                width: m.size().width as f64 / scale,
+                height: m.size().height as f64 / scale,
+            }
+        })
+        .collect())
+}
+
+/// Refuses points that are off every monitor
+pub fn check_on_screen<R: Runtime>(
+    handle: &AppHandle<R>,
+    x: f64,
+    y: f64,
+) -> Result<(), CommandError> {
+    let monitors = monitor_bounds(handle)?;
+    if x.is_finite() && y.is_finite() && monitors.iter().any(|m| contains(m, x, y)) {
+        return Ok(());
+    }
+    log::warn!("zones: refusing {}, {}, it is not on any monitor", x, y);
+    Err(CommandError::new(
+        ErrorKind::OutOfBounds { x, y },
+        format!(
+            "{}, {} is outside the screen ({})",
+            x,
+            y,
+            monitors
+                .iter()
+                .map(|m| format!("{}x{} at {}, {}", m.width, m.height, m.x, m.y))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    ))
+}
+
+/// Refuses points inside a forbidden zone
+pub fn check_zones<R: Runtime>(handle: &AppHandle<R>, x: f64, y: f64) -> Result<(), CommandError> {
+    let config = handle.state::<Zones>().0.lock().unwrap().clone();
+    if config.zones.is_empty() {
+        return Ok(());
+    }
+    let monitors = monitor_bounds(handle)?;
+    for zone in &config.zones {
+        let on: Vec<&Region> = match zone.monitor {
+            Some(index) => monitors.get(index).into_iter().collect(),
+            None => monitors.iter().collect(),
+        };
+        if !on.iter().any(|m| contains(&zone.resolve(m), x, y)) {
+            continue;
+        }
+        log::warn!(
+            "zones: refusing {}, {}, it is in forbidden zone {:?}",
+            x,
+            y,
+            zone.name
+        );
+        return Err(CommandError::new(
+            ErrorKind::ForbiddenZone {
+                zone: zone.name.clone(),
+                x,
+                y,
+            },
+            format!("{}, {} is in the forbidden zone {:?}", x, y, zone.name),
+        ));
+    }
+    Ok(())
+}
+
+#[command]
+pub fn get_zone_config(zones: State<'_, Zones>) -> ZoneConfig {
+    zones.0.lock().unwrap().clone()
+}
+
+#[command]
+pub fn set_zone_config(zones: State<'_, Zones>, config: ZoneConfig) -> Result<(), String> {
+    config::save(CONFIG_NAME, &config)?;
+    *zones.0.lock().unwrap() = config;
+    Ok(())
+}
+
+/// Every forbidden zone placed on the monitors, for showing them to the user
+#[command]
+pub fn list_forbidden_zones<R: Runtime>(
+    handle: AppHandle<R>,
+) -> Result<Vec<(String, Region)>, String> {
+    let config = handle.state::<Zones>().0.lock().unwrap().clone();
+    let monitors = monitor_bounds(&handle)?;
+    let mut zones = Vec::new();
+    for zone in &config.zones {
+        for (index, monitor) in monitors.iter().enumerate() {
+            if zone.monitor.is_none_or(|m| m == index) {
+                zones.push((zone.name.clone(), zone.resolve(monitor)));
+            }
+        }
+    }
+    Ok(zones)
+}
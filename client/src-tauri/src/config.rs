@@ -0,0 +1,30 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+fn config_path(name: &str) -> PathBuf {
+    crate::dot_paimon_dir().join(format!("{}.json", name))
+}
+
+/// Reads `~/.paimon/<name>.json`, falling back to the default when it is missing or invalid
+pub fn load<T: DeserializeOwned + Default>(name: &str) -> T {
+    let path = config_path(name);
+    let Ok(contents) = fs::read_to_string(&path) else {
+        return T::default();
+    };
+    serde_json::from_str(&contents).unwrap_or_else(|e| {
+        log::warn!("Ignoring invalid config {}: {}", path.display(), e);
+        T::default()
+    })
+}
+
+/// Writes `~/.paimon/<name>.json`
+pub fn save<T: Serialize>(name: &str, value: &T) -> Result<(), String> {
+    let path = config_path(name);
+    fs::create_dir_all(crate::dot_paimon_dir())
+        .map_err(|e| format!("Failed to create config directory: {}", e))?;
+    let contents = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Failed to serialize config {}: {}", name, e))?;
+    fs::write(&path, contents)
+        .map_err(|e| format!("Failed to write config {}: {}", path.display(), e))
+}
@@ -0,0 +1,207 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+// Encrypted credentials, for when there is no keyring, in `~/.paimon/credentials.json`
+const FILE_NAME: &str = "credentials";
+const KEY_FILE: &str = "credentials.key";
+const NONCE_LEN: usize = 12;
+
+/// Where a credential is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Store {
+    /// The macOS keychain, the Windows credential manager or the Secret Service on Linux
+    Keyring,
+    /// `~/.paimon/credentials.json`, encrypted with a key kept beside it. That is obfuscation,
+    /// not protection: anything that can read the file can read the key too.
+    File,
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+mod keyring_store {
+    use keyring::{Entry, Error};
+
+    const SERVICE: &str = "paimon";
+
+    fn entry(name: &str) -> Result<Entry, String> {
+        Entry::new(SERVICE, name).map_err(|e| format!("Failed to open the keyring: {}", e))
+    }
+
+    // There may be no keyring to talk to, like a Linux session without a Secret Service
+    fn unavailable(error: &Error) -> bool {
+        matches!(error, Error::NoStorageAccess(_) | Error::PlatformFailure(_))
+    }
+
+    pub fn get(name: &str) -> Result<Option<String>, String> {
+        match entry(name)?.get_password() {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NoEntry) => Ok(None),
+            Err(e) if unavailable(&e) => {
+                log::debug!("credentials: no keyring for {}: {}", name, e);
+                Ok(None)
+            }
+            Err(e) => Err(format!("Failed to read {} from the keyring: {}", name, e)),
+        }
+    }
+
+    pub fn set(name: &str, value: &str) -> Result<(), String> {
+        entry(name)?
+            .set_password(value)
+            .map_err(|e| format!("Failed to store {} in the keyring: {}", name, e))
+    }
+
+    pub fn delete(name: &str) -> Result<(), String> {
+        match entry(name)?.delete_credential() {
+            Ok(()) | Err(Error::NoEntry) => Ok(()),
+            Err(e) if unavailable(&e) => Ok(()),
+            Err(e) => Err(format!("Failed to remove {} from the keyring: {}", name, e)),
+        }
+    }
+}
+
+// Without a keyring everything goes to the file
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+mod keyring_store {
+    pub fn get(_name: &str) -> Result<Option<String>, String> {
+        Ok(None)
+    }
+
+    pub fn set(_name: &str, _value: &str) -> Result<(), String> {
+        Err("No keyring on this platform".to_string())
+    }
+
+    pub fn delete(_name: &str) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+fn key_path() -> PathBuf {
+    crate::dot_paimon_dir().join(KEY_FILE)
+}
+
+fn file_path() -> PathBuf {
+    crate::dot_paimon_dir().join(format!("{}.json", FILE_NAME))
+}
+
+// The file's encryption key, created the first time something is stored. Only a missing key is
+// replaced, one that can't be read must not make the stored credentials unreadable.
+fn cipher(create: bool) -> Result<Option<ChaCha20Poly1305>, String> {
+    let path = key_path();
+    let key = match fs::read(&path) {
+        Ok(key) => key,
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            return Err(format!("Failed to read {}: {}", path.display(), e))
+        }
+        Err(_) if !create => return Ok(None),
+        Err(_) => {
+            let key = ChaCha20Poly1305::generate_key(&mut OsRng).to_vec();
+            write_private(&path, &key)?;
+            key
+        }
+    };
+    ChaCha20Poly1305::new_from_slice(&key)
+        .map(Some)
+        .map_err(|_| format!("Invalid credentials key {}", path.display()))
+}
+
+// Writes a file only the user can read, tightening the permissions of one that exists already
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), String> {
+    fs::create_dir_all(crate::dot_paimon_dir())
+        .map_err(|e| format!("Failed to create config directory: {}", e))?;
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options
+        .open(path)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    #[cfg(unix)]
+    file.set_permissions(fs::Permissions::from_mode(0o600))
+        .map_err(|e| format!("Failed to restrict {}: {}", path.display(), e))?;
+    file.write_all(contents)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn file_get(name: &str) -> Result<Option<String>, String> {
+    let stored: HashMap<String, String> = crate::config::load(FILE_NAME);
+    let Some(encoded) = stored.get(name) else {
+        return Ok(None);
+    };
+    let cipher = cipher(false)?.ok_or("The credentials key is missing")?;
+    let data = STANDARD
+        .decode(encoded)
+        .map_err(|e| format!("Invalid stored credential {}: {}", name, e))?;
+    if data.len() < NONCE_LEN {
+        return Err(format!("Invalid stored credential {}", name));
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let value = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| format!("Failed to decrypt credential {}", name))?;
+    String::from_utf8(value)
+        .map(Some)
+        .map_err(|_| format!("Invalid stored credential {}", name))
+}
+
+fn file_set(name: &str, value: Option<&str>) -> Result<(), String> {
+    let mut stored: HashMap<String, String> = crate::config::load(FILE_NAME);
+    match value {
+        Some(value) => {
+            let cipher = cipher(true)?.ok_or("The credentials key is missing")?;
+            let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+            let ciphertext = cipher
+                .encrypt(&nonce, value.as_bytes())
+                .map_err(|_| format!("Failed to encrypt credential {}", name))?;
+            let mut data = nonce.to_vec();
+            data.extend(ciphertext);
+            stored.insert(name.to_string(), STANDARD.encode(data));
+        }
+        None => {
+            if stored.remove(name).is_none() {
+                return Ok(());
+            }
+        }
+    }
+    let contents = serde_json::to_vec_pretty(&stored)
+        .map_err(|e| format!("Failed to serialize credentials: {}", e))?;
+    write_private(&file_path(), &contents)
+}
+
+/// Reads a credential and where it was found, the keyring first
+pub fn get(name: &str) -> Result<Option<(String, Store)>, String> {
+    if let Some(value) = keyring_store::get(name)? {
+        return Ok(Some((value, Store::Keyring)));
+    }
+    Ok(file_get(name)?.map(|value| (value, Store::File)))
+}
+
+/// Stores a credential in the keyring, or in the encrypted file when there is none
+pub fn set(name: &str, value: &str) -> Result<Store, String> {
+    let store = match keyring_store::set(name, value) {
+        Ok(()) => {
+            // drop any copy left in the file from before
+            file_set(name, None)?;
+            Store::Keyring
+        }
+        Err(e) => {
+            log::warn!("credentials: {}, storing {} in a file", e, name);
+            file_set(name, Some(value))?;
+            Store::File
+        }
+    };
+    log::info!("credentials: stored {} in {:?}", name, store);
+    Ok(store)
+}
+
+pub fn delete(name: &str) -> Result<(), String> {
+    keyring_store::delete(name)?;
+    file_set(name, None)?;
+    log::info!("credentials: removed {}", name);
+    Ok(())
+}
@@ -14,6 +14,15 @@ use tauri::ActivationPolicy;
 use cocoa::appkit::NSWindow;
 
 mod commands;
+mod config;
+mod credentials;
+
+pub use commands::teach::run_input_hook;
+
+/// `~/.paimon`, where the app keeps its logs and everything else it persists
+pub fn dot_paimon_dir() -> std::path::PathBuf {
+    home::home_dir().unwrap().join(".paimon")
+}
 
 #[allow(non_upper_case_globals)]
 const NSWindowStyleMaskNonActivatingPanel: i32 = 1 << 7;
@@ -99,6 +108,9 @@ pub fn run() {
                 overlay_window.show().unwrap();
                 spotlight_window.show().unwrap();
             });
+
+            // `paimon replay <task_id>` while the app is already running replays in this instance
+            commands::replay::run_cli(handle, &_args, false);
         }))
         .plugin(tauri_plugin_screenshots::init())
         .plugin(tauri_plugin_opener::init())
@@ -106,6 +118,23 @@ pub fn run() {
         .plugin(tauri_plugin_macos_permissions::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_os::init())
+        .manage(commands::watch::Watchers::default())
+        .manage(commands::ocr::Ocr::default())
+        .manage(commands::marks::Marks::default())
+        .manage(commands::redact::Redactor::default())
+        .manage(commands::privacy::Privacy::default())
+        .manage(commands::zones::Zones::default())
+        .manage(commands::action::LastActionPoint::default())
+        .manage(commands::action::ActivePolicy::default())
+        .manage(commands::approval::Approvals::default())
+        .manage(commands::governor::Governor::default())
+        .manage(commands::journal::Journal::default())
+        .manage(commands::replay::Replays::default())
+        .manage(commands::teach::Teacher::default())
+        .manage(commands::workflow::Workflows::default())
+        .manage(commands::agent::AgentRunner::default())
+        .manage(commands::provider::Providers::default())
+        .manage(commands::secrets::Secrets::default())
         .setup(|app| {
             let overlay_window = app
                 .get_webview_window("overlay")
@@ -247,6 +276,9 @@ pub fn run() {
                 }))
                 .unwrap_or_else(|err| println!("Failed to set main window size: {:?}", err));
 
+            let args: Vec<String> = std::env::args().collect();
+            commands::replay::run_cli(app.handle(), &args, true);
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -255,11 +287,76 @@ pub fn run() {
             commands::action::click,
             commands::action::scroll,
             commands::action::double_click,
+            commands::action::triple_click,
+            commands::action::mouse_down,
+            commands::action::mouse_up,
             commands::action::keypress,
+            commands::action::hold_key,
             commands::action::type_text,
             commands::action::wait,
             commands::action::move_mouse,
             commands::action::drag,
+            commands::action::set_app_allowlist,
+            commands::action::get_app_allowlist,
+            commands::action::clear_app_allowlist,
+            commands::action::set_dry_run,
+            commands::action::get_dry_run,
+            commands::approval::respond_to_approval,
+            commands::approval::get_approval_config,
+            commands::approval::set_approval_config,
+            commands::governor::start_task_budget,
+            commands::governor::get_budget_usage,
+            commands::governor::get_governor_config,
+            commands::governor::set_governor_config,
+            commands::journal::start_journal_task,
+            commands::journal::set_journal_step,
+            commands::journal::query_journal,
+            commands::replay::replay_journal,
+            commands::replay::resume_replay,
+            commands::replay::stop_replay,
+            commands::teach::start_teaching,
+            commands::teach::stop_teaching,
+            commands::workflow::run_workflow,
+            commands::workflow::stop_workflow,
+            commands::agent::start_agent,
+            commands::agent::pause_agent,
+            commands::agent::resume_agent,
+            commands::agent::stop_agent,
+            commands::agent::get_agent_state,
+            commands::provider::get_provider_config,
+            commands::provider::set_provider_config,
+            commands::provider::set_api_key,
+            commands::provider::get_api_keys,
+            commands::secrets::set_secret,
+            commands::secrets::list_secrets,
+            commands::skills::list_skills,
+            commands::skills::get_skill,
+            commands::skills::create_skill,
+            commands::skills::edit_skill,
+            commands::skills::import_skill,
+            commands::skills::export_skill,
+            commands::skills::run_skill,
+            commands::watch::wait_until_stable,
+            commands::watch::watch_region,
+            commands::watch::wait_for_change,
+            commands::watch::cancel_watch,
+            commands::image::save_capture,
+            commands::template::find_image_on_screen,
+            commands::color::get_pixel_color,
+            commands::color::find_color,
+            commands::ocr::recognize_text,
+            commands::text::click_text,
+            commands::text::wait_for_text,
+            commands::text::assert_text_present,
+            commands::marks::resolve_mark,
+            commands::marks::click_mark,
+            commands::redact::get_redaction_config,
+            commands::redact::set_redaction_config,
+            commands::privacy::get_privacy_config,
+            commands::privacy::set_privacy_config,
+            commands::zones::get_zone_config,
+            commands::zones::set_zone_config,
+            commands::zones::list_forbidden_zones,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -5,8 +5,7 @@ use std::fs::File;
 use simplelog::{CombinedLogger, Config, TermLogger, TerminalMode, WriteLogger};
 fn setup_logger() -> Result<(), Box<dyn std::error::Error>> {
     // Create logs directory if it doesn't exist
-    let dot_paimon_path = home::home_dir().unwrap().join(".paimon");
-    let logs_path = dot_paimon_path.join("logs");
+    let logs_path = paimon_lib::dot_paimon_dir().join("logs");
     println!("Logs directory: {}", logs_path.display());
     std::fs::create_dir_all(&logs_path)?;
 
@@ -35,6 +34,10 @@ fn setup_logger() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 fn main() {
+    // Started as teaching's input hook, which owns stdout, see teach.rs
+    if paimon_lib::run_input_hook() {
+        return;
+    }
     setup_logger().unwrap();
     std::panic::set_hook(Box::new(|panic_info| {
         log::error!("Application panic: {}", panic_info);
@@ -1,17 +1,17 @@
-import { getResponse, takeAction } from "@/lib/cua";
 import { useGlobalShortcut } from "@/lib/shortcut";
 import { cn } from "@/lib/utils";
 import { appStore } from "@/stores/app";
 import { invoke } from "@tauri-apps/api/core";
-import { emit } from "@tauri-apps/api/event";
+import { emit, listen } from "@tauri-apps/api/event";
 import { getCurrentWindow } from "@tauri-apps/api/window";
 import {
   LoaderCircle,
+  PauseIcon,
+  PlayIcon,
   SparkleIcon,
   SparklesIcon,
   SquarePenIcon,
 } from "lucide-react";
-import OpenAI from "openai";
 import { usePostHog } from "posthog-js/react";
 import { useCallback, useEffect, useRef, useState } from "react";
 import { useLocalStorage } from "usehooks-ts";
@@ -90,10 +90,160 @@ export interface BrowserStep {
   messageId?: string;
 }
 
+// An action held by the backend until the user approves or denies it
+interface ApprovalRequest {
+  id: number;
+  // null when the provider's safety checks hold a call that does nothing on screen
+  action: ({ type: string } & Record<string, unknown>) | null;
+  rule: string;
+  // the screen with the intended action drawn on it
+  screenshot: string;
+  timeout_ms: number;
+}
+
 interface AgentState {
   steps: BrowserStep[];
 }
 
+// An input action as the backend performs it
+type AgentAction =
+  | { type: "click"; button: string; x: number; y: number }
+  | { type: "double_click"; x: number; y: number }
+  | { type: "triple_click"; x: number; y: number }
+  | { type: "mouse_down"; button: string; x: number; y: number }
+  | { type: "mouse_up"; button: string; x: number; y: number }
+  | {
+      type: "scroll";
+      x: number;
+      y: number;
+      scroll_x: number;
+      scroll_y: number;
+    }
+  | { type: "keypress"; keys: string[] }
+  | { type: "hold_key"; keys: string[]; ms: number }
+  | { type: "type_text"; text: string }
+  | { type: "wait"; ms: number }
+  | { type: "move_mouse"; x: number; y: number }
+  | { type: "drag"; path: [number, number][] };
+
+// What run_skill returns, a workflow skill has already finished when it does
+type SkillRun =
+  | { type: "prompt"; run_id: number; prompt: string }
+  | {
+      type: "workflow";
+      report: {
+        status: "passed" | "failed" | "stopped";
+        duration_ms: number;
+        steps: {
+          path: string;
+          name: string | null;
+          kind: string;
+          status: "passed" | "failed" | "stopped";
+          error: { message: string } | null;
+        }[];
+      };
+    };
+
+type RunnerState =
+  | "idle"
+  | "running"
+  | "paused"
+  | "awaiting_approval"
+  | "finished"
+  | "failed";
+
+// What the backend's agent runner reports while it works
+type AgentProgress = { run_id: number } & (
+  | { type: "started"; task: string }
+  | { type: "state_changed"; state: RunnerState; reason: string | null }
+  | { type: "message"; text: string }
+  | { type: "refusal"; text: string }
+  | { type: "action"; call_id: string; action: AgentAction }
+  | {
+      type: "observe";
+      call_id: string;
+      kind: "screenshot" | "wait" | "cursor_position";
+    }
+  | { type: "function_call"; call_id: string; name: string; arguments: unknown }
+  | {
+      type: "refused";
+      call_id: string;
+      error: { kind: string; message: string };
+    }
+);
+
+const ActionToTool: Record<AgentAction["type"], Tool> = {
+  click: "CLICK",
+  double_click: "DOUBLE_CLICK",
+  triple_click: "DOUBLE_CLICK",
+  mouse_down: "DRAG",
+  mouse_up: "DRAG",
+  scroll: "SCROLL",
+  keypress: "KEYPRESS",
+  hold_key: "KEYPRESS",
+  type_text: "TYPE",
+  wait: "WAIT",
+  move_mouse: "MOVE",
+  drag: "DRAG",
+};
+
+function describeAction(action: AgentAction): string {
+  switch (action.type) {
+    case "click":
+      return "Clicking at " + action.x + ", " + action.y;
+    case "double_click":
+      return "Double clicking at " + action.x + ", " + action.y;
+    case "triple_click":
+      return "Triple clicking at " + action.x + ", " + action.y;
+    case "mouse_down":
+      return (
+        "Pressing the " +
+        action.button +
+        " button at " +
+        action.x +
+        ", " +
+        action.y
+      );
+    case "mouse_up":
+      return (
+        "Releasing the " +
+        action.button +
+        " button at " +
+        action.x +
+        ", " +
+        action.y
+      );
+    case "drag":
+      return (
+        "Dragging from " +
+        action.path[0] +
+        " to " +
+        action.path[action.path.length - 1]
+      );
+    case "type_text":
+      return "Typing " + action.text;
+    case "keypress":
+      return "Pressing " + action.keys.join(", ");
+    case "hold_key":
+      return "Holding " + action.keys.join(", ") + " for " + action.ms + "ms";
+    case "move_mouse":
+      return "Moving to " + action.x + ", " + action.y;
+    case "scroll":
+      return "Scrolling " + action.scroll_x + " " + action.scroll_y;
+    case "wait":
+      return "Waiting for " + action.ms + "ms";
+  }
+}
+
+// The words of a chat command like `/expense note="team lunch"`, with each
+// key=value split and its quotes removed. Plain words have no value.
+function parseCommand(input: string): [string, string | undefined][] {
+  return Array.from(
+    input.matchAll(/([^\s=]+)(?:=(?:"([^"]*)"|'([^']*)'|(\S*)))?/g),
+    ([, key, double, single, bare]) => [key, double ?? single ?? bare],
+  );
+}
+
 export function Chat({ className, ...props }: ChatProps) {
   const snap = useSnapshot(appStore);
   const [isWaitingForAgent, setIsWaitingForAgent] = useState(false);
@@ -104,13 +254,13 @@ export function Chat({ className, ...props }: ChatProps) {
   isWaitingForInputRef.current = isWaitingForInput;
   const [userInput, setUserInput] = useState("");
   const inputRef = useRef<HTMLTextAreaElement>(null);
-  const currentResponseIdRef = useRef<string | null>(null);
-  const [isAgentFinished, setIsAgentFinished] = useState(false);
-  const isAgentFinishedRef = useRef(false);
-  isAgentFinishedRef.current = isAgentFinished;
+  const [runnerState, setRunnerState] = useState<RunnerState>("idle");
+  // the next task starts a new conversation instead of continuing the last one
+  const newConversationRef = useRef(false);
   const posthog = usePostHog();
   const chatContainerRef = useRef<HTMLDivElement>(null);
   const [isScrolled, setIsScrolled] = useState(false);
+  const [approval, setApproval] = useState<ApprovalRequest | null>(null);
 
   // Define shortcut ID constant
   const TOGGLE_AGENT_SHORTCUT = "toggle-agent";
@@ -128,19 +278,39 @@ export function Chat({ className, ...props }: ChatProps) {
     emit("agent_waiting_for_agent", {
       isWaitingForAgent,
     });
-    getCurrentWindow().setIgnoreCursorEvents(isWaitingForAgent);
-  }, [isWaitingForAgent]);
+    // the approval buttons have to stay clickable while the agent waits on them
+    getCurrentWindow().setIgnoreCursorEvents(isWaitingForAgent && !approval);
+  }, [isWaitingForAgent, approval]);
 
   useEffect(() => {
-    agentStateRef.current.steps = uiState.steps;
-    if (uiState.steps.length > 0) {
-      // Find the last element with reasoning = "Processing message"
-      const processingMessageStep = [...uiState.steps]
-        .reverse()
-        .find((step) => step.type === "agent_msg");
+    const unlistenRequested = listen<ApprovalRequest>(
+      "agent_approval_requested",
+      (event) => setApproval(event.payload),
+    );
+    const unlistenResolved = listen<{ id: number }>(
+      "agent_approval_resolved",
+      (event) =>
+        setApproval((current) =>
+          current?.id === event.payload.id ? null : current,
+        ),
+    );
+    return () => {
+      unlistenRequested.then((fn) => fn());
+      unlistenResolved.then((fn) => fn());
+    };
+  }, []);
 
-      currentResponseIdRef.current = processingMessageStep?.messageId || null;
-    }
+  const respondToApproval = useCallback(
+    async (approved: boolean) => {
+      if (!approval) return;
+      setApproval(null);
+      await invoke("respond_to_approval", { id: approval.id, approved });
+    },
+    [approval],
+  );
+
+  useEffect(() => {
+    agentStateRef.current.steps = uiState.steps;
   }, []);
 
   const scrollToBottom = useCallback(() => {
@@ -181,15 +351,6 @@ export function Chat({ className, ...props }: ChatProps) {
     }
   }, []);
 
-  useEffect(() => {
-    if (
-      uiState.steps.length > 0 &&
-      uiState.steps[uiState.steps.length - 1].tool === "CLOSE"
-    ) {
-      setIsAgentFinished(true);
-    }
-  }, [uiState.steps]);
-
   useEffect(() => {
     scrollToBottom();
   }, [uiState.steps, scrollToBottom]);
@@ -198,8 +359,7 @@ export function Chat({ className, ...props }: ChatProps) {
   const toggleAgent = useCallback(() => {
     if (isWaitingForAgentRef.current) {
       // Stop the agent
-      setIsWaitingForAgent(false);
-      setIsWaitingForInput(true);
+      invoke("stop_agent");
     } else {
       // nothing for now
     }
@@ -210,196 +370,235 @@ export function Chat({ className, ...props }: ChatProps) {
     "KeyM",
   ]);
 
-  // Add a new function to process a single step
-  const processStep = useCallback(
-    async (response: OpenAI.Responses.Response, stepNumber = 1) => {
-      if (isAgentFinishedRef.current) {
-        return;
-      }
-
-      // Special case for reasoning-only response
-      if (
-        response.output.length === 1 &&
-        response.output[0]?.type === "reasoning"
-      ) {
-        console.log("Detected reasoning-only response, adding message item");
-        response.output.push({
-          id: `msg_fallback_${response.id || "default"}`,
-          type: "message",
-          role: "assistant",
-          content: [
-            {
-              type: "output_text",
-              text: "I'll help you with that task.",
-              annotations: [],
-            },
-          ],
-          status: "completed",
-        });
-      }
-
-      currentResponseIdRef.current = response.id;
-      console.log("processing steps: ", response);
-      const addBrowserStep = (step: BrowserStep) => {
-        const isDuplicate = agentStateRef.current.steps.some(
-          (s) => s.messageId === step.messageId && step.messageId !== undefined,
-        );
-
-        if (isDuplicate) {
-          return;
-        }
-
-        agentStateRef.current = {
-          steps: [...agentStateRef.current.steps, step],
-        };
-
-        setUiState({
-          steps: agentStateRef.current.steps,
-        });
-      };
-
-      let executedAnything = false;
-      let callOutputs = [];
+  const addStep = useCallback((step: Omit<BrowserStep, "stepNumber">) => {
+    agentStateRef.current = {
+      steps: [
+        ...agentStateRef.current.steps,
+        { ...step, stepNumber: agentStateRef.current.steps.length + 1 },
+      ],
+    };
+    setUiState({ steps: agentStateRef.current.steps });
+  }, []);
 
-      // Process all output items one by one
-      for (const item of response.output) {
-        if (item.type === "message") {
-          if (item.content[0]?.type === "output_text") {
-            addBrowserStep({
-              text: item.content[0].text,
-              type: "agent_msg",
+  // The agent runs in the backend, the chat only renders what it reports
+  useEffect(() => {
+    const unlisten = listen<AgentProgress>("agent_progress", (event) => {
+      const progress = event.payload;
+      switch (progress.type) {
+        case "started":
+          addStep({ text: progress.task, type: "user_msg", tool: "MESSAGE" });
+          break;
+        case "state_changed": {
+          const { state, reason } = progress;
+          const busy = state === "running" || state === "awaiting_approval";
+          setRunnerState(state);
+          setIsWaitingForAgent(busy);
+          setIsWaitingForInput(!busy && state !== "paused");
+          if (state === "paused") {
+            addStep({
+              text: reason
+                ? `Paused: ${reason}. Resume when you're ready.`
+                : "Paused",
+              type: "system_msg",
               tool: "MESSAGE",
-              stepNumber: stepNumber++,
-              messageId: item.id,
             });
-          } else if (item.content[0]?.type === "refusal") {
-            addBrowserStep({
-              text: item.content[0].refusal,
-              type: "agent_msg",
+          } else if (state === "failed") {
+            addStep({
+              text:
+                "Sorry, there was an error processing your request: " + reason,
+              type: "system_msg",
               tool: "MESSAGE",
-              stepNumber: stepNumber++,
-              messageId: item.id,
             });
-            setIsWaitingForInput(true);
-            setIsAgentFinished(true);
+          } else if (state === "idle" && reason) {
+            addStep({ text: reason, type: "system_msg", tool: "MESSAGE" });
           }
-        } else if (item.type === "computer_call") {
-          executedAnything = true;
-
-          // basic step
-          let step: BrowserStep = {
-            text: "Doing " + item.action.type || "",
-            type: "agent_msg",
-            tool: item.action.type.toUpperCase() as
-              | "TYPE"
-              | "CLICK"
-              | "DOUBLE_CLICK"
-              | "DRAG"
-              | "KEYPRESS"
-              | "MOVE"
-              | "SCREENSHOT"
-              | "SCROLL"
-              | "WAIT",
-            stepNumber: stepNumber++,
-          };
-
-          // prettier-ignore
-          if(item.action.type==="click") {
-            step.text = "Clicking at " + item.action.x + ", " + item.action.y;
-          } else if(item.action.type==="double_click") {
-            step.text = "Double clicking at " + item.action.x + ", " + item.action.y;
-          } else if(item.action.type==="drag") {
-            step.text = "Dragging from " + item.action.path[0] + " to " + item.action.path[item.action.path.length - 1];
-          } else if(item.action.type==="type") {
-            step.text = "Typing " + item.action.text;
-          } else if(item.action.type==="keypress") {
-            step.text = "Pressing " + item.action.keys.join(", ");
-          } else if(item.action.type==="move") {
-            step.text = "Moving to " + item.action.x + ", " + item.action.y;
-          } else if(item.action.type==="scroll") {
-            step.text = "Scrolling " + item.action.scroll_x + " " + item.action.scroll_y;
-          } else if(item.action.type==="screenshot") {
-            step.text = "Taking screenshot";
-          } else if(item.action.type==="wait") {
-            step.text = "Waiting for a moment";
-          }
-
-          addBrowserStep(step);
-          setIsWaitingForAgent(true);
-          const computerCallData = await takeAction([item]);
-          setIsWaitingForAgent(false);
-          callOutputs.push(...computerCallData);
-        } else if (item.type === "function_call") {
-          executedAnything = true;
-          // TODO: we don't have any functions yet
+          break;
         }
+        case "message":
+        case "refusal":
+          addStep({ text: progress.text, type: "agent_msg", tool: "MESSAGE" });
+          break;
+        case "action":
+          addStep({
+            text: describeAction(progress.action),
+            type: "agent_msg",
+            tool: ActionToTool[progress.action.type],
+          });
+          break;
+        case "observe":
+          addStep({
+            text:
+              progress.kind === "wait"
+                ? "Waiting for a moment"
+                : progress.kind === "cursor_position"
+                  ? "Checking the pointer position"
+                  : "Taking screenshot",
+            type: "agent_msg",
+            tool: progress.kind === "wait" ? "WAIT" : "SCREENSHOT",
+          });
+          break;
+        case "function_call":
+          addStep({
+            text:
+              "Calling " +
+              progress.name +
+              " " +
+              JSON.stringify(progress.arguments),
+            type: "agent_msg",
+            tool: "WAIT",
+          });
+          break;
+        case "refused":
+          addStep({
+            text: "Refused: " + progress.error.message,
+            type: "system_msg",
+            tool: "MESSAGE",
+          });
+          break;
       }
-
-      // If no action items, wait for user input
-      if (!executedAnything) {
-        setIsWaitingForInput(true);
-        return;
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [addStep]);
+
+  const handleUserInput = useCallback(
+    async (input: string) => {
+      if (!input.trim()) return;
+      setUserInput("");
+      try {
+        // every message is a task of its own, with fresh budgets and journal entries
+        await invoke("start_agent", {
+          task: input,
+          options: { new_conversation: newConversationRef.current },
+        });
+        newConversationRef.current = false;
+      } catch (error) {
+        addStep({
+          text: "Sorry, there was an error processing your request: " + error,
+          type: "system_msg",
+          tool: "MESSAGE",
+        });
       }
-
-      setIsWaitingForAgent(true);
-      const nextStepData = await getResponse(
-        callOutputs,
-        currentResponseIdRef.current,
-      );
-      setIsWaitingForAgent(false);
-      return processStep(nextStepData, stepNumber);
     },
-    [],
+    [addStep],
   );
 
-  const handleUserInput = useCallback(async (input: string) => {
-    if (!input.trim()) return;
-
-    const userStep: BrowserStep = {
-      text: input,
-      type: "user_msg",
-      tool: "MESSAGE",
-      stepNumber: agentStateRef.current.steps.length + 1,
-    };
-
-    // Update agent state and UI with the user step
-    const updateSteps = (newStep: BrowserStep) => {
-      agentStateRef.current = {
-        steps: [...agentStateRef.current.steps, newStep],
-      };
-
-      setUiState({
-        steps: agentStateRef.current.steps,
+  // "/key provider key" stores an API key in the backend, which never sends it back
+  const setApiKey = useCallback(async (input: string) => {
+    const [, provider, key] = input.trim().split(/\s+/);
+    setUserInput("");
+    if (!provider) {
+      addStep({
+        text: "Usage: /key openai|anthropic|local <key>, without a key to remove it",
+        type: "system_msg",
+        tool: "MESSAGE",
       });
-    };
-
-    updateSteps(userStep);
-    setIsWaitingForInput(false);
+      return;
+    }
+    try {
+      await invoke("set_api_key", { provider, key: key ?? null });
+      addStep({
+        text: key
+          ? `Saved the ${provider} API key`
+          : `Removed the ${provider} API key`,
+        type: "system_msg",
+        tool: "MESSAGE",
+      });
+    } catch (error) {
+      addStep({
+        text: `Failed to set the ${provider} API key: ${error}`,
+        type: "system_msg",
+        tool: "MESSAGE",
+      });
+    }
+  }, [addStep]);
+
+  // "/secret name value=... app=... title=..." stores a secret the agent types as
+  // {{secret:name}}, only into the app or windows with the title given
+  const setSecret = useCallback(async (input: string) => {
+    const [, secret, ...args] = parseCommand(input);
+    const name = secret?.[0];
+    const { value, app, title } = Object.fromEntries(args);
     setUserInput("");
-
+    if (!name) {
+      addStep({
+        text: 'Usage: /secret <name> value=<value> app="<app>" title="<part of the window title>", without a value to remove it',
+        type: "system_msg",
+        tool: "MESSAGE",
+      });
+      return;
+    }
     try {
-      setIsWaitingForAgent(true);
-      let nextStepData = await getResponse(
-        [{ role: "user", content: input }],
-        currentResponseIdRef.current,
-      );
-      setIsWaitingForAgent(false);
-      return processStep(nextStepData, agentStateRef.current.steps.length + 1);
+      await invoke("set_secret", {
+        name,
+        value: value ?? null,
+        app: app ?? null,
+        title: title ?? null,
+      });
+      addStep({
+        text: value
+          ? `Saved the secret ${name} as {{secret:${name}}}`
+          : `Removed the secret ${name}`,
+        type: "system_msg",
+        tool: "MESSAGE",
+      });
     } catch (error) {
-      console.error("Error handling user input:", error);
-      const errorStep: BrowserStep = {
-        text: "Sorry, there was an error processing your request: " + error,
+      addStep({
+        text: `Failed to set the secret ${name}: ${error}`,
         type: "system_msg",
         tool: "MESSAGE",
-        stepNumber: agentStateRef.current.steps.length + 1,
-      };
-
-      updateSteps(errorStep);
-      setIsWaitingForInput(true);
-      setIsWaitingForAgent(false);
-      return null;
+      });
     }
-  }, []);
+  }, [addStep]);
+
+  // "/name key=value ..." runs a saved skill, values with spaces go in quotes
+  const runSkill = useCallback(async (input: string) => {
+    const [name, ...args] = parseCommand(input.slice(1));
+    const skillName = name?.[0] ?? "";
+    const skillArguments = Object.fromEntries(
+      args.map(([key, value]) => [key, value ?? ""]),
+    );
+    setUserInput("");
+    try {
+      const run = await invoke<SkillRun>("run_skill", {
+        name: skillName,
+        arguments: skillArguments,
+      });
+      if (run.type === "workflow") {
+        const { report } = run;
+        // the last failed step is the one that ended the run, the first one
+        // inside it is where it went wrong
+        const last = [...report.steps]
+          .reverse()
+          .find((step) => step.status === "failed");
+        const failed = last
+          ? report.steps.find(
+              (step) =>
+                step.status === "failed" &&
+                (step.path === last.path ||
+                  step.path.startsWith(`${last.path}.`)),
+            )
+          : undefined;
+        addStep({
+          text:
+            report.status === "passed"
+              ? `Skill ${skillName} finished ${report.steps.length} steps in ${report.duration_ms}ms`
+              : failed
+                ? `Skill ${skillName} ${report.status} at step ${failed.path} (${failed.name ?? failed.kind}): ${failed.error?.message ?? "failed"}`
+                : `Skill ${skillName} ${report.status}`,
+          type: "system_msg",
+          tool: "MESSAGE",
+        });
+      }
+    } catch (error) {
+      addStep({
+        text: `Failed to run skill ${skillName}: ${error}`,
+        type: "system_msg",
+        tool: "MESSAGE",
+      });
+    }
+  }, [addStep]);
 
   return (
     <div
@@ -470,6 +669,34 @@ export function Chat({ className, ...props }: ChatProps) {
           </div>
         )}
       </div>
+      {approval && (
+        <div className="mx-4 flex flex-col space-y-3 rounded-lg border-[0.5px] border-amber-400/70 bg-[hsl(0,0%,20%)]/50 p-4 shadow-md">
+          <div className="text-sm font-medium text-neutral-200">
+            Allow {approval.action?.type.replace("_", " ") ?? "the agent to go on"}?
+            It needs approval for {approval.rule}.
+          </div>
+          <img
+            src={approval.screenshot}
+            alt="The screen with the action marked"
+            className="rounded-md border border-neutral-700"
+          />
+          <div className="flex justify-end gap-x-2">
+            <Button
+              variant="ghost"
+              className="cursor-pointer text-neutral-300"
+              onClick={() => respondToApproval(false)}
+            >
+              Deny
+            </Button>
+            <Button
+              className="cursor-pointer"
+              onClick={() => respondToApproval(true)}
+            >
+              Approve
+            </Button>
+          </div>
+        </div>
+      )}
       <div className="w-full p-4">
         <div
           className={cn(
@@ -484,7 +711,9 @@ export function Chat({ className, ...props }: ChatProps) {
             placeholder={
               isWaitingForAgent
                 ? `AI is working... Press ${shortcut.join("+")} to stop`
-                : "What's on your mind?"
+                : runnerState === "paused"
+                  ? "Paused"
+                  : "What's on your mind?"
             }
             disabled={!isWaitingForInput}
             value={userInput}
@@ -493,28 +722,51 @@ export function Chat({ className, ...props }: ChatProps) {
               if (e.key === "Enter" && !e.shiftKey) {
                 e.preventDefault();
                 if (["quit", "exit", "bye"].includes(userInput.toLowerCase())) {
-                  setIsAgentFinished(true);
+                  setUserInput("");
                   return;
                 } else {
-                  setIsAgentFinished(false);
-                  handleUserInput(userInput);
+                  if (/^\/key(\s|$)/.test(userInput)) {
+                    setApiKey(userInput);
+                  } else if (/^\/secret(\s|$)/.test(userInput)) {
+                    setSecret(userInput);
+                  } else if (userInput.startsWith("/")) {
+                    runSkill(userInput);
+                  } else {
+                    handleUserInput(userInput);
+                  }
                 }
               }
             }}
           />
           {isWaitingForAgent ? (
-            <div className="absolute right-2 bottom-2 flex size-9 animate-spin items-center justify-center rounded-full text-neutral-300">
-              <LoaderCircle className="h-4 w-4" />
-            </div>
+            <>
+              <Button
+                size="icon"
+                variant="ghost"
+                className="absolute right-12 bottom-2 cursor-pointer text-neutral-400 hover:text-neutral-300"
+                onClick={() => invoke("pause_agent")}
+              >
+                <PauseIcon className="h-4 w-4" />
+              </Button>
+              <div className="absolute right-2 bottom-2 flex size-9 animate-spin items-center justify-center rounded-full text-neutral-300">
+                <LoaderCircle className="h-4 w-4" />
+              </div>
+            </>
+          ) : runnerState === "paused" ? (
+            <Button
+              size="icon"
+              variant="ghost"
+              className="absolute right-2 bottom-2 cursor-pointer text-neutral-400 hover:text-neutral-300"
+              onClick={() => invoke("resume_agent")}
+            >
+              <PlayIcon className="h-4 w-4" />
+            </Button>
           ) : (
             <Button
               size="icon"
               variant="ghost"
               className="absolute right-2 bottom-2 cursor-pointer text-neutral-400 hover:text-neutral-300"
               onClick={() => {
-                setIsAgentFinished(true);
-                setIsWaitingForAgent(false);
-                setIsWaitingForInput(true);
                 setUserInput("");
                 setUiState({
                   steps: [],
@@ -523,7 +775,7 @@ export function Chat({ className, ...props }: ChatProps) {
                   steps: [],
                 };
 
-                currentResponseIdRef.current = null;
+                newConversationRef.current = true;
               }}
             >
               <SquarePenIcon className="h-4 w-4" />
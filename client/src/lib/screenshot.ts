@@ -1,7 +1,41 @@
 import { invoke } from "@tauri-apps/api/core";
 
-export const getScreenshot = async () => {
-    const result = await invoke<{ path: string; base64: string }>("get_screenshot");
+export type MarkSource =
+    | { type: "grid"; spacing?: number }
+    | { type: "elements" }
+    | { type: "text" };
+
+export type Mark = {
+    id: number;
+    x: number;
+    y: number;
+    bounds: { x: number; y: number; width: number; height: number };
+    label: string | null;
+};
+
+export type Redaction = {
+    // "region", "app:<name>", "pattern:<kind>" or "secret:<name>"
+    reason: string;
+    bounds: { x: number; y: number; width: number; height: number };
+};
+
+export type ScreenshotOptions = {
+    marks?: MarkSource | null;
+    // draw the mouse pointer, which capture APIs leave out
+    cursor?: boolean;
+    // mark where the last action landed
+    crosshair?: boolean;
+};
+
+export const getScreenshot = async (options: ScreenshotOptions = {}) => {
+    const result = await invoke<{
+        path: string;
+        base64: string;
+        marks: Mark[] | null;
+        redactions: Redaction[];
+        // the copy kept for the action journal
+        journal_id: string;
+    }>("get_screenshot", options);
     return result;
 };
 
@@ -43,6 +43,8 @@ type ActionEvent = {
   scroll_x?: number;
   scroll_y?: number;
   path?: Array<[number, number]>;
+  // set in dry run, when no input was actually sent
+  simulated?: boolean;
 };
 
 type VisualIndicator = {
@@ -55,7 +57,11 @@ type VisualIndicator = {
 };
 
 // Define action types that should show the circle effect
-const LOCATION_BASED_ACTIONS = ["agent_click", "agent_double_click"];
+const LOCATION_BASED_ACTIONS = [
+  "agent_click",
+  "agent_double_click",
+  "agent_triple_click",
+];
 
 // Circle effect component for clicks
 function CircleEffect({
@@ -151,7 +157,8 @@ function Overlay() {
         const newIndicator: VisualIndicator = {
           id: Date.now() + Math.random(),
           type: eventName,
-          message: messageFormatter(payload),
+          message:
+            messageFormatter(payload) + (payload.simulated ? " (dry run)" : ""),
           timestamp: Date.now(),
         };
 
@@ -164,7 +171,7 @@ function Overlay() {
           // Add circle effect for click-like actions
           if (LOCATION_BASED_ACTIONS.includes(eventName)) {
             const circleColor =
-              eventName === "agent_double_click" ? "#ff5500" : "#3b82f6";
+              eventName === "agent_click" ? "#3b82f6" : "#ff5500";
             setCircleEffects((prev) => [
               ...prev,
               {
@@ -194,6 +201,21 @@ function Overlay() {
       (payload) => `Double Click: ${payload.x},${payload.y}`,
     );
 
+    setupListener(
+      "agent_triple_click",
+      (payload) => `Triple Click: ${payload.x},${payload.y}`,
+    );
+
+    setupListener(
+      "agent_mouse_down",
+      (payload) => `Mouse Down: ${payload.x},${payload.y}`,
+    );
+
+    setupListener(
+      "agent_mouse_up",
+      (payload) => `Mouse Up: ${payload.x},${payload.y}`,
+    );
+
     setupListener(
       "agent_move_mouse",
       (payload) => `Move: ${payload.x},${payload.y}`,
@@ -210,6 +232,11 @@ function Overlay() {
       (payload) => `Keys: ${payload.keys?.join(", ")}`,
     );
 
+    setupListener(
+      "agent_hold_key",
+      (payload) => `Hold: ${payload.keys?.join(", ")} for ${payload.ms}ms`,
+    );
+
     setupListener("agent_type_text", (payload) => `Typing: "${payload.text}"`);
 
     setupListener("agent_wait", (payload) => `Waiting: ${payload.ms}ms`);